@@ -1,5 +1,9 @@
 // Based on https://sotrh.github.io/learn-wgpu/intermediate/tutorial12-camera/#the-camera
-use winit::{event::*, dpi::LogicalPosition};
+use crate::input::InputState;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use winit::event::*;
 use cgmath::*;
 use std::time::Duration;
 use std::f32::consts::FRAC_PI_2;
@@ -12,11 +16,53 @@ pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
-#[derive(Debug)]
+// Applied after OPENGL_TO_WGPU_MATRIX, turns depth around so the near plane ends up at 1
+//  and the far plane at 0. See Projection::set_reverse_z.
+#[rustfmt::skip]
+pub const REVERSE_Z_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
+);
+
+// A snapshot of where the camera is and how it looks, see Camera::save_view. Angles are in
+//  radians. The aspect ratio isn't part of it, that follows the window.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraState {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    // See Camera::set_up. Views saved before there was one are y-up.
+    #[serde(default = "y_up")]
+    pub up: [f32; 3],
+}
+
+fn y_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+impl CameraState {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Camera {
     pub position: Point3<f32>,
     yaw: Rad<f32>,
     pitch: Rad<f32>,
+    // The world's up, +y by default. Yaw turns around it and pitch tilts towards it.
+    up: Vector3<f32>,
     pub projection: Projection,
 }
 
@@ -30,21 +76,94 @@ impl Camera {
             position: position.into(),
             yaw: yaw.into(),
             pitch: pitch.into(),
+            up: Vector3::unit_y(),
             projection: Projection::default(),
         }
     }
 
-    pub fn calculate_matrix(&self) -> Matrix4<f32> {
+    // For scenes that aren't y-up, like z-up data from CAD or GIS tools. Yaw and pitch
+    //  keep working the same way relative to the new up. Doesn't need to be normalized,
+    //  a zero vector is ignored.
+    pub fn set_up(&mut self, up: Vector3<f32>) {
+        if !up.is_zero() {
+            self.up = up.normalize();
+        }
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.up
+    }
+
+    // Turns yaw and pitch's y-up space into the world, where up is self.up.
+    pub fn up_rotation(&self) -> Quaternion<f32> {
+        Quaternion::from_arc(Vector3::unit_y(), self.up, None)
+    }
+
+    pub fn save_view(&self) -> CameraState {
+        CameraState {
+            position: self.position.into(),
+            yaw: self.yaw.0,
+            pitch: self.pitch.0,
+            fovy: self.projection.fovy.0,
+            znear: self.projection.znear,
+            zfar: self.projection.zfar,
+            up: self.up.into(),
+        }
+    }
+
+    // Keeps the current aspect ratio. The controller only acts on this frame's input, so
+    //  there's no leftover movement or rotation to reset.
+    pub fn load_view(&mut self, state: CameraState) {
+        self.position = state.position.into();
+        self.yaw = Rad(state.yaw);
+        self.pitch = Rad(state.pitch);
+        self.projection.fovy = Rad(state.fovy);
+        self.projection.znear = state.znear;
+        self.projection.zfar = state.zfar;
+        self.set_up(state.up.into());
+    }
+
+    pub fn direction(&self) -> Vector3<f32> {
+        // Never parallel to up, pitch is clamped short of that in y-up space before it's
+        //  turned into the world.
+        self.up_rotation().rotate_vector(Vector3::new(
+            self.yaw.0.cos(),
+            self.pitch.0.sin(),
+            self.yaw.0.sin(),
+        ).normalize())
+    }
+
+    // World to view space.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
         Matrix4::look_at_dir(
             self.position, 
-            Vector3::new(
-                self.yaw.0.cos(),
-                self.pitch.0.sin(),
-                self.yaw.0.sin(),
-            ).normalize(),
-           Vector3::unit_y(),
+            self.direction(),
+            self.up,
         )
     }
+
+    // World to clip space, what the shaders get as view_proj.
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.projection.calculate_matrix() * self.view_matrix()
+    }
+
+    // The view that fits the bounding box (min, max) in the frustum, looking at it from the
+    //  current direction. Uses the box's bounding sphere, so it's a bit loose for long
+    //  thin boxes, but it doesn't depend on the orientation.
+    pub fn framing(&self, bounds: (Vector3<f32>, Vector3<f32>)) -> CameraState {
+        let (min, max) = bounds;
+        let center = (min + max) * 0.5;
+        // Degenerate boxes still get some room, so we don't end up inside the object.
+        let radius = ((max - min).magnitude() * 0.5).max(MIN_FRAME_RADIUS);
+
+        let half_fovy = self.projection.fovy.0 * 0.5;
+        let half_fovx = (half_fovy.tan() * self.projection.aspect).atan();
+        let distance = radius / half_fovy.min(half_fovx).sin();
+
+        let mut state = self.save_view();
+        state.position = (center - self.direction() * distance).into();
+        state
+    }
 }
 #[derive(Clone, Copy, Debug)]
 pub struct Projection {
@@ -52,6 +171,7 @@ pub struct Projection {
     fovy: Rad<f32>,
     znear: f32,
     zfar: f32,
+    reverse_z: bool,
 }
 
 impl Projection {
@@ -67,6 +187,7 @@ impl Projection {
             fovy: fovy.into(),
             znear,
             zfar,
+            reverse_z: false,
         }
     }
 
@@ -74,8 +195,45 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
-    pub fn calculate_matrix(self) -> Matrix4<f32> {
-        OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
+    // Push the far plane out for big scenes, or the near plane in for small ones. The
+    //  further apart they are the less depth precision is left, see set_reverse_z.
+    pub fn set_planes(&mut self, znear: f32, zfar: f32) {
+        assert!(
+            0.0 < znear && znear < zfar,
+            "Near plane {} has to be above 0 and below the far plane {}",
+            znear,
+            zfar
+        );
+        self.znear = znear;
+        self.zfar = zfar;
+    }
+
+    // (znear, zfar)
+    pub fn planes(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
+
+    // Floats are most precise near 0, and perspective depth bunches up near 1 far away
+    //  from the camera. Reversed, the two roughly cancel out and depth keeps its precision
+    //  all the way to the far plane. Needs a depth buffer set up for it, see
+    //  Renderer::set_reverse_z.
+    pub fn set_reverse_z(&mut self, enabled: bool) {
+        self.reverse_z = enabled;
+    }
+
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    // View to clip space, in wgpu's 0 to 1 depth range.
+    pub fn calculate_matrix(&self) -> Matrix4<f32> {
+        let projection =
+            OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar);
+        if self.reverse_z {
+            REVERSE_Z_MATRIX * projection
+        } else {
+            projection
+        }
     }
 }
 
@@ -86,119 +244,353 @@ impl Default for Projection {
             fovy: cgmath::Deg(45.0).into(),
             znear: 0.1,
             zfar: 100.0,
+            reverse_z: false,
+        }
+    }
+}
+
+// Smallest bounding sphere radius Camera::framing will fit, for flat or empty bounds.
+const MIN_FRAME_RADIUS: f32 = 0.5;
+
+// How fast (radians per second) a fully deflected look stick turns the camera.
+const GAMEPAD_LOOK_SPEED: f32 = 2.0;
+
+// Which keys move the camera. Each action can have several keys, holding any of them
+//  triggers it.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    pub forward: Vec<VirtualKeyCode>,
+    pub backward: Vec<VirtualKeyCode>,
+    pub left: Vec<VirtualKeyCode>,
+    pub right: Vec<VirtualKeyCode>,
+    pub up: Vec<VirtualKeyCode>,
+    pub down: Vec<VirtualKeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            forward: vec![VirtualKeyCode::W, VirtualKeyCode::Up],
+            backward: vec![VirtualKeyCode::S, VirtualKeyCode::Down],
+            left: vec![VirtualKeyCode::A, VirtualKeyCode::Left],
+            right: vec![VirtualKeyCode::D, VirtualKeyCode::Right],
+            up: vec![VirtualKeyCode::O],
+            down: vec![VirtualKeyCode::I],
+        }
+    }
+}
+
+// How a camera transition progresses over its duration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseInOut,
+}
+
+impl Easing {
+    // Maps the linear progress t (0.0..=1.0) onto the eased one.
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
         }
     }
 }
 
+// Orientation as a rotation of +x onto the camera's look direction, so it can be slerped.
+fn orientation(yaw: f32, pitch: f32) -> Quaternion<f32> {
+    Quaternion::from_angle_y(Rad(-yaw)) * Quaternion::from_angle_z(Rad(pitch))
+}
+
+#[derive(Debug)]
+struct Transition {
+    // Filled in on the first update, animate_to doesn't get to see the camera.
+    from: Option<CameraState>,
+    to: CameraState,
+    elapsed: f32,
+    duration: f32,
+}
+
 #[derive(Debug)]
 pub struct CameraController {
-    amount_left: f32,
-    amount_right: f32,
-    amount_forward: f32,
-    amount_backward: f32,
-    amount_up: f32,
-    amount_down: f32,
-    rotate_horizontal: f32,
-    rotate_vertical: f32,
-    scroll: f32,
-    speed: f32,
-    sensitivity: f32,
+    // Units per second.
+    pub speed: f32,
+    // How fast mouse movement turns the camera.
+    pub sensitivity: f32,
+    bindings: KeyBindings,
+    easing: Easing,
+    transition: Option<Transition>,
+    // How far down and up the pitch goes, see set_pitch_limits.
+    pitch_limits: (Rad<f32>, Rad<f32>),
+    // Turn with every mouse movement instead of only while dragging, see set_mouse_look.
+    mouse_look: bool,
 }
 
 impl CameraController {
     pub fn new(speed: f32, sensitivity: f32) -> Self {
         Self {
-            amount_left: 0.0,
-            amount_right: 0.0,
-            amount_forward: 0.0,
-            amount_backward: 0.0,
-            amount_up: 0.0,
-            amount_down: 0.0,
-            rotate_horizontal: 0.0,
-            rotate_vertical: 0.0,
-            scroll: 0.0,
             speed,
             sensitivity,
+            bindings: KeyBindings::default(),
+            easing: Easing::EaseInOut,
+            transition: None,
+            pitch_limits: (-Rad(FRAC_PI_2), Rad(FRAC_PI_2)),
+            mouse_look: false,
         }
     }
 
-    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
-        let amount = if state == ElementState::Pressed { 1.0 } else { 0.0 };
-        match key {
-            VirtualKeyCode::W | VirtualKeyCode::Up => {
-                self.amount_forward = amount;
-                true
-            }
-            VirtualKeyCode::S | VirtualKeyCode::Down => {
-                self.amount_backward = amount;
-                true
-            }
-            VirtualKeyCode::A | VirtualKeyCode::Left => {
-                self.amount_left = amount;
-                true
-            }
-            VirtualKeyCode::D | VirtualKeyCode::Right => {
-                self.amount_right = amount;
-                true
-            }
-            VirtualKeyCode::O => {
-                self.amount_up = amount;
-                true
-            }
-            VirtualKeyCode::I => {
-                self.amount_down = amount;
-                true
-            }
-            _ => false,
-        }
-    }
-
-    pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
-        self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
-    }
-
-    pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
-        self.scroll = -match delta {
-            MouseScrollDelta::LineDelta(_, scroll) => scroll * 100.0,
-            MouseScrollDelta::PixelDelta(LogicalPosition {
-                y: scroll,
-                ..
-            }) => *scroll  as f32,
+    // Looks around with the mouse without holding a button, for when the pointer is
+    //  grabbed by the window.
+    pub fn set_mouse_look(&mut self, enabled: bool) {
+        self.mouse_look = enabled;
+    }
+
+    // Bounds the pitch the mouse and gamepad can reach. Defaults to straight down and up,
+    //  -90 to 90 degrees. Camera::direction blends pitch in without scaling down yaw, so
+    //  even at 90 degrees it stays short of vertical and the view doesn't flip. Panics if
+    //  min is above max.
+    pub fn set_pitch_limits<A: Into<Rad<f32>>, B: Into<Rad<f32>>>(&mut self, min: A, max: B) {
+        let (min, max) = (min.into(), max.into());
+        assert!(min <= max, "Pitch limits {:?} and {:?} are the wrong way around", min, max);
+        self.pitch_limits = (min, max);
+    }
+
+    pub fn set_easing(&mut self, easing: Easing) {
+        self.easing = easing;
+    }
+
+    // Moves the camera to target over duration, driven by update_camera. Any movement input
+    //  cancels it and leaves the camera wherever it got to.
+    pub fn animate_to(&mut self, target: CameraState, duration: Duration) {
+        self.transition = Some(Transition {
+            from: None,
+            to: target,
+            elapsed: 0.0,
+            duration: duration.as_secs_f32(),
+        });
+    }
+
+    // Flies the camera over to frame the bounding box, see Camera::framing.
+    pub fn frame(
+        &mut self,
+        camera: &Camera,
+        bounds: (Vector3<f32>, Vector3<f32>),
+        duration: Duration,
+    ) {
+        self.animate_to(camera.framing(bounds), duration);
+    }
+
+    // Whether the user is trying to move the camera this frame.
+    fn has_input(&self, input: &InputState) -> bool {
+        let bindings = &self.bindings;
+        let keys = [
+            &bindings.forward,
+            &bindings.backward,
+            &bindings.left,
+            &bindings.right,
+            &bindings.up,
+            &bindings.down,
+        ];
+        let gamepad = input.gamepad();
+        let mouse_delta = input.mouse_delta();
+        keys.iter().any(|keys| Self::amount(input, keys) > 0.0)
+            || input.scroll_delta() != 0.0
+            || ((self.mouse_look || input.is_button_pressed(MouseButton::Left))
+                && (mouse_delta.0 != 0.0 || mouse_delta.1 != 0.0))
+            || gamepad.movement != (0.0, 0.0)
+            || gamepad.look != (0.0, 0.0)
+            || gamepad.vertical != 0.0
+    }
+
+    // Advances the transition by dt, returns false once it's done.
+    fn advance_transition(&mut self, camera: &mut Camera, dt: f32) -> bool {
+        let easing = self.easing;
+        let transition = match &mut self.transition {
+            Some(transition) => transition,
+            None => return false,
         };
+        let from = *transition.from.get_or_insert_with(|| camera.save_view());
+        let to = transition.to;
+        transition.elapsed += dt;
+        let t = if transition.duration > 0.0 {
+            (transition.elapsed / transition.duration).min(1.0)
+        } else {
+            1.0
+        };
+
+        if t >= 1.0 {
+            camera.load_view(to);
+            self.transition = None;
+            return false;
+        }
+
+        let t = easing.apply(t);
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let position = Vector3::from(from.position).lerp(Vector3::from(to.position), t);
+        let rotation = orientation(from.yaw, from.pitch).slerp(orientation(to.yaw, to.pitch), t);
+        let direction = rotation.rotate_vector(Vector3::unit_x());
+        camera.load_view(CameraState {
+            position: position.into(),
+            yaw: direction.z.atan2(direction.x),
+            pitch: direction.y.clamp(-1.0, 1.0).asin(),
+            fovy: lerp(from.fovy, to.fovy),
+            znear: lerp(from.znear, to.znear),
+            zfar: lerp(from.zfar, to.zfar),
+            up: to.up,
+        });
+        true
+    }
+
+    pub fn bindings(&self) -> &KeyBindings {
+        &self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+    // 1.0 while any of the keys is held, 0.0 otherwise.
+    fn amount(input: &InputState, keys: &[VirtualKeyCode]) -> f32 {
+        if keys.iter().any(|key| input.is_key_pressed(*key)) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera, input: &InputState, dt: Duration) {
         let dt = dt.as_secs_f32();
 
+        if self.transition.is_some() && self.has_input(input) {
+            self.transition = None;
+        }
+        if self.advance_transition(camera, dt) {
+            return;
+        }
+
+        let amount_forward = Self::amount(input, &self.bindings.forward);
+        let amount_backward = Self::amount(input, &self.bindings.backward);
+        let amount_left = Self::amount(input, &self.bindings.left);
+        let amount_right = Self::amount(input, &self.bindings.right);
+        let amount_up = Self::amount(input, &self.bindings.up);
+        let amount_down = Self::amount(input, &self.bindings.down);
+        let gamepad = input.gamepad();
+
+        // Movement is worked out in y-up space like yaw and pitch, then turned to the
+        //  camera's up.
+        let to_world = camera.up_rotation();
+
         // Translation fwd, bwd, l, r.
         let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
-        let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
-        let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
+        let forward = to_world.rotate_vector(Vector3::new(yaw_cos, 0.0, yaw_sin).normalize());
+        let right = to_world.rotate_vector(Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize());
+        let amount_forward = (amount_forward - amount_backward + gamepad.movement.1).clamp(-1.0, 1.0);
+        let amount_right = (amount_right - amount_left + gamepad.movement.0).clamp(-1.0, 1.0);
+        camera.position += forward * amount_forward * self.speed * dt;
+        camera.position += right * amount_right * self.speed * dt;
 
         // fake zoom by changing the cams position
         let (pitch_sin, pitch_cos) = camera.pitch.0.sin_cos();
-        let scrollward = Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
-        camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
-        self.scroll = 0.0;
+        let scrollward = to_world.rotate_vector(
+            Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize(),
+        );
+        let scroll = -input.scroll_delta();
+        camera.position += scrollward * scroll * self.speed * self.sensitivity * dt;
 
         // Translation up, down
-        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        let amount_up = (amount_up - amount_down + gamepad.vertical).clamp(-1.0, 1.0);
+        camera.position += camera.up * amount_up * self.speed * dt;
 
-        // Rotate
-        camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
-        camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
-
-        self.rotate_horizontal = 0.0;
-        self.rotate_vertical = 0.0;
+        // Rotate while dragging with the left mouse button, or always in mouse look.
+        if self.mouse_look || input.is_button_pressed(MouseButton::Left) {
+            let (rotate_horizontal, rotate_vertical) = input.mouse_delta();
+            camera.yaw += Rad(rotate_horizontal as f32) * self.sensitivity * dt;
+            camera.pitch += Rad(-rotate_vertical as f32) * self.sensitivity * dt;
+        }
+        camera.yaw += Rad(gamepad.look.0 * GAMEPAD_LOOK_SPEED * dt);
+        camera.pitch += Rad(gamepad.look.1 * GAMEPAD_LOOK_SPEED * dt);
 
         // Clamp camera pitch
-        if camera.pitch < -Rad(FRAC_PI_2) {
-            camera.pitch = -Rad(FRAC_PI_2);
-        } else if camera.pitch > Rad(FRAC_PI_2) {
-            camera.pitch = Rad(FRAC_PI_2);
+        let (min_pitch, max_pitch) = self.pitch_limits;
+        if camera.pitch < min_pitch {
+            camera.pitch = min_pitch;
+        } else if camera.pitch > max_pitch {
+            camera.pitch = max_pitch;
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // With z up a level camera looks along the ground, and up stays up on screen.
+    #[test]
+    fn z_up_camera_stays_level() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(30.0), Deg(0.0));
+        camera.set_up(Vector3::new(0.0, 0.0, 2.0));
+        camera.set_up(Vector3::zero());
+        assert_eq!(camera.up(), Vector3::unit_z());
+
+        assert!(camera.direction().dot(Vector3::unit_z()).abs() < 1e-6);
+        let up_in_view = camera.view_matrix() * Vector4::unit_z();
+        assert!((up_in_view.truncate() - Vector3::unit_y()).magnitude() < 1e-5);
+    }
+
+    // Dragging the mouse far up stops at the upper limit, far down at the lower one.
+    #[test]
+    fn pitch_stays_within_limits() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let mut controller = CameraController::new(1.0, 1.0);
+        controller.set_mouse_look(true);
+        controller.set_pitch_limits(Deg(-30.0), Deg(45.0));
+
+        for &(delta, limit) in &[(-1e6, Deg(45.0)), (1e6, Deg(-30.0))] {
+            let mut input = InputState::default();
+            input.process_device_event(&DeviceEvent::MouseMotion { delta: (0.0, delta) });
+            controller.update_camera(&mut camera, &input, Duration::from_secs(1));
+            assert!((camera.save_view().pitch - Rad::from(limit).0).abs() < 1e-6);
+        }
+    }
+
+    // The new near plane lands at depth 0 and the far one at 1, or the other way around
+    //  with reverse z.
+    #[test]
+    fn planes_map_to_depth_range() {
+        let mut projection = Projection::default();
+        projection.set_planes(0.5, 2000.0);
+        assert_eq!(projection.planes(), (0.5, 2000.0));
+
+        let depth = |projection: &Projection, distance: f32| {
+            let clip = projection.calculate_matrix() * Vector4::new(0.0, 0.0, -distance, 1.0);
+            clip.z / clip.w
+        };
+        assert!(depth(&projection, 0.5).abs() < 1e-5);
+        assert!((depth(&projection, 2000.0) - 1.0).abs() < 1e-5);
+        projection.set_reverse_z(true);
+        assert!((depth(&projection, 0.5) - 1.0).abs() < 1e-5);
+        assert!(depth(&projection, 2000.0).abs() < 1e-5);
+    }
+
+    // A z-up view comes back z-up, and one saved without an up is y-up.
+    #[test]
+    fn saved_view_keeps_up() {
+        let mut camera = Camera::new((1.0, 2.0, 3.0), Deg(30.0), Deg(10.0));
+        camera.set_up(Vector3::unit_z());
+        let json = serde_json::to_string(&camera.save_view()).unwrap();
+
+        let mut loaded = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        loaded.load_view(serde_json::from_str(&json).unwrap());
+        assert_eq!(loaded.up(), Vector3::unit_z());
+        assert_eq!(loaded.save_view(), camera.save_view());
+
+        let old = r#"{"position":[0,0,0],"yaw":0,"pitch":0,"fovy":1,"znear":0.1,"zfar":100}"#;
+        loaded.load_view(serde_json::from_str(old).unwrap());
+        assert_eq!(loaded.up(), Vector3::unit_y());
+    }
+
+    #[test]
+    #[should_panic]
+    fn planes_out_of_order() {
+        Projection::default().set_planes(10.0, 1.0);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::renderer::Renderer;
+use crate::scene::Scene;
+
+use cgmath::{Deg, Euler, Quaternion, Vector3};
+
+// The inspector window, built every frame on the context Renderer::debug_ui hands out.
+//  Edits the clear color, the light, how the camera moves and the transform of the node
+//  placing the selected instance.
+pub fn show(
+    ctx: &egui::CtxRef,
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    selected: Option<(usize, usize)>,
+) {
+    egui::Window::new("Inspector").show(ctx, |ui| {
+        ui.collapsing("Renderer", |ui| {
+            let stats = renderer.frame_stats();
+            ui.label(format!(
+                "{} draw calls, {} instances, {} triangles",
+                stats.draw_calls,
+                stats.instances,
+                stats.triangles()
+            ));
+            let memory = renderer.gpu_memory_report(&scene.models);
+            let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+            ui.label(format!(
+                "{:.1} MiB on the gpu: {:.1} instances, {:.1} meshes, {:.1} textures, \
+                 {:.1} targets",
+                mib(memory.total()),
+                mib(memory.instances),
+                mib(memory.meshes),
+                mib(memory.textures),
+                mib(memory.render_targets)
+            ));
+            let mut culling = renderer.culling_enabled();
+            if ui.checkbox(&mut culling, "Frustum culling").changed() {
+                renderer.set_gpu_culling(culling);
+            }
+
+            let clear_color = renderer.clear_color;
+            let mut color = [
+                clear_color.r as f32,
+                clear_color.g as f32,
+                clear_color.b as f32,
+            ];
+            ui.horizontal(|ui| {
+                ui.label("Clear color");
+                if ui.color_edit_button_rgb(&mut color).changed() {
+                    renderer.clear_color = wgpu::Color {
+                        r: color[0] as f64,
+                        g: color[1] as f64,
+                        b: color[2] as f64,
+                        a: 1.0,
+                    };
+                }
+            });
+        });
+
+        ui.collapsing("Light", |ui| {
+            let mut light = renderer.light();
+            ui.horizontal(|ui| {
+                ui.label("Position");
+                if drag_vector(ui, &mut light.position, 0.1) {
+                    renderer.set_light_position(light.position);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                if ui.color_edit_button_rgb(&mut light.color).changed() {
+                    renderer.set_light_color(light.color);
+                }
+            });
+            if ui
+                .add(egui::Slider::new(&mut light.intensity, 0.0..=200.0).text("Intensity"))
+                .changed()
+            {
+                renderer.set_light_intensity(light.intensity);
+            }
+        });
+
+        ui.collapsing("Camera", |ui| {
+            let controller = renderer.camera_controller_mut();
+            ui.add(egui::Slider::new(&mut controller.speed, 0.1..=50.0).text("Speed"));
+            ui.add(egui::Slider::new(&mut controller.sensitivity, 0.01..=2.0).text("Sensitivity"));
+        });
+
+        ui.collapsing("Selected node", |ui| {
+            let path = match selected
+                .and_then(|(model_id, instance_id)| scene.node_path_of(model_id, instance_id))
+            {
+                Some(path) => path,
+                None => {
+                    ui.label("Click an instance to select it.");
+                    return;
+                }
+            };
+            let node = match scene.node(&path) {
+                Some(node) => node,
+                None => return,
+            };
+
+            let mut position: [f32; 3] = node.position.into();
+            // Euler angles in degrees, they're easier to type than a quaternion.
+            let euler = Euler::from(node.rotation);
+            let mut rotation = [
+                Deg::from(euler.x).0,
+                Deg::from(euler.y).0,
+                Deg::from(euler.z).0,
+            ];
+            let mut scale = node.scale;
+
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Position");
+                changed |= drag_vector(ui, &mut position, 0.1);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Rotation");
+                changed |= drag_vector(ui, &mut rotation, 1.0);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Scale");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut scale).speed(0.01))
+                    .changed();
+            });
+
+            // Only marks the node dirty when it's actually edited, see Scene::node_mut.
+            if changed {
+                if let Some(node) = scene.node_mut(&path) {
+                    node.set_local_transform(
+                        Vector3::from(position),
+                        Quaternion::from(Euler::new(
+                            Deg(rotation[0]),
+                            Deg(rotation[1]),
+                            Deg(rotation[2]),
+                        )),
+                        scale,
+                    );
+                }
+            }
+        });
+    });
+}
+
+// Returns whether any of the components changed.
+fn drag_vector(ui: &mut egui::Ui, vector: &mut [f32; 3], speed: f32) -> bool {
+    let mut changed = false;
+    for component in vector.iter_mut() {
+        changed |= ui
+            .add(egui::DragValue::new(component).speed(speed))
+            .changed();
+    }
+    changed
+}
@@ -0,0 +1,56 @@
+use crate::input::{GamepadAxes, InputState};
+use gilrs::{Axis, Button, Gilrs};
+
+// Stick deflection below this is treated as the stick being at rest.
+const DEADZONE: f32 = 0.15;
+
+// Polls the first connected controller and hands its sticks and triggers to InputState.
+pub struct Gamepad {
+    gilrs: Gilrs,
+}
+
+impl Gamepad {
+    // None if there's no way to talk to controllers on this platform.
+    pub fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(Self { gilrs }),
+            Err(e) => {
+                log::warn!("Gamepad support unavailable: {}", e);
+                None
+            }
+        }
+    }
+
+    // Call once per frame, before the input is used.
+    pub fn poll(&mut self, input: &mut InputState) {
+        // Gilrs only updates its gamepad state while events are being pulled.
+        while self.gilrs.next_event().is_some() {}
+
+        let axes = match self.gilrs.gamepads().next() {
+            Some((_, gamepad)) => {
+                let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+                GamepadAxes {
+                    movement: (
+                        deadzone(gamepad.value(Axis::LeftStickX)),
+                        deadzone(gamepad.value(Axis::LeftStickY)),
+                    ),
+                    look: (
+                        deadzone(gamepad.value(Axis::RightStickX)),
+                        deadzone(gamepad.value(Axis::RightStickY)),
+                    ),
+                    vertical: trigger(Button::RightTrigger2) - trigger(Button::LeftTrigger2),
+                }
+            }
+            None => GamepadAxes::default(),
+        };
+        input.set_gamepad(axes);
+    }
+}
+
+fn deadzone(value: f32) -> f32 {
+    if value.abs() < DEADZONE {
+        0.0
+    } else {
+        value
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+use winit::{dpi::PhysicalPosition, event::*};
+
+// Analog controller input, each axis in -1.0..=1.0 (0.0..=1.0 for the triggers).
+//  Stays zeroed unless the gamepad feature is on, see gamepad.rs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GamepadAxes {
+    // Left stick: x is right, y is forward.
+    pub movement: (f32, f32),
+    // Right stick: x is right, y is up.
+    pub look: (f32, f32),
+    // Right trigger minus left trigger.
+    pub vertical: f32,
+}
+
+// Keeps track of keyboard and mouse state, fed from the winit event loop. Anything that
+//  wants to react to input (camera, scene, ...) can query it instead of handling events.
+#[derive(Debug)]
+pub struct InputState {
+    pressed_keys: HashSet<VirtualKeyCode>,
+    pressed_buttons: HashSet<MouseButton>,
+    cursor_position: PhysicalPosition<f64>,
+    // Accumulated since the last end_frame.
+    mouse_delta: (f64, f64),
+    scroll_delta: f32,
+    gamepad: GamepadAxes,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            pressed_keys: HashSet::new(),
+            pressed_buttons: HashSet::new(),
+            cursor_position: PhysicalPosition::new(0.0, 0.0),
+            mouse_delta: (0.0, 0.0),
+            scroll_delta: 0.0,
+            gamepad: GamepadAxes::default(),
+        }
+    }
+}
+
+impl InputState {
+    // Keys, mouse buttons and the cursor come in as window events...
+    pub fn process_window_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => {
+                match state {
+                    ElementState::Pressed => self.pressed_keys.insert(*key),
+                    ElementState::Released => self.pressed_keys.remove(key),
+                };
+                true
+            }
+            WindowEvent::MouseInput { button, state, .. } => {
+                match state {
+                    ElementState::Pressed => self.pressed_buttons.insert(*button),
+                    ElementState::Released => self.pressed_buttons.remove(button),
+                };
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+                true
+            }
+            // We won't hear about releases while unfocused, so don't keep keys stuck down.
+            WindowEvent::Focused(false) => {
+                self.pressed_keys.clear();
+                self.pressed_buttons.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // ...while raw mouse motion and scrolling come in as device events.
+    //  See https://github.com/rust-windowing/winit/issues/1470
+    pub fn process_device_event(&mut self, event: &DeviceEvent) -> bool {
+        match event {
+            DeviceEvent::MouseMotion { delta } => {
+                self.mouse_delta.0 += delta.0;
+                self.mouse_delta.1 += delta.1;
+                true
+            }
+            DeviceEvent::MouseWheel { delta } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, scroll) => scroll * 100.0,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Resets the per frame deltas, call once the frame has used them.
+    pub fn end_frame(&mut self) {
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    pub fn cursor_position(&self) -> PhysicalPosition<f64> {
+        self.cursor_position
+    }
+
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse_delta
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    pub fn gamepad(&self) -> GamepadAxes {
+        self.gamepad
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn set_gamepad(&mut self, gamepad: GamepadAxes) {
+        self.gamepad = gamepad;
+    }
+}
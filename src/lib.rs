@@ -0,0 +1,6 @@
+// The renderer and the scene graph, split out of main.rs so tests can drive them without
+//  a window. main.rs is the demo on top.
+pub mod camera;
+pub mod input;
+pub mod renderer;
+pub mod scene;
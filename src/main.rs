@@ -1,23 +1,53 @@
-mod camera;
-mod renderer;
-mod scene;
+#[cfg(feature = "debug-ui")]
+mod debug_panel;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+
+use wgpu_practice::{camera, input, renderer, scene};
 
 use winit::{
+    dpi::PhysicalPosition,
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use renderer::{model::Model, Renderer};
+use camera::{Camera, CameraState};
+use input::InputState;
+use renderer::{
+    deferred::ShadingMode,
+    model::{Model, ModelSource},
+    particles::ParticleSystem,
+    viewport::Viewport,
+    Renderer,
+};
 
-use scene::{DrawScene, Scene};
+use scene::{CameraHandle, DrawScene, Scene};
 
 #[cfg(target_arch = "wasm32")]
 use {log::info, log::Level};
 
+// Where F5 saves the camera view and F9 flies back to it.
+const CAMERA_VIEW_FILE: &str = "camera_view.json";
+// F6 saves the scene here, F10 loads it back.
+const SCENE_FILE: &str = "scene.json";
+// F8 exports the scene here, to open it in other tools.
+const EXPORT_FILE: &str = "scene.glb";
+// The sparks flying off the sun, emitted per second and alive at most at once.
+const SPARKS_PER_SECOND: f32 = 150.0;
+const MAX_SPARKS: usize = 1000;
+// How fast the arrow keys and page up/down move the selected node, in units per second.
+const NODE_NUDGE_SPEED: f32 = 2.0;
+const ARROW_KEYS: [VirtualKeyCode; 4] = [
+    VirtualKeyCode::Up,
+    VirtualKeyCode::Down,
+    VirtualKeyCode::Left,
+    VirtualKeyCode::Right,
+];
+
 fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -38,11 +68,25 @@ fn main() {
             .expect("Append canvas to HTML body");
     }
 
-    let _res_dir = std::path::Path::new(env!("OUT_DIR")).join("res");
+    // fn main() cannot be async. Natively we can block until the renderer is set up, but
+    //  the browser can't block its only thread, so there it's left to its event loop.
+    #[cfg(not(target_arch = "wasm32"))]
+    futures::executor::block_on(run(event_loop, window));
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(run(event_loop, window));
+}
 
-    // fn main() cannot be async, so block the main thread until future complete.
-    use futures::executor::block_on;
-    let mut renderer = block_on(Renderer::new(&window));
+// Sets up the renderer and the demo scene, then runs the event loop. Never returns unless
+//  the renderer can't start.
+async fn run(event_loop: EventLoop<()>, window: Window) {
+    let mut renderer = match Renderer::new(&window).await {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            eprintln!("Couldn't start the renderer: {}", e);
+            eprintln!("Make sure a Vulkan, Metal or DX12 capable gpu and driver are available.");
+            return;
+        }
+    };
 
     // Create scene, add a model to it.
     let mut scene = Scene::empty();
@@ -52,7 +96,52 @@ fn main() {
     // Simple test scene to test scenegraph.
     scene.add_model(model);
     scene.make_galaxy();
+    let sparks = scene.add_particle_system(ParticleSystem::new(&renderer, MAX_SPARKS));
+    // Fractions of a spark left over from the frames so far.
+    let mut sparks_due = 0.0;
+
+    // Ground plane below the galaxy, so there's something to orient against.
+    let mut ground = Model::plane(&renderer, 20.0, 20);
+    ground.add_instance_with_transform(
+        cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, -2.0, 0.0)),
+        None,
+    );
+    ground.instance_resource.sync_gpu();
+    scene.add_model(ground);
 
+    // A patch lying right on the ground, the depth bias keeps it from flickering through.
+    let mut decal = Model::plane(&renderer, 4.0, 1);
+    decal.materials[0].depth_bias = true;
+    decal.add_instance_with_transform(
+        cgmath::Matrix4::from_translation(cgmath::Vector3::new(4.0, -2.0, 4.0)),
+        Some([0.8, 0.3, 0.2, 1.0]),
+    );
+    decal.instance_resource.sync_gpu();
+    scene.add_model(decal);
+
+    let mut input = InputState::default();
+    // The last picked instance, it's highlighted and F frames it.
+    let mut selected = None;
+    // The arrow keys select_instance took from the camera's movement bindings, in
+    //  forward, backward, left, right order. They're given back when the selection clears.
+    let mut arrow_bindings: [Vec<VirtualKeyCode>; 4] = Default::default();
+    let mut debug_bounds = false;
+    // C switches to a view from straight above, V splits the window to show both.
+    let overhead_camera = scene.add_camera(Camera::new(
+        (0.0, 20.0, 0.01),
+        cgmath::Deg(-90.0),
+        cgmath::Deg(-89.0),
+    ));
+    let mut split_screen = false;
+    // M grabs the pointer to look around without dragging. Where the cursor was when it
+    //  was grabbed, to put it back there after.
+    let mut grabbed_at = None;
+    let mut ssao = false;
+    let mut bloom = false;
+    #[cfg(feature = "gamepad")]
+    let mut gamepad = gamepad::Gamepad::new();
+    // Resizes are applied once per frame, dragging a window edge fires loads of them.
+    let mut pending_resize = None;
     let mut last_render_time = Instant::now();
     let mut _spawn_time = Instant::now();
     let mut _removing = false;
@@ -60,32 +149,372 @@ fn main() {
         *control_flow = ControlFlow::Poll;
 
         match event {
-            // TODO: Probably shouldn't do any input processing in renderer but move it to a seperate mod?
             Event::DeviceEvent { ref event, .. } => {
-                renderer.input_mouse_movement(event);
+                // Scrolling or dragging over the panel shouldn't move the camera.
+                #[cfg(feature = "debug-ui")]
+                {
+                    if renderer.debug_ui.wants_pointer_input() {
+                        return;
+                    }
+                }
+                input.process_device_event(event);
             }
             Event::WindowEvent {
                 ref event,
                 window_id,
             } if window_id == window.id() => {
+                // Clicks and typing meant for the panel don't reach the camera or the keys
+                //  below.
+                #[cfg(feature = "debug-ui")]
+                {
+                    if renderer.debug_ui.handle_event(event) {
+                        return;
+                    }
+                }
+                input.process_window_event(event);
                 match event {
                     WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                    WindowEvent::KeyboardInput { input, .. } => match input {
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        // TODO: Probably shouldn't do any input processing in renderer but move it to a seperate mod?
-                        _ => {
-                            renderer.input(event);
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    // Save the current view, or go back to the saved one.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F5),
+                                ..
+                            },
+                        ..
+                    } => match scene.active_camera().save_view().save(CAMERA_VIEW_FILE) {
+                        Ok(_) => println!("Saved camera view to {}", CAMERA_VIEW_FILE),
+                        Err(e) => eprintln!("Failed to save camera view: {:?}", e),
+                    },
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F9),
+                                ..
+                            },
+                        ..
+                    } => match CameraState::load(CAMERA_VIEW_FILE) {
+                        Ok(state) => renderer.animate_camera_to(state, Duration::from_secs(1)),
+                        Err(e) => eprintln!("Failed to load camera view: {:?}", e),
+                    },
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F6),
+                                ..
+                            },
+                        ..
+                    } => match scene.save(SCENE_FILE) {
+                        Ok(_) => println!("Saved scene to {}", SCENE_FILE),
+                        Err(e) => eprintln!("Failed to save scene: {:?}", e),
+                    },
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F8),
+                                ..
+                            },
+                        ..
+                    } => {
+                        match scene.export_gltf(&renderer.device, &renderer.queue, EXPORT_FILE) {
+                            Ok(_) => println!("Exported scene to {}", EXPORT_FILE),
+                            Err(e) => eprintln!("Failed to export scene: {:?}", e),
                         }
+                    }
+                    // Load the models from files again, after editing them elsewhere.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F7),
+                                ..
+                            },
+                        ..
+                    } => {
+                        for model_id in 0..scene.models.len() {
+                            let source = scene.models[model_id].source.clone();
+                            if let ModelSource::Gltf(path) = source {
+                                match scene.reload_model(&renderer, model_id) {
+                                    Ok(_) => println!("Reloaded {}", path.display()),
+                                    Err(e) => {
+                                        eprintln!("Failed to reload {}: {:?}", path.display(), e)
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F10),
+                                ..
+                            },
+                        ..
+                    } => match Scene::load(&renderer, SCENE_FILE) {
+                        Ok(loaded) => {
+                            scene = loaded;
+                            selected = None;
+                            select_instance(
+                                &mut renderer,
+                                &mut scene,
+                                selected,
+                                &mut arrow_bindings,
+                            );
+                        }
+                        Err(e) => eprintln!("Failed to load scene: {:?}", e),
                     },
+                    // Toggle the bounding box overlay.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::B),
+                                ..
+                            },
+                        ..
+                    } => {
+                        debug_bounds = !debug_bounds;
+                        renderer.draw_debug_bounds(debug_bounds);
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::V),
+                                ..
+                            },
+                        ..
+                    } => split_screen = !split_screen,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::C),
+                                ..
+                            },
+                        ..
+                    } => scene.set_active_camera(if scene.active_camera_id() == 0 {
+                        overhead_camera
+                    } else {
+                        0
+                    }),
+                    // Compare forward and deferred shading.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::G),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let shading_mode = match renderer.shading_mode() {
+                            ShadingMode::Forward => ShadingMode::Deferred,
+                            ShadingMode::Deferred => ShadingMode::Forward,
+                        };
+                        println!("Switched to {:?} shading", shading_mode);
+                        renderer.set_shading_mode(shading_mode);
+                    }
+                    // Ambient occlusion, deferred shading only.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Q),
+                                ..
+                            },
+                        ..
+                    } => {
+                        ssao = !ssao;
+                        renderer.set_ssao(ssao, 0.2, 1.0);
+                    }
+                    // Glow around bright spots, like emissive materials.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::L),
+                                ..
+                            },
+                        ..
+                    } => {
+                        bloom = !bloom;
+                        renderer.set_bloom(bloom, 1.0, 0.5);
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::M),
+                                ..
+                            },
+                        ..
+                    } => {
+                        grabbed_at = match grabbed_at {
+                            Some(position) => {
+                                release_pointer(&window, &mut renderer, position);
+                                None
+                            }
+                            None => grab_pointer(&window, &mut renderer)
+                                .then(|| input.cursor_position()),
+                        }
+                    }
+                    // Alt-tabbing away shouldn't leave the cursor hidden.
+                    WindowEvent::Focused(false) => {
+                        if let Some(position) = grabbed_at.take() {
+                            release_pointer(&window, &mut renderer, position);
+                        }
+                    }
+                    // Reversed depth, for comparing z-fighting far away.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Z),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let reverse_z = !renderer.reverse_z();
+                        println!("Reversed z: {}", reverse_z);
+                        renderer.set_reverse_z(reverse_z);
+                    }
+                    // Base colors without lighting, for checking textures.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::U),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let unlit = !renderer.debug_unlit();
+                        println!("Unlit: {}", unlit);
+                        renderer.set_debug_unlit(unlit);
+                    }
+                    // Hide or show the selected node and what's below it, it stays selected
+                    //  so it can be brought back.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::H),
+                                ..
+                            },
+                        ..
+                    } => {
+                        if let (Some((model_id, instance_id)), Some(path)) =
+                            (selected, scene.selected_node().map(<[usize]>::to_vec))
+                        {
+                            let visible = !scene.models[model_id].is_instance_visible(instance_id);
+                            println!("Selected node visible: {}", visible);
+                            scene.set_node_visible(&path, visible);
+                        }
+                    }
+                    // Stop or restart the light going around.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::P),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let paused = renderer.light_orbit_speed() != 0.0;
+                        println!("Light paused: {}", paused);
+                        renderer.set_light_orbit_speed(if paused { 0.0 } else { 60.0 });
+                    }
+                    // Cycle through normals, uvs and the other debug views.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::N),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let view = renderer.debug_view().next();
+                        println!("Debug view: {:?}", view);
+                        renderer.set_debug_view(view);
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    } => {
+                        // Pick the instance under the cursor, through the camera of
+                        //  the viewport it's over.
+                        let cursor_position = input.cursor_position();
+                        let (x, y) = (cursor_position.x as f32, cursor_position.y as f32);
+                        let viewports = if split_screen {
+                            split_viewports(&renderer, &scene, overhead_camera).to_vec()
+                        } else {
+                            vec![Viewport::full(renderer.get_size(), scene.active_camera().clone())]
+                        };
+                        let hit = viewports
+                            .iter()
+                            .find(|viewport| viewport.rect.contains(x, y))
+                            .and_then(|viewport| {
+                                renderer.screen_ray(&viewport.camera, viewport.rect, x, y)
+                            })
+                            .and_then(|(origin, dir)| scene.raycast(origin, dir));
+                        match hit {
+                            Some((model_id, instance_id, distance)) => {
+                                println!(
+                                    "Hit instance {} of model {} at distance {}",
+                                    instance_id, model_id, distance
+                                );
+                                selected = Some((model_id, instance_id));
+                            }
+                            None => {
+                                println!("Nothing hit.");
+                                selected = None;
+                            }
+                        }
+                        select_instance(&mut renderer, &mut scene, selected, &mut arrow_bindings);
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let bounds = selected.and_then(|(model_id, instance_id)| {
+                            scene.models.get(model_id)?.instance_bounds(instance_id)
+                        });
+                        if let Some(bounds) = bounds {
+                            renderer.frame_camera(
+                                scene.active_camera(),
+                                bounds,
+                                Duration::from_millis(500),
+                            );
+                        }
+                    }
                     WindowEvent::Resized(physical_size) => {
-                        renderer.resize(Some(*physical_size));
+                        pending_resize = Some(*physical_size);
                     }
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        renderer.resize(Some(**new_inner_size));
+                        pending_resize = Some(**new_inner_size);
                     }
                     _ => {}
                 }
@@ -108,18 +537,76 @@ fn main() {
 
                 //}
 
-                scene.animate_galaxy(dt);
+                if let Some(size) = pending_resize.take() {
+                    renderer.resize(Some(size));
+                }
+
+                #[cfg(feature = "gamepad")]
+                {
+                    if let Some(gamepad) = &mut gamepad {
+                        gamepad.poll(&mut input);
+                    }
+                }
+
+                // Before the update, so nodes edited in the panel are synced this frame.
+                #[cfg(feature = "debug-ui")]
+                {
+                    let ui = renderer.debug_ui.begin_frame(renderer.get_size());
+                    debug_panel::show(&ui, &mut renderer, &mut scene, selected);
+                }
+
+                // Lay out the scene by hand: the arrow keys move the selected node in x and
+                //  z, page up and down move it up and down.
+                if scene.selected_node().is_some() {
+                    let held = |key| if input.is_key_pressed(key) { 1.0 } else { 0.0 };
+                    let direction = cgmath::Vector3::new(
+                        held(VirtualKeyCode::Right) - held(VirtualKeyCode::Left),
+                        held(VirtualKeyCode::PageUp) - held(VirtualKeyCode::PageDown),
+                        held(VirtualKeyCode::Down) - held(VirtualKeyCode::Up),
+                    );
+                    // Only touch the node when it moves, so it isn't synced every frame.
+                    if direction != cgmath::Vector3::new(0.0, 0.0, 0.0) {
+                        scene.translate_selected(direction * NODE_NUDGE_SPEED * dt.as_secs_f32());
+                    }
+                }
+
+                sparks_due += SPARKS_PER_SECOND * dt.as_secs_f32();
+                scene.particle_system_mut(sparks).emit_burst(
+                    cgmath::Vector3::new(0.0, 0.0, 0.0),
+                    sparks_due as usize,
+                    1.5,
+                    [6.0, 3.0, 1.0, 1.0],
+                    0.03,
+                    2.0,
+                );
+                sparks_due = sparks_due.fract();
                 scene.update(dt);
-                renderer.update(dt);
-                match renderer.draw_scene(&scene) {
+                renderer.update(dt, &input, scene.active_camera_mut());
+                input.end_frame();
+
+                // Nothing to draw to while minimized.
+                if renderer.is_minimized() {
+                    return;
+                }
+
+                let result = if split_screen {
+                    let viewports = split_viewports(&renderer, &scene, overhead_camera);
+                    renderer.draw_scene_viewports(&mut scene, &viewports)
+                } else {
+                    renderer.draw_scene(&mut scene)
+                };
+                match result {
                     // All good.
                     Ok(_) => {}
                     // Recreate the sc if it is lost.
                     Err(wgpu::SurfaceError::Lost) => renderer.resize(None),
+                    // The window changed under the surface, e.g. mid resize or when it's
+                    //  moved to another monitor. Reconfigure it for the window's size now.
+                    Err(wgpu::SurfaceError::Outdated) => renderer.resize(Some(window.inner_size())),
                     // Out of mem, just exit the program.
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errrors should be resolved by the next frame.
-                    Err(e) => eprintln!("{:?}", e),
+                    // The frame didn't come in time, skip it and try again next frame.
+                    Err(wgpu::SurfaceError::Timeout) => {}
                 }
             }
             Event::MainEventsCleared => {
@@ -131,6 +618,72 @@ fn main() {
     });
 }
 
+// What V shows: the first camera on the left, the overhead one on the right.
+fn split_viewports(
+    renderer: &Renderer,
+    scene: &Scene,
+    overhead_camera: CameraHandle,
+) -> [Viewport; 2] {
+    Viewport::side_by_side(
+        renderer.get_size(),
+        scene.camera(0).clone(),
+        scene.camera(overhead_camera).clone(),
+    )
+}
+
+// Hides the cursor and keeps it in the window, so every mouse movement turns the camera.
+//  Returns false when the platform doesn't allow grabbing it.
+fn grab_pointer(window: &Window, renderer: &mut Renderer) -> bool {
+    if let Err(e) = window.set_cursor_grab(true) {
+        eprintln!("Couldn't grab the pointer: {:?}", e);
+        return false;
+    }
+    window.set_cursor_visible(false);
+    renderer.camera_controller_mut().set_mouse_look(true);
+    true
+}
+
+// Undoes grab_pointer and puts the cursor back at position, where the platform allows it.
+fn release_pointer(window: &Window, renderer: &mut Renderer, position: PhysicalPosition<f64>) {
+    let _ = window.set_cursor_grab(false);
+    window.set_cursor_visible(true);
+    let _ = window.set_cursor_position(position);
+    renderer.camera_controller_mut().set_mouse_look(false);
+}
+
+// Highlights the instance and selects the node that places it. While a node is selected
+//  the arrow keys move it instead of the camera, the arrow keys bound to camera movement
+//  are set aside in arrow_bindings until then.
+fn select_instance(
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    selected: Option<(usize, usize)>,
+    arrow_bindings: &mut [Vec<VirtualKeyCode>; 4],
+) {
+    renderer.set_selection(selected);
+    let path =
+        selected.and_then(|(model_id, instance_id)| scene.node_path_of(model_id, instance_id));
+    scene.select_node(path);
+
+    // Starts from the current bindings so custom ones stay, with the arrow keys of an
+    //  earlier selection put back.
+    let mut bindings = renderer.camera_controller_mut().bindings().clone();
+    let mut movement = [
+        &mut bindings.forward,
+        &mut bindings.backward,
+        &mut bindings.left,
+        &mut bindings.right,
+    ];
+    for (keys, arrows) in movement.iter_mut().zip(arrow_bindings.iter_mut()) {
+        keys.append(arrows);
+        if scene.selected_node().is_some() {
+            arrows.extend(keys.iter().filter(|key| ARROW_KEYS.contains(key)));
+            keys.retain(|key| !ARROW_KEYS.contains(key));
+        }
+    }
+    renderer.camera_controller_mut().set_bindings(bindings);
+}
+
 #[cfg(target_arch = "wasm32")]
 mod wasm {
     use wasm_bindgen::prelude::*;
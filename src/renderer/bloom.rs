@@ -0,0 +1,454 @@
+use super::texture::Texture;
+
+use wgpu::util::DeviceExt;
+
+// The mip chain stops once its smaller side would drop below this. That keeps the
+//  smallest level, and with it the widest blur, the same fraction of the screen at any
+//  resolution.
+const MIN_LEVEL_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    // 0 while bloom is off, so the composite just copies the scene.
+    intensity: f32,
+    // Upsample blur radius as a fraction of the screen height.
+    filter_radius: f32,
+    aspect_ratio: f32,
+}
+
+// Bloom on the HDR target the scene is drawn to. bloom_bright.wgsl keeps the pixels above
+//  the threshold at half resolution, bloom_blur.wgsl downsamples those through a chain of
+//  ever smaller levels and blurs them back up, adding every level to the one above. The
+//  composite adds the result to the scene while copying it to the surface, which it also
+//  does with bloom off.
+pub struct Bloom {
+    enabled: bool,
+    uniform: BloomUniform,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+    // Largest first.
+    levels: Vec<wgpu::TextureView>,
+    // Read the HDR target, and every level in the same order.
+    bright_bind_group: wgpu::BindGroup,
+    level_bind_groups: Vec<wgpu::BindGroup>,
+    composite_bind_group: wgpu::BindGroup,
+    bright_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+}
+
+impl Bloom {
+    // hdr_view is the target the scene is drawn to, see Renderer::hdr_texture.
+    pub fn new(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        hdr_view: &wgpu::TextureView,
+    ) -> Self {
+        let uniform = BloomUniform {
+            threshold: 1.0,
+            intensity: 0.0,
+            filter_radius: 0.005,
+            aspect_ratio: aspect_ratio(surface_configuration),
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Bloom uniform buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let entries = [
+            texture_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                    filtering: true,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            texture_entry(3),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries[..3],
+            label: Some("Bloom bind group layout"),
+        });
+        // The same plus the bloom to add.
+        let composite_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &entries,
+                label: Some("Bloom composite bind group layout"),
+            });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let bright_shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/bloom_bright.wgsl"));
+        let blur_shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/bloom_blur.wgsl"));
+        let bright_pipeline = Self::create_pipeline(
+            device,
+            &layout,
+            &bright_shader,
+            "fs_main",
+            Texture::HDR_FORMAT,
+            None,
+            "Bloom bright pass pipeline",
+        );
+        let downsample_pipeline = Self::create_pipeline(
+            device,
+            &layout,
+            &blur_shader,
+            "fs_downsample",
+            Texture::HDR_FORMAT,
+            None,
+            "Bloom downsample pipeline",
+        );
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let upsample_pipeline = Self::create_pipeline(
+            device,
+            &layout,
+            &blur_shader,
+            "fs_upsample",
+            Texture::HDR_FORMAT,
+            Some(wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+            "Bloom upsample pipeline",
+        );
+
+        let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom composite pipeline layout"),
+            bind_group_layouts: &[&composite_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let composite_shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/bloom_composite.wgsl"));
+        let composite_pipeline = Self::create_pipeline(
+            device,
+            &composite_layout,
+            &composite_shader,
+            "fs_main",
+            surface_configuration.format,
+            None,
+            "Bloom composite pipeline",
+        );
+
+        let (levels, bright_bind_group, level_bind_groups, composite_bind_group) =
+            Self::create_levels(
+                device,
+                surface_configuration,
+                hdr_view,
+                &uniform_buffer,
+                &sampler,
+                &bind_group_layout,
+                &composite_bind_group_layout,
+            );
+
+        Self {
+            enabled: false,
+            uniform,
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            composite_bind_group_layout,
+            levels,
+            bright_bind_group,
+            level_bind_groups,
+            composite_bind_group,
+            bright_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+        }
+    }
+
+    fn create_levels(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        hdr_view: &wgpu::TextureView,
+        uniform_buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        composite_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> (
+        Vec<wgpu::TextureView>,
+        wgpu::BindGroup,
+        Vec<wgpu::BindGroup>,
+        wgpu::BindGroup,
+    ) {
+        // Separate textures rather than the mips of one, so no pass reads the texture it
+        //  draws to.
+        let mut levels = vec![];
+        let mut width = (surface_configuration.width / 2).max(1);
+        let mut height = (surface_configuration.height / 2).max(1);
+        loop {
+            let view = device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Bloom level"),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: Texture::HDR_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            levels.push(view);
+
+            width /= 2;
+            height /= 2;
+            if width.min(height) < MIN_LEVEL_SIZE {
+                break;
+            }
+        }
+
+        let bind_group = |view: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("Bloom bind group"),
+            })
+        };
+        let bright_bind_group = bind_group(hdr_view);
+        let level_bind_groups = levels.iter().map(bind_group).collect();
+
+        let composite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: composite_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&levels[0]),
+                },
+            ],
+            label: Some("Bloom composite bind group"),
+        });
+
+        (
+            levels,
+            bright_bind_group,
+            level_bind_groups,
+            composite_bind_group,
+        )
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        fragment_entry_point: &str,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+        label: &str,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fragment_entry_point,
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                strip_index_format: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        })
+    }
+
+    // Pixels brighter than threshold bloom, intensity scales how much of it is added back.
+    pub fn set(&mut self, queue: &wgpu::Queue, enabled: bool, threshold: f32, intensity: f32) {
+        self.enabled = enabled;
+        self.uniform.threshold = threshold;
+        self.uniform.intensity = if enabled { intensity } else { 0.0 };
+        self.write_uniform(queue);
+    }
+
+    fn write_uniform(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    // The levels depend on the surface size, and the bind groups hold the new hdr_view.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        hdr_view: &wgpu::TextureView,
+    ) {
+        let (levels, bright_bind_group, level_bind_groups, composite_bind_group) =
+            Self::create_levels(
+                device,
+                surface_configuration,
+                hdr_view,
+                &self.uniform_buffer,
+                &self.sampler,
+                &self.bind_group_layout,
+                &self.composite_bind_group_layout,
+            );
+        self.levels = levels;
+        self.bright_bind_group = bright_bind_group;
+        self.level_bind_groups = level_bind_groups;
+        self.composite_bind_group = composite_bind_group;
+        self.uniform.aspect_ratio = aspect_ratio(surface_configuration);
+        self.write_uniform(queue);
+    }
+
+    // Fills the mip chain from the HDR target, does nothing while bloom is off. Call after
+    //  the scene is drawn and before composite.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut pass = |target: &wgpu::TextureView,
+                        load: wgpu::LoadOp<wgpu::Color>,
+                        pipeline: &wgpu::RenderPipeline,
+                        bind_group: &wgpu::BindGroup| {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Bloom render pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load, store: true },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        };
+
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        pass(
+            &self.levels[0],
+            clear,
+            &self.bright_pipeline,
+            &self.bright_bind_group,
+        );
+        for i in 1..self.levels.len() {
+            pass(
+                &self.levels[i],
+                clear,
+                &self.downsample_pipeline,
+                &self.level_bind_groups[i - 1],
+            );
+        }
+        for i in (1..self.levels.len()).rev() {
+            pass(
+                &self.levels[i - 1],
+                wgpu::LoadOp::Load,
+                &self.upsample_pipeline,
+                &self.level_bind_groups[i],
+            );
+        }
+    }
+
+    // Draws the scene with the bloom added into render_pass, which should cover the whole
+    //  surface.
+    pub fn composite<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, &self.composite_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn aspect_ratio(surface_configuration: &wgpu::SurfaceConfiguration) -> f32 {
+    surface_configuration.width as f32 / surface_configuration.height.max(1) as f32
+}
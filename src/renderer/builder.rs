@@ -0,0 +1,101 @@
+use super::light::Light;
+use super::{Renderer, RendererError};
+
+use winit::window::Window;
+
+// What Renderer::new otherwise decides by itself, set with chained calls before building:
+//  RendererBuilder::new().present_mode(wgpu::PresentMode::Mailbox).build(&window).await
+//  Whatever isn't set is what Renderer::new uses.
+#[derive(Clone, Debug)]
+pub struct RendererBuilder {
+    pub(super) power_preference: wgpu::PowerPreference,
+    pub(super) present_mode: wgpu::PresentMode,
+    pub(super) clear_color: wgpu::Color,
+    pub(super) features: wgpu::Features,
+    pub(super) limits: wgpu::Limits,
+    pub(super) light: Light,
+    pub(super) sample_count: u32,
+}
+
+impl RendererBuilder {
+    pub fn new() -> Self {
+        Self {
+            power_preference: wgpu::PowerPreference::default(),
+            present_mode: wgpu::PresentMode::Fifo,
+            clear_color: wgpu::Color::BLACK,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+            // Bright enough to light the origin, 2√3 away, about as much as before the
+            //  falloff was there.
+            light: Light::point([2.0, 2.0, 2.0], [1.0, 1.0, 1.0], 40.0),
+            sample_count: 1,
+        }
+    }
+
+    // Which adapter to ask for when there's a choice, e.g. a laptop's integrated gpu.
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    // Fifo (vsync) by default, which every surface supports.
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    pub fn clear_color(mut self, clear_color: wgpu::Color) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    // The device must support these, building fails with RendererError::MissingFeatures
+    //  otherwise. Timestamp queries and push constants are turned on when available
+    //  regardless, see GpuTimer and draw_constants.rs.
+    pub fn features(mut self, features: wgpu::Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn limits(mut self, limits: wgpu::Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // The light the scene starts out with, see Renderer::set_light_position and friends
+    //  to change it later.
+    pub fn light(mut self, light: Light) -> Self {
+        self.light = light;
+        self
+    }
+
+    // Multisampled anti-aliasing of the scene, 1 turns it off. 4 is what every adapter
+    //  supports. The scene and the G-buffer are drawn multisampled and resolved before
+    //  bloom and SSAO, which stay single sampled.
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        assert!(
+            sample_count.is_power_of_two(),
+            "sample_count must be a power of two, got {}",
+            sample_count
+        );
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub async fn build(self, window: &Window) -> Result<Renderer, RendererError> {
+        Renderer::from_builder(Some(window), window.inner_size(), self).await
+    }
+
+    // Draws to a texture instead of a window, see Renderer::new_headless. The present mode
+    //  doesn't matter there.
+    pub async fn build_headless(self, width: u32, height: u32) -> Result<Renderer, RendererError> {
+        let size = winit::dpi::PhysicalSize::new(width, height);
+        Renderer::from_builder(None, size, self).await
+    }
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,401 @@
+use super::instance::InstanceRaw;
+use super::model::Model;
+use super::viewport::Viewport;
+
+use std::ops::Range;
+
+use cgmath::{Matrix4, Vector3};
+
+const WORKGROUP_SIZE: u32 = 64;
+// Bytes per mesh in an indirect buffer, see wgpu's draw_indexed_indirect.
+const INDIRECT_ARGS_SIZE: usize = 5 * std::mem::size_of::<u32>();
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullParams {
+    view_proj: [[f32; 4]; 4],
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+}
+
+// Which instances of a model to draw in a viewport, see Culling::visibility.
+pub enum Visibility<'a> {
+    // Drawn without culling.
+    All,
+    // Culled on the cpu, the visible instances as runs of consecutive ids.
+    Ranges(Vec<Range<u32>>),
+    // Culled on the gpu, the visible instances were copied to the target.
+    Indirect(&'a CullTarget),
+    // The visible instances sorted by level of detail, with the range of each level in
+    //  instances. See lod.rs.
+    Lod {
+        instances: &'a wgpu::Buffer,
+        levels: &'a [Range<u32>],
+    },
+}
+
+// Frustum culling of the opaque models' instances, turned on with
+//  Renderer::set_gpu_culling. The selected model is never culled, the instances are
+//  renumbered on the gpu and the highlight goes by instance id.
+pub enum Culling {
+    Off,
+    // When the adapter can't draw indirectly.
+    Cpu,
+    Gpu(GpuCulling),
+}
+
+impl Culling {
+    // Records the gpu culling of every viewport, call after the instances are synced and
+    //  before the passes drawing them.
+    pub fn record(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        models: &[Model],
+        viewports: &[Viewport],
+        skip_model: Option<usize>,
+    ) {
+        if let Culling::Gpu(gpu_culling) = self {
+            gpu_culling.record(device, queue, encoder, models, viewports, skip_model);
+        }
+    }
+
+    // Per model, how to draw it in the given viewport. The gpu culling has to be recorded
+    //  for this frame already. Models with hidden instances are drawn in the runs between
+    //  them, culled on the cpu when culling is on.
+    pub fn visibility(
+        &self,
+        models: &[Model],
+        viewport_id: usize,
+        viewport: &Viewport,
+        skip_model: Option<usize>,
+    ) -> Vec<Visibility<'_>> {
+        let view_proj = view_proj(viewport);
+        models
+            .iter()
+            .enumerate()
+            .map(|(model_id, model)| {
+                // Transparent instances are drawn one by one, they skip hidden ones there.
+                if model.is_transparent() {
+                    return Visibility::All;
+                }
+                let frustum = match self {
+                    Culling::Off => None,
+                    _ if Some(model_id) == skip_model => None,
+                    _ => Some(view_proj),
+                };
+                if model.has_hidden_instances() {
+                    return Visibility::Ranges(visible_ranges(model, frustum));
+                }
+                if Some(model_id) == skip_model {
+                    return Visibility::All;
+                }
+                match self {
+                    Culling::Off => Visibility::All,
+                    Culling::Cpu => Visibility::Ranges(visible_ranges(model, frustum)),
+                    Culling::Gpu(gpu_culling) => match gpu_culling.target(viewport_id, model_id) {
+                        Some(target) => Visibility::Indirect(target),
+                        None => Visibility::All,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+fn view_proj(viewport: &Viewport) -> Matrix4<f32> {
+    viewport.camera.view_projection()
+}
+
+// The ids of the instances that aren't hidden and whose world bounds are (partly) in
+//  view, consecutive ones merged so they're drawn in one go. Without a view_proj only
+//  hidden instances are left out.
+fn visible_ranges(model: &Model, view_proj: Option<Matrix4<f32>>) -> Vec<Range<u32>> {
+    let mut ranges: Vec<Range<u32>> = vec![];
+    for instance_id in 0..model.get_num_instances() {
+        let visible = model.is_instance_visible(instance_id)
+            && match view_proj {
+                Some(view_proj) => model
+                    .instance_bounds(instance_id)
+                    .is_some_and(|bounds| !outside_frustum(view_proj, bounds)),
+                None => true,
+            };
+        if !visible {
+            continue;
+        }
+        let instance_id = instance_id as u32;
+        match ranges.last_mut() {
+            Some(range) if range.end == instance_id => range.end += 1,
+            _ => ranges.push(instance_id..instance_id + 1),
+        }
+    }
+    ranges
+}
+
+// Corner i of the box, bits 0, 1 and 2 pick max over min for x, y and z.
+pub fn corner(bounds: (Vector3<f32>, Vector3<f32>), i: usize) -> Vector3<f32> {
+    let (min, max) = bounds;
+    Vector3::new(
+        if i & 1 == 0 { min.x } else { max.x },
+        if i & 2 == 0 { min.y } else { max.y },
+        if i & 4 == 0 { min.z } else { max.z },
+    )
+}
+
+// True if all corners of the box lie outside the same clip plane. Conservative: a box
+//  near a frustum corner can pass without being visible.
+pub fn outside_frustum(view_proj: Matrix4<f32>, bounds: (Vector3<f32>, Vector3<f32>)) -> bool {
+    let corners: Vec<_> = (0..8)
+        .map(|i| view_proj * corner(bounds, i).extend(1.0))
+        .collect();
+
+    // Clip space in wgpu: -w <= x, y <= w and 0 <= z <= w.
+    let planes: [fn(&cgmath::Vector4<f32>) -> bool; 6] = [
+        |c| c.x < -c.w,
+        |c| c.x > c.w,
+        |c| c.y < -c.w,
+        |c| c.y > c.w,
+        |c| c.z < 0.0,
+        |c| c.z > c.w,
+    ];
+    planes.iter().any(|outside| corners.iter().all(outside))
+}
+
+// Indirect draws need the downlevel flag, the culling pass binds three storage buffers.
+pub fn gpu_culling_supported(
+    downlevel: &wgpu::DownlevelCapabilities,
+    limits: &wgpu::Limits,
+) -> bool {
+    downlevel
+        .flags
+        .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION)
+        && limits.max_storage_buffers_per_shader_stage >= 3
+}
+
+// Where one model's visible instances in one viewport end up, with the indirect draw of
+//  each of its meshes.
+pub struct CullTarget {
+    // Room for this many instances.
+    capacity: usize,
+    mesh_count: usize,
+    pub instance_buffer: wgpu::Buffer,
+    // The draw_indexed_indirect arguments, one set per mesh.
+    pub indirect_buffer: wgpu::Buffer,
+    // Counted up by the culling pass, then copied into each mesh's instance count.
+    count_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+}
+
+impl CullTarget {
+    fn new(device: &wgpu::Device, capacity: usize, mesh_count: usize) -> Self {
+        let buffer = |label, size: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size as wgpu::BufferAddress,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            capacity,
+            mesh_count,
+            instance_buffer: buffer(
+                "Culled instance buffer",
+                capacity * std::mem::size_of::<InstanceRaw>(),
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            ),
+            indirect_buffer: buffer(
+                "Culled indirect buffer",
+                mesh_count * INDIRECT_ARGS_SIZE,
+                wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+            ),
+            count_buffer: buffer(
+                "Culled count buffer",
+                std::mem::size_of::<u32>(),
+                wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            ),
+            params_buffer: buffer(
+                "Cull params buffer",
+                std::mem::size_of::<CullParams>(),
+                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            ),
+        }
+    }
+
+    // Where the mesh's arguments are in indirect_buffer.
+    pub fn indirect_offset(mesh_id: usize) -> wgpu::BufferAddress {
+        (mesh_id * INDIRECT_ARGS_SIZE) as wgpu::BufferAddress
+    }
+}
+
+// The culling pass, see cull_instances.wgsl. Each viewport and model gets its own target,
+//  since all viewports are drawn in the same pass.
+pub struct GpuCulling {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // Per viewport, per model. None for models that aren't culled this frame.
+    targets: Vec<Vec<Option<CullTarget>>>,
+}
+
+impl GpuCulling {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                entry(0, wgpu::BufferBindingType::Uniform),
+                entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+                entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+                entry(3, wgpu::BufferBindingType::Storage { read_only: false }),
+            ],
+            label: Some("Cull bind group layout"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/cull_instances.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            targets: vec![],
+        }
+    }
+
+    fn target(&self, viewport_id: usize, model_id: usize) -> Option<&CullTarget> {
+        self.targets.get(viewport_id)?.get(model_id)?.as_ref()
+    }
+
+    fn record(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        models: &[Model],
+        viewports: &[Viewport],
+        skip_model: Option<usize>,
+    ) {
+        self.targets.resize_with(viewports.len(), Vec::new);
+        for (viewport, targets) in viewports.iter().zip(&mut self.targets) {
+            targets.resize_with(models.len(), || None);
+            let view_proj = view_proj(viewport);
+
+            for (model_id, (model, target)) in models.iter().zip(targets.iter_mut()).enumerate() {
+                // The instances the gpu buffer holds, it can lag behind until the next sync.
+                let count = model
+                    .get_num_instances()
+                    .min(model.instance_resource._get_gpu_length());
+                // Models with hidden instances are culled on the cpu, see visibility.
+                if Some(model_id) == skip_model
+                    || model.is_transparent()
+                    || model.has_hidden_instances()
+                    || count == 0
+                {
+                    *target = None;
+                    continue;
+                }
+
+                // Grown to the next power of two, so adding instances one by one doesn't
+                //  reallocate every frame.
+                let fits = target.as_ref().is_some_and(|target| {
+                    target.capacity >= count && target.mesh_count == model.meshes.len()
+                });
+                if !fits {
+                    *target = Some(CullTarget::new(
+                        device,
+                        count.next_power_of_two(),
+                        model.meshes.len(),
+                    ));
+                }
+                let target = target.as_ref().unwrap();
+
+                let (min, max) = model.bounds;
+                let params = CullParams {
+                    view_proj: view_proj.into(),
+                    bounds_min: min.extend(0.0).into(),
+                    bounds_max: max.extend(0.0).into(),
+                };
+                queue.write_buffer(&target.params_buffer, 0, bytemuck::bytes_of(&params));
+                queue.write_buffer(&target.count_buffer, 0, bytemuck::bytes_of(&0u32));
+                // Index count, instance count, first index, base vertex and first
+                //  instance. The instance count is filled in below.
+                let args: Vec<[u32; 5]> = model
+                    .meshes
+                    .iter()
+                    .map(|mesh| [mesh.num_elements(), 0, 0, 0, 0])
+                    .collect();
+                queue.write_buffer(&target.indirect_buffer, 0, bytemuck::cast_slice(&args));
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: target.params_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: model.instance_resource.get_gpu_buffer(),
+                                offset: 0,
+                                size: wgpu::BufferSize::new(
+                                    (count * std::mem::size_of::<InstanceRaw>()) as u64,
+                                ),
+                            }),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: target.instance_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: target.count_buffer.as_entire_binding(),
+                        },
+                    ],
+                    label: Some("Cull bind group"),
+                });
+
+                {
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("Cull pass"),
+                        });
+                    compute_pass.set_pipeline(&self.pipeline);
+                    compute_pass.set_bind_group(0, &bind_group, &[]);
+                    let workgroups = (count as u32).div_ceil(WORKGROUP_SIZE);
+                    compute_pass.dispatch(workgroups, 1, 1);
+                }
+                for mesh_id in 0..model.meshes.len() {
+                    encoder.copy_buffer_to_buffer(
+                        &target.count_buffer,
+                        0,
+                        &target.indirect_buffer,
+                        CullTarget::indirect_offset(mesh_id) + std::mem::size_of::<u32>() as u64,
+                        std::mem::size_of::<u32>() as wgpu::BufferAddress,
+                    );
+                }
+            }
+        }
+    }
+}
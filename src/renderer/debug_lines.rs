@@ -0,0 +1,209 @@
+use crate::renderer::{
+    culling::{corner, outside_frustum},
+    model::Vertex,
+    resource::{Resource, ResourceType},
+    texture::Texture,
+};
+
+use std::sync::Arc;
+
+use cgmath::{Matrix4, Vector3};
+
+const DRAWN_COLOR: [f32; 3] = [0.0, 1.0, 0.0];
+const CULLED_COLOR: [f32; 3] = [1.0, 0.0, 0.0];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+impl Vertex for DebugVertex {
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+            ],
+        }
+    }
+}
+
+// Colored world space lines, rebuilt every frame. Used to draw the bounding box of every
+//  instance, see Renderer::draw_debug_bounds.
+pub struct DebugLines {
+    pipeline: wgpu::RenderPipeline,
+    vertices: Resource<DebugVertex>,
+    // Of the target the lines are drawn into, kept for set_reverse_z.
+    sample_count: u32,
+}
+
+impl DebugLines {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let pipeline = Self::create_pipeline(
+            &device,
+            uniform_bind_group_layout,
+            color_format,
+            depth_format,
+            sample_count,
+            false,
+        );
+
+        let vertices = Resource::new_sized(device, queue, 24, ResourceType::Vertex);
+
+        Self {
+            pipeline,
+            vertices,
+            sample_count,
+        }
+    }
+
+    // Rebuilds the pipeline for the depth direction, see Renderer::set_reverse_z.
+    pub fn set_reverse_z(
+        &mut self,
+        device: &wgpu::Device,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        reverse_z: bool,
+    ) {
+        self.pipeline = Self::create_pipeline(
+            device,
+            uniform_bind_group_layout,
+            color_format,
+            depth_format,
+            self.sample_count,
+            reverse_z,
+        );
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        reverse_z: bool,
+    ) -> wgpu::RenderPipeline {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug lines pipeline layout"),
+            bind_group_layouts: &[uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("shader_src/debug.wgsl"));
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug lines render pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[DebugVertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                strip_index_format: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                clamp_depth: false,
+                conservative: false,
+            },
+            // Depth tested so the boxes sit in the scene, but they don't occlude anything.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: Texture::depth_compare(reverse_z, true),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    // Adds the 12 edges of the box (min, max).
+    pub fn add_box(&mut self, bounds: (Vector3<f32>, Vector3<f32>), color: [f32; 3]) {
+        // Corners that differ in exactly one axis bit share an edge.
+        let mut lines = Vec::with_capacity(24);
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    lines.push(DebugVertex {
+                        position: corner(bounds, i).into(),
+                        color,
+                    });
+                    lines.push(DebugVertex {
+                        position: corner(bounds, i | axis).into(),
+                        color,
+                    });
+                }
+            }
+        }
+        self.vertices.add_to_buffer(lines);
+    }
+
+    // Adds the box in green if it's (partly) in view, red if culling would skip it.
+    pub fn add_bounds(&mut self, bounds: (Vector3<f32>, Vector3<f32>), view_proj: Matrix4<f32>) {
+        let color = if outside_frustum(view_proj, bounds) {
+            CULLED_COLOR
+        } else {
+            DRAWN_COLOR
+        };
+        self.add_box(bounds, color);
+    }
+
+    // Uploads the lines, call before the render pass they're drawn in starts.
+    pub fn sync_gpu(&mut self) {
+        self.vertices.sync_gpu();
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        uniforms: &'a wgpu::BindGroup,
+    ) {
+        let count = self.vertices.get_cpu_length() as u32;
+        if count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, uniforms, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.get_gpu_buffer().slice(..));
+        render_pass.draw(0..count, 0..1);
+    }
+}
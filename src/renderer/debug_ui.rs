@@ -0,0 +1,236 @@
+use std::time::Instant;
+
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+// How far a scroll wheel notch scrolls, in points.
+const POINTS_PER_SCROLL_LINE: f32 = 50.0;
+
+// Egui drawn on top of the finished frame, see draw_scene. The app feeds it window events
+//  with handle_event and builds its panels on the context begin_frame returns, the frame
+//  is drawn by draw_scene after that.
+pub struct DebugUi {
+    context: egui::CtxRef,
+    render_pass: RenderPass,
+    // Collects the events of the frame being built.
+    raw_input: egui::RawInput,
+    // In points, pointer button events need to know where they happened.
+    pointer_position: egui::Pos2,
+    modifiers: egui::Modifiers,
+    scale_factor: f32,
+    start_time: Instant,
+    // Whether begin_frame was called since the last draw.
+    frame_started: bool,
+}
+
+impl DebugUi {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, scale_factor: f64) -> Self {
+        Self {
+            context: egui::CtxRef::default(),
+            render_pass: RenderPass::new(device, format, 1),
+            raw_input: egui::RawInput::default(),
+            pointer_position: egui::Pos2::ZERO,
+            modifiers: egui::Modifiers::default(),
+            scale_factor: scale_factor as f32,
+            start_time: Instant::now(),
+            frame_started: false,
+        }
+    }
+
+    // Hands a window event to egui. Returns true when egui takes it, like a click on a
+    //  panel or typing into a field, the rest of the app should ignore those. Releases are
+    //  never taken, so nothing else is left thinking a key or button is still held.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor = *scale_factor as f32;
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer_position = egui::pos2(
+                    position.x as f32 / self.scale_factor,
+                    position.y as f32 / self.scale_factor,
+                );
+                self.raw_input
+                    .events
+                    .push(egui::Event::PointerMoved(self.pointer_position));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.raw_input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                let pressed = *state == ElementState::Pressed;
+                self.raw_input.events.push(egui::Event::PointerButton {
+                    pos: self.pointer_position,
+                    button,
+                    pressed,
+                    modifiers: self.modifiers,
+                });
+                pressed && self.context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.raw_input.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        egui::vec2(*x, *y) * POINTS_PER_SCROLL_LINE
+                    }
+                    MouseScrollDelta::PixelDelta(position) => {
+                        egui::vec2(position.x as f32, position.y as f32) / self.scale_factor
+                    }
+                };
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = modifiers(*state);
+                false
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                if c.is_control() {
+                    return false;
+                }
+                self.raw_input.events.push(egui::Event::Text(c.to_string()));
+                self.context.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                if let Some(key) = key(*keycode) {
+                    self.raw_input.events.push(egui::Event::Key {
+                        key,
+                        pressed,
+                        modifiers: self.modifiers,
+                    });
+                }
+                pressed && self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    // Whether the pointer is over a panel or dragging something in it. Mouse motion and
+    //  scrolling shouldn't move the camera then.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.context.wants_pointer_input()
+    }
+
+    // Starts a frame with the events handled since the last one. Build the panels on the
+    //  returned context before drawing the scene.
+    pub fn begin_frame(&mut self, (width, height): (u32, u32)) -> egui::CtxRef {
+        // A frame that never got drawn, e.g. because the surface was lost.
+        if self.frame_started {
+            let _ = self.context.end_frame();
+        }
+
+        let mut raw_input = std::mem::take(&mut self.raw_input);
+        raw_input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(width as f32, height as f32) / self.scale_factor,
+        ));
+        raw_input.pixels_per_point = Some(self.scale_factor);
+        raw_input.time = Some(self.start_time.elapsed().as_secs_f64());
+        raw_input.modifiers = self.modifiers;
+        self.context.begin_frame(raw_input);
+        self.frame_started = true;
+
+        self.context.clone()
+    }
+
+    // Finishes the frame and draws it over what's in view. Does nothing if no frame was
+    //  started.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        (width, height): (u32, u32),
+    ) {
+        if !self.frame_started {
+            return;
+        }
+        self.frame_started = false;
+
+        // The output asks for things like a different cursor icon, which aren't supported.
+        let (_output, shapes) = self.context.end_frame();
+        let paint_jobs = self.context.tessellate(shapes);
+        let screen_descriptor = ScreenDescriptor {
+            physical_width: width,
+            physical_height: height,
+            scale_factor: self.scale_factor,
+        };
+
+        self.render_pass
+            .update_texture(device, queue, &self.context.texture());
+        self.render_pass.update_user_textures(device, queue);
+        self.render_pass
+            .update_buffers(device, queue, &paint_jobs, &screen_descriptor);
+        if let Err(e) =
+            self.render_pass
+                .execute(encoder, view, &paint_jobs, &screen_descriptor, None)
+        {
+            eprintln!("Failed to draw the debug ui: {:?}", e);
+        }
+    }
+}
+
+fn modifiers(state: ModifiersState) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: state.alt(),
+        ctrl: state.ctrl(),
+        shift: state.shift(),
+        mac_cmd: cfg!(target_os = "macos") && state.logo(),
+        command: if cfg!(target_os = "macos") {
+            state.logo()
+        } else {
+            state.ctrl()
+        },
+    }
+}
+
+// The keys egui uses to edit text and move between widgets.
+fn key(keycode: VirtualKeyCode) -> Option<egui::Key> {
+    use egui::Key;
+    Some(match keycode {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::K => Key::K,
+        VirtualKeyCode::U => Key::U,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::W => Key::W,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
@@ -0,0 +1,498 @@
+use crate::renderer::{
+    instance::InstanceRaw,
+    model::ModelVertex,
+    model::Vertex,
+    ssao::Ssao,
+    texture::{DepthBias, Texture},
+    viewport::Rect,
+};
+
+// How opaque models get lit, see Renderer::set_shading_mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadingMode {
+    // Every mesh is lit as it's drawn, by shader.wgsl.
+    Forward,
+    // Meshes only write their surface to the G-buffer, which is then lit in one fullscreen
+    //  draw. Transparent models are still drawn forward afterwards.
+    Deferred,
+}
+
+// Formats of the G-buffer targets, in the order gbuffer.wgsl writes them.
+const TARGET_FORMATS: [wgpu::TextureFormat; 4] = [
+    // Albedo with metallic in alpha.
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    // World space normal with roughness in w.
+    wgpu::TextureFormat::Rgba16Float,
+    // World space position, half floats aren't precise enough for this.
+    wgpu::TextureFormat::Rgba32Float,
+    // Emissive with the selection highlight in alpha.
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+const TARGET_LABELS: [&str; 4] = [
+    "G-buffer albedo",
+    "G-buffer normal",
+    "G-buffer position",
+    "G-buffer emissive",
+];
+
+// The pipelines and screen sized targets of deferred shading. The targets are only read
+//  with textureLoad, so there are no samplers.
+pub struct Deferred {
+    bind_group_layout: wgpu::BindGroupLayout,
+    views: Vec<wgpu::TextureView>,
+    // Multisampled targets the G-buffer pass draws to and resolves into views, empty
+    //  without MSAA. See RendererBuilder::sample_count.
+    msaa_views: Vec<wgpu::TextureView>,
+    // The G-buffer targets followed by the ambient occlusion of ssao.
+    bind_group: wgpu::BindGroup,
+    pub ssao: Ssao,
+    pub pipeline: wgpu::RenderPipeline,
+    // For double sided materials, like Renderer::no_cull_render_pipeline.
+    pub no_cull_pipeline: wgpu::RenderPipeline,
+    // For materials with depth_bias, like Renderer::depth_bias_render_pipeline.
+    pub depth_bias_pipeline: wgpu::RenderPipeline,
+    lighting_pipeline: wgpu::RenderPipeline,
+    // Kept to build the G-buffer pipelines again, see set_reverse_z and set_depth_bias.
+    shader: wgpu::ShaderModule,
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+    reverse_z: bool,
+    depth_bias: DepthBias,
+}
+
+impl Deferred {
+    // render_pipeline_layout is the one of the forward pipelines, the G-buffer is written
+    //  with the same bind groups. push_constants as in Renderer::new. sample_count is the
+    //  one of the depth texture, which the G-buffer pass shares with the forward pass.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        render_pipeline_layout: &wgpu::PipelineLayout,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        push_constants: bool,
+    ) -> Self {
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = (0..TARGET_FORMATS.len() as u32 + 1)
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    // Rgba32Float can't be filtered, the others just don't need to be.
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            })
+            .collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("G-buffer bind group layout"),
+        });
+        let ssao = Ssao::new(
+            device,
+            surface_configuration,
+            &bind_group_layout,
+            uniform_bind_group_layout,
+        );
+        let (views, bind_group) =
+            Self::create_targets(device, surface_configuration, &bind_group_layout, &ssao);
+        let msaa_views = Self::create_msaa_targets(device, surface_configuration, sample_count);
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("gbuffer.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                super::draw_constants::shader_source(
+                    include_str!("shader_src/gbuffer.wgsl"),
+                    push_constants,
+                )
+                .into(),
+            ),
+        });
+        let pipeline = Self::create_gbuffer_pipeline(
+            device,
+            render_pipeline_layout,
+            &shader,
+            depth_format,
+            sample_count,
+            Some(wgpu::Face::Back),
+            false,
+            wgpu::DepthBiasState::default(),
+        );
+        let no_cull_pipeline = Self::create_gbuffer_pipeline(
+            device,
+            render_pipeline_layout,
+            &shader,
+            depth_format,
+            sample_count,
+            None,
+            false,
+            wgpu::DepthBiasState::default(),
+        );
+        let depth_bias = DepthBias::default();
+        let depth_bias_pipeline = Self::create_gbuffer_pipeline(
+            device,
+            render_pipeline_layout,
+            &shader,
+            depth_format,
+            sample_count,
+            None,
+            false,
+            depth_bias.state(false),
+        );
+
+        let lighting_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred lighting pipeline layout"),
+            bind_group_layouts: &[
+                &bind_group_layout,
+                uniform_bind_group_layout,
+                light_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let lighting_shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/deferred_lighting.wgsl"));
+        let lighting_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Deferred lighting render pipeline"),
+            layout: Some(&lighting_layout),
+            vertex: wgpu::VertexState {
+                module: &lighting_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &lighting_shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                strip_index_format: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                clamp_depth: false,
+                conservative: false,
+            },
+            // The depth buffer of the G-buffer pass is still bound, the lighting neither
+            //  tests against nor writes it.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            // Drawn in the forward pass, into the multisampled HDR target with MSAA on.
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            bind_group_layout,
+            views,
+            msaa_views,
+            bind_group,
+            ssao,
+            pipeline,
+            no_cull_pipeline,
+            depth_bias_pipeline,
+            lighting_pipeline,
+            shader,
+            depth_format,
+            sample_count,
+            reverse_z: false,
+            depth_bias,
+        }
+    }
+
+    fn create_views(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Vec<wgpu::TextureView> {
+        TARGET_FORMATS
+            .iter()
+            .zip(TARGET_LABELS)
+            .map(|(&format, label)| {
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size: wgpu::Extent3d {
+                            width: surface_configuration.width,
+                            height: surface_configuration.height,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count,
+                        dimension: wgpu::TextureDimension::D2,
+                        format,
+                        usage: Texture::target_usage(sample_count),
+                    })
+                    .create_view(&wgpu::TextureViewDescriptor::default())
+            })
+            .collect()
+    }
+
+    // None are needed without MSAA, the G-buffer pass draws to the targets directly.
+    fn create_msaa_targets(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Vec<wgpu::TextureView> {
+        if sample_count > 1 {
+            Self::create_views(device, surface_configuration, sample_count)
+        } else {
+            vec![]
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+        ssao: &Ssao,
+    ) -> (Vec<wgpu::TextureView>, wgpu::BindGroup) {
+        let views = Self::create_views(device, surface_configuration, 1);
+
+        let entries: Vec<wgpu::BindGroupEntry> = views
+            .iter()
+            .chain(Some(ssao.ambient_occlusion_view()))
+            .enumerate()
+            .map(|(binding, view)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some("G-buffer bind group"),
+        });
+
+        (views, bind_group)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_gbuffer_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        cull_mode: Option<wgpu::Face>,
+        reverse_z: bool,
+        depth_bias: wgpu::DepthBiasState,
+    ) -> wgpu::RenderPipeline {
+        let targets: Vec<wgpu::ColorTargetState> = TARGET_FORMATS
+            .iter()
+            .map(|&format| wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+            .collect();
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("G-buffer render pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::layout(), InstanceRaw::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &targets,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode,
+                strip_index_format: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: true,
+                depth_compare: Texture::depth_compare(reverse_z, false),
+                stencil: wgpu::StencilState::default(),
+                bias: depth_bias,
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        })
+    }
+
+    // Rebuilds the G-buffer pipelines for the depth direction, and clears depth to match.
+    //  See Renderer::set_reverse_z.
+    pub fn set_reverse_z(
+        &mut self,
+        device: &wgpu::Device,
+        render_pipeline_layout: &wgpu::PipelineLayout,
+        reverse_z: bool,
+    ) {
+        self.pipeline = Self::create_gbuffer_pipeline(
+            device,
+            render_pipeline_layout,
+            &self.shader,
+            self.depth_format,
+            self.sample_count,
+            Some(wgpu::Face::Back),
+            reverse_z,
+            wgpu::DepthBiasState::default(),
+        );
+        self.no_cull_pipeline = Self::create_gbuffer_pipeline(
+            device,
+            render_pipeline_layout,
+            &self.shader,
+            self.depth_format,
+            self.sample_count,
+            None,
+            reverse_z,
+            wgpu::DepthBiasState::default(),
+        );
+        self.reverse_z = reverse_z;
+        self.set_depth_bias(device, render_pipeline_layout, self.depth_bias);
+    }
+
+    // See Renderer::set_depth_bias.
+    pub fn set_depth_bias(
+        &mut self,
+        device: &wgpu::Device,
+        render_pipeline_layout: &wgpu::PipelineLayout,
+        depth_bias: DepthBias,
+    ) {
+        self.depth_bias_pipeline = Self::create_gbuffer_pipeline(
+            device,
+            render_pipeline_layout,
+            &self.shader,
+            self.depth_format,
+            self.sample_count,
+            None,
+            self.reverse_z,
+            depth_bias.state(self.reverse_z),
+        );
+        self.depth_bias = depth_bias;
+    }
+
+    // What the G-buffer targets take up, see Renderer::gpu_memory_report. With MSAA that's
+    //  the multisampled targets on top of the resolved ones.
+    pub fn target_bytes(
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> u64 {
+        let samples = if sample_count > 1 {
+            1 + sample_count
+        } else {
+            1
+        };
+        let pixels = surface_configuration.width as u64
+            * surface_configuration.height as u64
+            * samples as u64;
+        TARGET_FORMATS
+            .iter()
+            .map(|format| pixels * format.describe().block_size as u64)
+            .sum()
+    }
+
+    // The targets have to match the surface size, call along with Renderer::resize.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+    ) {
+        self.ssao.resize(device, surface_configuration);
+        let (views, bind_group) = Self::create_targets(
+            device,
+            surface_configuration,
+            &self.bind_group_layout,
+            &self.ssao,
+        );
+        self.views = views;
+        self.bind_group = bind_group;
+        self.msaa_views =
+            Self::create_msaa_targets(device, surface_configuration, self.sample_count);
+    }
+
+    // Starts the pass that fills the G-buffer, clearing it and the depth buffer. With MSAA
+    //  the multisampled targets are drawn to and resolved into the ones the lighting reads.
+    pub fn gbuffer_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        depth_view: &'a wgpu::TextureView,
+    ) -> wgpu::RenderPass<'a> {
+        let color_attachments: Vec<wgpu::RenderPassColorAttachment> = self
+            .views
+            .iter()
+            .enumerate()
+            .map(|(i, view)| match self.msaa_views.get(i) {
+                Some(msaa_view) => (msaa_view, Some(view)),
+                None => (view, None),
+            })
+            .map(|(view, resolve_target)| wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    // A position w of 0 tells the lighting pass nothing was drawn there.
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })
+            .collect();
+
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-buffer render pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(Texture::far_depth(self.reverse_z)),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    // Fills in the ambient occlusion the lighting pass reads, after the G-buffer pass.
+    //  viewports as in Ssao::draw.
+    pub fn draw_ssao(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        viewports: &[(Rect, &wgpu::BindGroup)],
+    ) {
+        self.ssao.draw(encoder, &self.bind_group, viewports);
+    }
+
+    // Lights the G-buffer onto the pass' color target in one fullscreen draw.
+    pub fn draw_lighting<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        uniform_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.lighting_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,146 @@
+use std::num::NonZeroU64;
+
+// Small per draw values for shader.wgsl. Passed as push constants where the device has
+//  them, otherwise through a dynamic uniform buffer in bind group 3, see
+//  DrawConstantsBuffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawConstants {
+    selected_instance: i32,
+    // Uniform bindings want at least 16 bytes.
+    _padding: [u32; 3],
+}
+
+impl DrawConstants {
+    pub const SIZE: u32 = std::mem::size_of::<DrawConstants>() as u32;
+
+    pub fn new(selected_instance: Option<usize>) -> Self {
+        Self {
+            selected_instance: selected_instance.map_or(-1, |i| i as i32),
+            _padding: [0; 3],
+        }
+    }
+}
+
+// How a draw gets its constants, see DrawModel::draw_model_instanced.
+#[derive(Clone, Copy)]
+pub enum PerDraw<'a> {
+    PushConstants(DrawConstants),
+    // The fallback bind group and this draw's dynamic offset into it.
+    Uniform(&'a wgpu::BindGroup, wgpu::DynamicOffset),
+}
+
+// Push constants need the feature, and the device limit has to fit DrawConstants. We ask
+//  for exactly that much in Renderer::new, so check against the adapter's limit here.
+pub fn push_constants_supported(adapter: &wgpu::Adapter) -> bool {
+    adapter.features().contains(wgpu::Features::PUSH_CONSTANTS)
+        && adapter.limits().max_push_constant_size >= DrawConstants::SIZE
+}
+
+// shader.wgsl declares the constants as a push constant. Without support for those they're
+//  read from the fallback uniform instead.
+pub fn shader_source(source: &str, push_constants: bool) -> String {
+    if push_constants {
+        source.to_owned()
+    } else {
+        source.replace(
+            "var<push_constant> draw:",
+            "[[group(3), binding(0)]] var<uniform> draw:",
+        )
+    }
+}
+
+// Fallback for devices without push constants: the constants of every draw in a frame,
+//  each at its own dynamic offset in one uniform buffer.
+pub struct DrawConstantsBuffer {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // Bytes between draws, a multiple of the uniform offset alignment.
+    stride: u32,
+    capacity: usize,
+}
+
+impl DrawConstantsBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(DrawConstants::SIZE as u64),
+                },
+                count: None,
+            }],
+            label: Some("Draw constants bind group layout"),
+        });
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let stride = DrawConstants::SIZE.div_ceil(alignment) * alignment;
+        let capacity = 16;
+        let (buffer, bind_group) =
+            Self::create_buffer(device, &bind_group_layout, stride, capacity);
+
+        Self {
+            bind_group_layout,
+            buffer,
+            bind_group,
+            stride,
+            capacity,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        stride: u32,
+        capacity: usize,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Draw constants buffer"),
+            size: stride as wgpu::BufferAddress * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(DrawConstants::SIZE as u64),
+                }),
+            }],
+            label: Some("Draw constants bind group"),
+        });
+        (buffer, bind_group)
+    }
+
+    // Uploads the constants of all draws this frame, growing the buffer if needed. Call
+    //  before the render pass starts.
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, draws: &[DrawConstants]) {
+        if draws.len() > self.capacity {
+            self.capacity = draws.len().next_power_of_two();
+            let (buffer, bind_group) =
+                Self::create_buffer(device, &self.bind_group_layout, self.stride, self.capacity);
+            self.buffer = buffer;
+            self.bind_group = bind_group;
+        }
+
+        let mut data = vec![0u8; self.stride as usize * draws.len()];
+        for (i, draw) in draws.iter().enumerate() {
+            let offset = i * self.stride as usize;
+            data[offset..offset + DrawConstants::SIZE as usize]
+                .copy_from_slice(bytemuck::bytes_of(draw));
+        }
+        queue.write_buffer(&self.buffer, 0, &data);
+    }
+
+    // The constants written for draw i.
+    pub fn per_draw(&self, i: usize) -> PerDraw<'_> {
+        PerDraw::Uniform(&self.bind_group, i as u32 * self.stride)
+    }
+}
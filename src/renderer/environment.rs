@@ -0,0 +1,333 @@
+use std::num::NonZeroU32;
+
+use super::texture::{SamplerConfig, Texture};
+
+use wgpu::util::DeviceExt;
+
+// Diffuse light changes slowly with the direction, so its cubemap can be tiny.
+const IRRADIANCE_SIZE: u32 = 32;
+const SPECULAR_SIZE: u32 = 128;
+// Mip 0 is a mirror, the last one fully rough. Matches SPECULAR_MIP_LEVELS in shader.wgsl
+//  and deferred_lighting.wgsl.
+const SPECULAR_MIP_LEVELS: u32 = 5;
+// Roughly the flat ambient term lighting had before there was an environment.
+const NEUTRAL_GRAY: u8 = 13;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterUniform {
+    face: u32,
+    roughness: f32,
+    _padding: [u32; 2],
+}
+
+// Image based lighting, bound next to the light (see Renderer::set_environment). The
+//  environment cubemap is prefiltered into the light reaching a surface from all around
+//  it for diffuse ambient light, and into reflections that blur more with every mip for
+//  specular ambient light. See environment_prefilter.wgsl.
+pub struct Environment {
+    irradiance_view: wgpu::TextureView,
+    specular_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl Environment {
+    // Light the same gray from every direction, for when no environment is set.
+    pub fn neutral(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Neutral environment texture"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            &[NEUTRAL_GRAY, NEUTRAL_GRAY, NEUTRAL_GRAY, 255].repeat(6),
+        );
+
+        Self {
+            irradiance_view: cube_view(&texture),
+            specular_view: cube_view(&texture),
+            sampler: Self::sampler(device),
+        }
+    }
+
+    // Does all the prefiltering right away, which takes a moment for large cubemaps.
+    pub fn from_cubemap(device: &wgpu::Device, queue: &wgpu::Queue, cubemap: &Texture) -> Self {
+        let irradiance = create_cube_texture(device, IRRADIANCE_SIZE, 1, "Irradiance texture");
+        let specular = create_cube_texture(
+            device,
+            SPECULAR_SIZE,
+            SPECULAR_MIP_LEVELS,
+            "Prefiltered specular texture",
+        );
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Prefilter uniform buffer"),
+            size: std::mem::size_of::<PrefilterUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: cubemap.sampler_config.binding_type(),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Prefilter bind group layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cubemap.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Prefilter bind group"),
+        });
+
+        // Only used this once, so the pipelines aren't kept around.
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Prefilter pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::include_wgsl!(
+            "shader_src/environment_prefilter.wgsl"
+        ));
+        let irradiance_pipeline = create_pipeline(
+            device,
+            &layout,
+            &shader,
+            "fs_irradiance",
+            "Irradiance pipeline",
+        );
+        let specular_pipeline =
+            create_pipeline(device, &layout, &shader, "fs_specular", "Specular pipeline");
+
+        let mut passes = Vec::new();
+        for face in 0..6 {
+            passes.push((&irradiance_pipeline, &irradiance, 0, face, 0.0));
+            for mip in 0..SPECULAR_MIP_LEVELS {
+                let roughness = mip as f32 / (SPECULAR_MIP_LEVELS - 1) as f32;
+                passes.push((&specular_pipeline, &specular, mip, face, roughness));
+            }
+        }
+
+        // Every pass is submitted on its own, so the uniform written before it is the one
+        //  it sees.
+        for (pipeline, texture, mip, face, roughness) in passes {
+            queue.write_buffer(
+                &uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[PrefilterUniform {
+                    face,
+                    roughness,
+                    _padding: [0; 2],
+                }]),
+            );
+
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Prefilter target view"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: mip,
+                mip_level_count: NonZeroU32::new(1),
+                base_array_layer: face,
+                array_layer_count: NonZeroU32::new(1),
+                ..Default::default()
+            });
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Prefilter encoder"),
+            });
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Prefilter render pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        Self {
+            irradiance_view: cube_view(&irradiance),
+            specular_view: cube_view(&specular),
+            sampler: Self::sampler(device),
+        }
+    }
+
+    // Trilinear, the shaders pick the specular mip by roughness.
+    fn sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        SamplerConfig {
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        }
+        .create_sampler(device)
+    }
+
+    // The irradiance and specular cubemaps and their sampler, at bindings 1 to 3 of the
+    //  light bind group.
+    pub fn bind_group_layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        let cube = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        [
+            cube(1),
+            cube(2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                    filtering: true,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&self.specular_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
+
+fn create_cube_texture(
+    device: &wgpu::Device,
+    size: u32,
+    mip_level_count: u32,
+    label: &str,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Texture::HDR_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    })
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Environment view"),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fs_entry_point: &str,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fs_entry_point,
+            targets: &[wgpu::ColorTargetState {
+                format: Texture::HDR_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            strip_index_format: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            clamp_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+    })
+}
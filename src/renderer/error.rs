@@ -0,0 +1,54 @@
+use std::fmt;
+
+// Ways Renderer::new can fail. These depend on the machine we run on rather than on a bug,
+//  so they're returned instead of panicking.
+#[derive(Debug)]
+pub enum RendererError {
+    // No adapter can present to the window's surface, or there's none at all headless.
+    NoAdapter,
+    // The adapter can't present to the surface in the format we render to.
+    UnsupportedSurface {
+        adapter: String,
+    },
+    // The caller asked for features the adapter doesn't have, see RendererBuilder::features.
+    MissingFeatures {
+        adapter: String,
+        missing: wgpu::Features,
+    },
+    // Usually means the requested limits are higher than what the adapter supports.
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::NoAdapter => write!(f, "no compatible graphics adapter found"),
+            RendererError::UnsupportedSurface { adapter } => {
+                write!(f, "adapter {} can't present to this window", adapter)
+            }
+            RendererError::MissingFeatures { adapter, missing } => write!(
+                f,
+                "adapter {} doesn't support the requested features {:?}",
+                adapter, missing
+            ),
+            RendererError::RequestDevice(error) => {
+                write!(f, "failed to create device: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::RequestDevice(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for RendererError {
+    fn from(error: wgpu::RequestDeviceError) -> Self {
+        RendererError::RequestDevice(error)
+    }
+}
@@ -0,0 +1,111 @@
+use super::texture::Texture;
+
+// Where frames end up: the window's surface, or a texture when there's no window to
+//  present to. See Renderer::capture_frame.
+pub enum RenderTarget {
+    Surface(wgpu::Surface),
+    Offscreen(Texture),
+}
+
+// Owns what a frame needs to record a render pass into: the surface texture, its view
+//  and the command encoder. The render pass itself borrows from this, which gets around
+//  the &frame.view borrow issues of keeping it all in one place.
+pub struct ForwardPass {
+    // None when drawing offscreen, there's nothing to present.
+    frame: Option<wgpu::SurfaceTexture>,
+    pub view: wgpu::TextureView,
+    pub encoder: wgpu::CommandEncoder,
+}
+
+impl ForwardPass {
+    // Acquires the next surface texture to draw to, offscreen targets are drawn to as is.
+    pub fn begin(
+        target: &RenderTarget,
+        device: &wgpu::Device,
+    ) -> Result<Self, wgpu::SurfaceError> {
+        let (frame, view) = match target {
+            RenderTarget::Surface(surface) => {
+                let frame = surface.get_current_texture()?;
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(frame), view)
+            }
+            RenderTarget::Offscreen(texture) => (
+                None,
+                texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
+
+        // Encoders can create a commandbuffer.
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+
+        Ok(Self {
+            frame,
+            view,
+            encoder,
+        })
+    }
+
+    // Starts a render pass that clears color_view to clear_color, usually the HDR target
+    //  the scene is drawn to. A multisampled color_view is resolved into resolve_target.
+    //  The depth buffer is cleared or kept as depth_load says. Drop it before calling
+    //  submit.
+    pub fn pass<'a>(
+        &'a mut self,
+        color_view: &'a wgpu::TextureView,
+        resolve_target: Option<&'a wgpu::TextureView>,
+        clear_color: wgpu::Color,
+        depth_view: &'a wgpu::TextureView,
+        depth_load: wgpu::LoadOp<f32>,
+    ) -> wgpu::RenderPass<'a> {
+        self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Scene render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    // Starts a render pass on the frame itself, without depth. Whatever it draws has to
+    //  cover the whole frame, see Bloom::composite.
+    pub fn output_pass(&mut self) -> wgpu::RenderPass<'_> {
+        self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Output render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        })
+    }
+
+    // Finishes the encoder, submits it and presents the frame.
+    pub fn submit(self, queue: &wgpu::Queue) {
+        queue.submit(std::iter::once(self.encoder.finish()));
+        if let Some(frame) = self.frame {
+            frame.present();
+        }
+    }
+}
@@ -0,0 +1,23 @@
+// What the last draw_scene drew, see Renderer::frame_stats. Only the meshes of the scene's
+//  models and its particles are counted, fullscreen passes like the skybox and bloom
+//  aren't. Indirect draws
+//  of the gpu culling count as draw calls, but what they draw is only known on the gpu.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FrameStats {
+    pub draw_calls: u32,
+    pub instances: u32,
+    // Every instance counts, so a mesh of 300 indices drawn 10 times adds 3000.
+    pub indices: u64,
+}
+
+impl FrameStats {
+    pub fn record_draw(&mut self, instances: u32, indices: u32) {
+        self.draw_calls += 1;
+        self.instances += instances;
+        self.indices += instances as u64 * indices as u64;
+    }
+
+    pub fn triangles(&self) -> u64 {
+        self.indices / 3
+    }
+}
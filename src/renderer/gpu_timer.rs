@@ -0,0 +1,110 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::FutureExt;
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+// Measures how long the scene pass takes on the gpu with a pair of timestamp queries.
+//  Results are read back asynchronously, so they lag a frame or two behind. Frames that
+//  start while the previous readback is still in flight simply aren't measured.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    // Resolved timestamps end up here, it's mapped directly to read them.
+    buffer: wgpu::Buffer,
+    // Nanoseconds per timestamp tick.
+    period: f32,
+    recording: bool,
+    mapping: Option<MapFuture>,
+    last: Option<Duration>,
+}
+
+impl GpuTimer {
+    // None if the device wasn't created with Features::TIMESTAMP_QUERY.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Gpu timer queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gpu timer buffer"),
+            size: 2 * std::mem::size_of::<u64>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            buffer,
+            period: queue.get_timestamp_period(),
+            recording: false,
+            mapping: None,
+            last: None,
+        })
+    }
+
+    // The most recent measurement.
+    pub fn last(&self) -> Option<Duration> {
+        self.last
+    }
+
+    // Picks up a finished readback and, if the buffer is free again, writes the start
+    //  timestamp.
+    pub fn start(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        self.poll(device);
+        if self.mapping.is_none() {
+            encoder.write_timestamp(&self.query_set, 0);
+            self.recording = true;
+        }
+    }
+
+    // Writes the end timestamp and resolves both into the buffer.
+    pub fn finish(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.recording {
+            encoder.write_timestamp(&self.query_set, 1);
+            encoder.resolve_query_set(&self.query_set, 0..2, &self.buffer, 0);
+        }
+    }
+
+    // Starts reading the timestamps back, call once the encoder is submitted.
+    pub fn submitted(&mut self) {
+        if self.recording {
+            self.recording = false;
+            self.mapping = Some(Box::pin(
+                self.buffer.slice(..).map_async(wgpu::MapMode::Read),
+            ));
+        }
+    }
+
+    fn poll(&mut self, device: &wgpu::Device) {
+        let mapping = match &mut self.mapping {
+            Some(mapping) => mapping,
+            None => return,
+        };
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        match mapping.poll_unpin(&mut cx) {
+            Poll::Pending => return,
+            Poll::Ready(Ok(())) => {
+                let data = self.buffer.slice(..).get_mapped_range();
+                let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                let ticks = timestamps[1].saturating_sub(timestamps[0]);
+                self.last = Some(Duration::from_nanos(
+                    (ticks as f64 * self.period as f64) as u64,
+                ));
+                drop(data);
+                self.buffer.unmap();
+            }
+            Poll::Ready(Err(_)) => self.last = None,
+        }
+        self.mapping = None;
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::*;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+
+use notify::{RecursiveMode, Watcher};
+
+// Shaders are read from the source tree, so edits show up without a rebuild.
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/renderer/shader_src");
+
+// Watches shader_src for changes, see Renderer::reload_shaders.
+pub struct HotReload {
+    _watcher: notify::RecommendedWatcher,
+    changes: mpsc::Receiver<PathBuf>,
+    // Collects wgpu errors while a reload is in progress, None otherwise.
+    errors: Arc<Mutex<Option<Vec<String>>>>,
+}
+
+impl HotReload {
+    pub fn new(device: &wgpu::Device) -> Result<Self> {
+        let (sender, changes) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    // Editors tend to save by replacing the file, so creates count too.
+                    if event.kind.is_modify() || event.kind.is_create() {
+                        for path in event.paths {
+                            let _ = sender.send(path);
+                        }
+                    }
+                }
+            })?;
+        watcher.watch(Path::new(SHADER_DIR), RecursiveMode::NonRecursive)?;
+
+        // wgpu panics on errors by default. A broken shader shouldn't take the app down
+        //  mid edit, so errors during a reload are collected instead. Outside of reloads
+        //  we still panic like before.
+        let errors: Arc<Mutex<Option<Vec<String>>>> = Arc::new(Mutex::new(None));
+        let sink = Arc::clone(&errors);
+        device.on_uncaptured_error(move |error| match sink.lock().unwrap().as_mut() {
+            Some(errors) => errors.push(error.to_string()),
+            None => panic!("wgpu error: {}", error),
+        });
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+            errors,
+        })
+    }
+
+    // File names of the shaders that changed since the last call, without duplicates.
+    pub fn changed_shaders(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .changes
+            .try_iter()
+            .filter_map(|path| Some(path.file_name()?.to_str()?.to_owned()))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // Runs build, returning the errors wgpu raised during it if there were any.
+    pub fn try_build<T>(&self, build: impl FnOnce() -> T) -> Result<T, Vec<String>> {
+        *self.errors.lock().unwrap() = Some(vec![]);
+        let result = build();
+        let errors = self.errors.lock().unwrap().take().unwrap_or_default();
+        if errors.is_empty() {
+            Ok(result)
+        } else {
+            Err(errors)
+        }
+    }
+}
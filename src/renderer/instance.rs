@@ -1,24 +1,32 @@
 use cgmath::prelude::*;
 use cgmath::{Matrix3, Matrix4, Quaternion, Vector3};
 
+// Upper bound on the steps decompose_rotation_scale takes to find the rotation. It usually
+//  converges in a handful.
+const POLAR_ITERATIONS: usize = 20;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Instance {
     pub position: Vector3<f32>,
     pub rotation: Quaternion<f32>,
-    pub scale: f32,
+    // Per axis, applied before the rotation.
+    pub scale: Vector3<f32>,
+    // Tint multiplied into the base color, white leaves the material untouched.
+    pub color: [f32; 4],
 }
 
 impl Instance {
     pub fn to_raw(&self) -> InstanceRaw {
         let model = Matrix4::from_translation(self.position)
             * Matrix4::from(self.rotation)
-            * Matrix4::from_scale(self.scale);
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
 
         let inverse_model = model.invert().unwrap();
 
         InstanceRaw {
             model: model.into(),
             inverse_model: inverse_model.into(),
+            color: self.color,
         }
     }
 }
@@ -26,17 +34,15 @@ impl Instance {
 impl From<InstanceRaw> for Instance {
     fn from(raw: InstanceRaw) -> Self {
         let m: Matrix4<f32> = raw.model.into();
-        // Rotation scale 3x3 submatrix, transpose to row major.
-        let rs = Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate()).transpose();
-        // Assume uniform xyz scaling
-        let scale = rs.x.dot(rs.x).sqrt();
-        // Pull rotation, but don't forget to transpose rs back to column major.
-        let rotation: Quaternion<f32> = ((1.0 / scale) * rs.transpose()).into();
+        // Rotation scale 3x3 submatrix.
+        let rs = Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate());
+        let (rotation, scale) = decompose_rotation_scale(rs);
         let position = m.w.truncate();
         Instance {
             position,
             rotation,
             scale,
+            color: raw.color,
         }
     }
 }
@@ -46,9 +52,47 @@ impl Default for Instance {
         Self {
             position: Vector3::new(0.0, 0.0, 0.0),
             rotation: Quaternion::from_axis_angle(Vector3::unit_z(), cgmath::Deg(0.0)),
-            scale: 1.0,
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+// Splits a matrix into rotation * scale. This is the polar decomposition, the rotation is
+//  the one closest to the matrix. That still holds up when a non-uniformly scaled parent
+//  in the scene graph left the matrix sheared, but the shear itself can't be represented
+//  and only the diagonal of what's left after the rotation ends up as the scale.
+fn decompose_rotation_scale(m: Matrix3<f32>) -> (Quaternion<f32>, Vector3<f32>) {
+    // A mirroring matrix has no rotation close to it. Mirror x back and negate its scale.
+    let flip = if m.determinant() < 0.0 { -1.0 } else { 1.0 };
+    let m = Matrix3::from_cols(m.x * flip, m.y, m.z);
+
+    // Averaging with the inverse transpose converges on the rotation.
+    let mut u = m;
+    for _ in 0..POLAR_ITERATIONS {
+        let next = match u.invert() {
+            Some(inverse) => (u + inverse.transpose()) * 0.5,
+            // Flattened on an axis, there's no telling how it was rotated.
+            None => {
+                return (
+                    Quaternion::one(),
+                    Vector3::new(m.x.magnitude() * flip, m.y.magnitude(), m.z.magnitude()),
+                )
+            }
+        };
+        let step = next - u;
+        let converged = step.x.magnitude2() + step.y.magnitude2() + step.z.magnitude2() < 1e-12;
+        u = next;
+        if converged {
+            break;
         }
     }
+
+    let p = u.transpose() * m;
+    (
+        Quaternion::from(u),
+        Vector3::new(p.x.x * flip, p.y.y, p.z.z),
+    )
 }
 
 #[repr(C)]
@@ -56,6 +100,7 @@ impl Default for Instance {
 pub struct InstanceRaw {
     pub model: [[f32; 4]; 4],
     pub inverse_model: [[f32; 4]; 4],
+    pub color: [f32; 4],
 }
 
 impl InstanceRaw {
@@ -85,11 +130,6 @@ impl InstanceRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
                 // inverse_model starts here.
                 wgpu::VertexAttribute {
                     offset: std::mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
@@ -111,7 +151,63 @@ impl InstanceRaw {
                     shader_location: 12,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                // color
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 32]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Whatever went into to_raw comes back out of From<InstanceRaw>, also with a non-uniform
+    //  scale and a mirrored axis.
+    #[test]
+    fn raw_round_trip() {
+        let cases = [
+            (
+                Vector3::new(1.0, 2.0, 3.0),
+                Quaternion::from_axis_angle(
+                    Vector3::new(1.0, 1.0, 0.0).normalize(),
+                    cgmath::Deg(37.0),
+                ),
+                Vector3::new(1.0, 1.0, 1.0),
+            ),
+            (
+                Vector3::new(0.0, 0.0, 0.0),
+                Quaternion::from_axis_angle(
+                    Vector3::new(0.3, -1.0, 0.5).normalize(),
+                    cgmath::Deg(75.0),
+                ),
+                Vector3::new(0.5, 3.0, 1.5),
+            ),
+            (
+                Vector3::new(5.0, 5.0, 5.0),
+                Quaternion::one(),
+                Vector3::new(-1.0, 2.0, 1.0),
+            ),
+        ];
+        for &(position, rotation, scale) in &cases {
+            let instance = Instance {
+                position,
+                rotation,
+                scale,
+                ..Default::default()
+            };
+            let back = Instance::from(instance.to_raw());
+            assert!((back.position - position).magnitude() < 1e-5);
+            assert!((back.scale - scale).magnitude() < 1e-4, "{:?}", back.scale);
+            assert!(
+                back.rotation.dot(rotation).abs() > 1.0 - 1e-5,
+                "{:?}",
+                back.rotation
+            );
+        }
+    }
+}
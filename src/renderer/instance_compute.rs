@@ -0,0 +1,202 @@
+use super::instance::{Instance, InstanceRaw};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+// How instance_transforms.wgsl reads an instance, vec3s padded to vec4s.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceTrs {
+    position: [f32; 4],
+    rotation: [f32; 4],
+    scale: [f32; 4],
+    color: [f32; 4],
+}
+
+impl From<&Instance> for InstanceTrs {
+    fn from(instance: &Instance) -> Self {
+        let rotation = instance.rotation;
+        Self {
+            position: instance.position.extend(0.0).into(),
+            rotation: [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s],
+            scale: instance.scale.extend(0.0).into(),
+            color: instance.color,
+        }
+    }
+}
+
+// Computes instance matrices on the gpu, see Renderer::update_instances_gpu. Only the
+//  positions, rotations and scales are uploaded, the compute pass writes the matrices
+//  straight into the model's instance buffer.
+pub struct InstanceCompute {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // Reused between updates, grown when an update doesn't fit.
+    trs_buffer: wgpu::Buffer,
+    trs_capacity: usize,
+}
+
+impl InstanceCompute {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage_entry(0, true), storage_entry(1, false)],
+            label: Some("Instance compute bind group layout"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Instance compute pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device
+            .create_shader_module(&wgpu::include_wgsl!("shader_src/instance_transforms.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Instance compute pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let trs_capacity = WORKGROUP_SIZE as usize;
+        Self {
+            pipeline,
+            bind_group_layout,
+            trs_buffer: Self::create_trs_buffer(device, trs_capacity),
+            trs_capacity,
+        }
+    }
+
+    fn create_trs_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance trs buffer"),
+            size: (capacity * std::mem::size_of::<InstanceTrs>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Writes the matrices of instances into the first instances.len() slots of
+    //  instance_buffer, which has to have room for them.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instance_buffer: &wgpu::Buffer,
+        instances: &[Instance],
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+        if instances.len() > self.trs_capacity {
+            self.trs_buffer.destroy();
+            self.trs_capacity = instances.len().next_power_of_two();
+            self.trs_buffer = Self::create_trs_buffer(device, self.trs_capacity);
+        }
+
+        let trs: Vec<InstanceTrs> = instances.iter().map(InstanceTrs::from).collect();
+        queue.write_buffer(&self.trs_buffer, 0, bytemuck::cast_slice(&trs));
+
+        // Bound to just the instances being updated, the shader takes its length from that.
+        let binding = |buffer, item_size: usize| {
+            wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new((instances.len() * item_size) as u64),
+            })
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: binding(&self.trs_buffer, std::mem::size_of::<InstanceTrs>()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: binding(instance_buffer, std::mem::size_of::<InstanceRaw>()),
+                },
+            ],
+            label: Some("Instance compute bind group"),
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Instance compute encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Instance compute pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            let workgroups = (instances.len() as u32).div_ceil(WORKGROUP_SIZE);
+            compute_pass.dispatch(workgroups, 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::model::Model;
+    use crate::renderer::test_renderer;
+    use cgmath::{InnerSpace, Quaternion, Rotation3, Vector3};
+
+    // What the gpu writes is what Instance::to_raw computes, inverse included. More instances
+    //  than fit in one workgroup, each scaled differently per axis.
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn matches_to_raw() {
+        let mut renderer = test_renderer();
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/res/avocado");
+        let mut model = Model::load(&renderer, path.join("Avocado.glb")).unwrap();
+
+        let instances: Vec<Instance> = (0..WORKGROUP_SIZE as usize + 6)
+            .map(|i| {
+                let i = i as f32;
+                Instance {
+                    position: Vector3::new(i, -2.0 * i, 0.5),
+                    rotation: Quaternion::from_axis_angle(
+                        Vector3::new(1.0, i, 2.0).normalize(),
+                        cgmath::Deg(i * 7.0),
+                    ),
+                    scale: Vector3::new(0.5, 1.0 + i * 0.1, 3.0),
+                    color: [i / 100.0, 0.5, 1.0, 1.0],
+                }
+            })
+            .collect();
+        for _ in &instances {
+            model.add_instance();
+        }
+        model.instance_resource.sync_gpu();
+
+        renderer.update_instances_gpu(&model, &instances);
+        let data = model.instance_resource.read_back();
+        renderer.device.poll(wgpu::Maintain::Wait);
+        let data = futures::executor::block_on(data);
+
+        assert_eq!(data.len(), instances.len());
+        let expected: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        let expected: &[f32] = bytemuck::cast_slice(&expected);
+        let actual: &[f32] = bytemuck::cast_slice(&data);
+        for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+            let instance = &instances[i / 36];
+            assert!(
+                (expected - actual).abs() <= 1e-4 * expected.abs().max(1.0),
+                "{} != {} for {:?}",
+                actual,
+                expected,
+                instance
+            );
+        }
+    }
+}
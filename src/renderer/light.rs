@@ -1,7 +1,108 @@
+use serde::{Deserialize, Serialize};
+
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub struct Light {
     pub position: [f32; 3],
-    pub _padding: u32,
+    // Falls off with the square of the distance, fits in the padding after position.
+    pub intensity: f32,
+    pub color: [f32; 3],
+    // Distance past which the light doesn't reach at all, it fades out towards it. 0 is
+    //  no cutoff, which is also what scene files without a range get.
+    #[serde(default)]
+    pub range: f32,
+}
+
+impl Light {
+    // Panics on a color channel or intensity that's negative, NaN or infinite, those
+    //  turn the whole lit scene black or white.
+    pub fn new(position: [f32; 3], color: [f32; 3], intensity: f32) -> Self {
+        check_color(color);
+        check_intensity(intensity);
+        Self {
+            position,
+            intensity,
+            color,
+            range: 0.0,
+        }
+    }
+
+    // Every light is a point light for now, this is Light::new.
+    pub fn point(position: [f32; 3], color: [f32; 3], intensity: f32) -> Self {
+        Self::new(position, color, intensity)
+    }
+
+    pub fn set_color(&mut self, color: [f32; 3]) {
+        check_color(color);
+        self.color = color;
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        check_intensity(intensity);
+        self.intensity = intensity;
+    }
+
+    pub fn set_range(&mut self, range: f32) {
+        assert!(
+            range.is_finite() && range >= 0.0,
+            "Light range {} is negative or not finite",
+            range
+        );
+        self.range = range;
+    }
+}
+
+// Scales and tints the ambient light from the environment (see Renderer::set_environment),
+//  it's multiplied with the diffuse and specular parts both. The default of white at 1
+//  leaves the environment as it is, the neutral one is a faint gray.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Ambient {
     pub color: [f32; 3],
-}
\ No newline at end of file
+    pub strength: f32,
+}
+
+impl Ambient {
+    // Panics like Light::new, on a color channel or strength that's negative or
+    //  not finite.
+    pub fn new(color: [f32; 3], strength: f32) -> Self {
+        check_color(color);
+        assert!(
+            strength.is_finite() && strength >= 0.0,
+            "Ambient strength {} is negative or not finite",
+            strength
+        );
+        Self { color, strength }
+    }
+}
+
+impl Default for Ambient {
+    fn default() -> Self {
+        Self::new([1.0, 1.0, 1.0], 1.0)
+    }
+}
+
+// What the light buffer holds. The shaders' Light has the ambient fields after the
+//  light's own, at 32 bytes.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    pub light: Light,
+    pub ambient: Ambient,
+}
+
+fn check_color(color: [f32; 3]) {
+    assert!(
+        color.iter().all(|c| c.is_finite() && *c >= 0.0),
+        "Light color {:?} has a negative or non-finite channel",
+        color
+    );
+}
+
+fn check_intensity(intensity: f32) {
+    assert!(
+        intensity.is_finite() && intensity >= 0.0,
+        "Light intensity {} is negative or not finite",
+        intensity
+    );
+}
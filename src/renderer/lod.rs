@@ -0,0 +1,254 @@
+use super::culling::Visibility;
+use super::instance::InstanceRaw;
+use super::model::{IndexResource, Mesh, Model, ModelVertex};
+use super::resource::{Resource, ResourceType};
+use super::viewport::Viewport;
+use super::Renderer;
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use cgmath::{InnerSpace, MetricSpace, Point3, Vector3};
+
+// Grid cells along the longest side of the model for the first generated level, each
+//  level after that halves it.
+const FIRST_LOD_RESOLUTION: f32 = 32.0;
+
+// A coarser version of a model's meshes, used for instances at least distance away from
+//  the camera. The meshes line up with the model's, and use its materials.
+pub struct Lod {
+    pub distance: f32,
+    pub meshes: Vec<Mesh>,
+}
+
+impl Model {
+    // Adds a level per distance, each made by merging the vertices of the one before it
+    //  on a coarser grid. Replaces the levels the model had. Gltf files can't bring their
+    //  own levels yet.
+    pub fn generate_lods(&mut self, renderer: &Renderer, distances: &[f32]) {
+        let (min, max) = self.bounds;
+        let size = max - min;
+        let longest = size.x.max(size.y).max(size.z).max(f32::EPSILON);
+
+        self.lods = distances
+            .iter()
+            .enumerate()
+            .map(|(level, &distance)| {
+                let cell_size = longest / (FIRST_LOD_RESOLUTION / 2f32.powi(level as i32));
+                let meshes = self
+                    .meshes
+                    .iter()
+                    .map(|mesh| decimate(renderer, mesh, min, cell_size))
+                    .collect();
+                Lod { distance, meshes }
+            })
+            .collect();
+    }
+
+    // Changes when each level kicks in, extra distances are ignored.
+    pub fn set_lod_distances(&mut self, distances: &[f32]) {
+        for (lod, &distance) in self.lods.iter_mut().zip(distances) {
+            lod.distance = distance;
+        }
+    }
+
+    // 0 is the model's own meshes, the levels count up from there.
+    pub fn lod_level(&self, distance: f32) -> usize {
+        self.lods
+            .iter()
+            .take_while(|lod| distance >= lod.distance)
+            .count()
+    }
+
+    pub fn lod_meshes(&self, level: usize) -> &[Mesh] {
+        match level {
+            0 => &self.meshes,
+            _ => &self.lods[level - 1].meshes,
+        }
+    }
+}
+
+// Vertex clustering: vertices in the same grid cell become one at their average position,
+//  triangles that lose a corner that way are dropped. The other attributes are taken from
+//  the first vertex in the cell.
+fn decimate(renderer: &Renderer, mesh: &Mesh, origin: Vector3<f32>, cell_size: f32) -> Mesh {
+    let vertices = mesh.vertex_resource.local_slice();
+
+    let mut cells: HashMap<[i32; 3], u32> = HashMap::new();
+    let mut merged: Vec<ModelVertex> = vec![];
+    // Position and normal sums and vertex count per merged vertex.
+    let mut sums: Vec<(Vector3<f32>, Vector3<f32>, f32)> = vec![];
+    let remap: Vec<u32> = vertices
+        .iter()
+        .map(|v| {
+            let cell = (Vector3::from(v.position) - origin) / cell_size;
+            let key = [
+                cell.x.floor() as i32,
+                cell.y.floor() as i32,
+                cell.z.floor() as i32,
+            ];
+            let index = *cells.entry(key).or_insert_with(|| {
+                merged.push(*v);
+                sums.push((
+                    Vector3::new(0.0, 0.0, 0.0),
+                    Vector3::new(0.0, 0.0, 0.0),
+                    0.0,
+                ));
+                merged.len() as u32 - 1
+            });
+            let sum = &mut sums[index as usize];
+            sum.0 += Vector3::from(v.position);
+            sum.1 += Vector3::from(v.normal);
+            sum.2 += 1.0;
+            index
+        })
+        .collect();
+
+    for (vertex, (position, normal, count)) in merged.iter_mut().zip(sums) {
+        vertex.position = (position / count).into();
+        if normal.magnitude2() > 0.0 {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+
+    let mut indices = vec![];
+    for triangle in mesh.index_resource.to_u32().chunks_exact(3) {
+        let [a, b, c] = [
+            remap[triangle[0] as usize],
+            remap[triangle[1] as usize],
+            remap[triangle[2] as usize],
+        ];
+        if a != b && b != c && a != c {
+            indices.extend_from_slice(&[a, b, c]);
+        }
+    }
+
+    let device = Arc::clone(&renderer.device);
+    let queue = Arc::clone(&renderer.queue);
+    // Fewer vertices than the source, so u16 indices still fit if it had them.
+    let index_resource = match mesh.index_resource {
+        IndexResource::U16(_) => IndexResource::U16(Resource::new_with_data(
+            device.clone(),
+            queue.clone(),
+            indices.iter().map(|&i| i as u16).collect(),
+            ResourceType::Index,
+        )),
+        IndexResource::U32(_) => IndexResource::U32(Resource::new_with_data(
+            device.clone(),
+            queue.clone(),
+            indices,
+            ResourceType::Index,
+        )),
+    };
+    Mesh {
+        name: mesh.name.clone(),
+        vertex_resource: Resource::new_with_data(device, queue, merged, ResourceType::Vertex),
+        index_resource,
+        material: mesh.material,
+    }
+}
+
+// A model's sorted instances and the range of each level in them.
+type LodTarget = (Resource<InstanceRaw>, Vec<Range<u32>>);
+
+// The visible instances of models with levels, regrouped per viewport so each level is
+//  one instanced draw. They're copied in a buffer of their own, sorted by level.
+pub struct LodInstances {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    // Per viewport, per model. None for models drawn without levels this frame.
+    targets: Vec<Vec<Option<LodTarget>>>,
+}
+
+impl LodInstances {
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
+        Self {
+            device,
+            queue,
+            targets: vec![],
+        }
+    }
+
+    // Picks the level of every visible instance by its distance to the viewport's camera.
+    //  Models culled on the gpu are left alone, as is the selected one: its instances
+    //  would be renumbered, while the highlight goes by instance id.
+    pub fn prepare(
+        &mut self,
+        models: &[Model],
+        viewports: &[Viewport],
+        visibility: &[Vec<Visibility>],
+        skip_model: Option<usize>,
+    ) {
+        let LodInstances {
+            device,
+            queue,
+            targets: all_targets,
+        } = self;
+        all_targets.resize_with(viewports.len(), Vec::new);
+        for (viewport_id, viewport) in viewports.iter().enumerate() {
+            let targets = &mut all_targets[viewport_id];
+            targets.resize_with(models.len(), || None);
+
+            for (model_id, model) in models.iter().enumerate() {
+                let visible: Vec<u32> = match &visibility[viewport_id][model_id] {
+                    _ if model.lods.is_empty() || Some(model_id) == skip_model => vec![],
+                    Visibility::All => (0..model.get_num_instances() as u32).collect(),
+                    Visibility::Ranges(ranges) => ranges.iter().cloned().flatten().collect(),
+                    Visibility::Indirect(_) | Visibility::Lod { .. } => vec![],
+                };
+                if visible.is_empty() {
+                    targets[model_id] = None;
+                    continue;
+                }
+
+                let mut levels: Vec<Vec<InstanceRaw>> = vec![vec![]; model.lods.len() + 1];
+                for instance_id in visible {
+                    if let Some(raw) = model.instance_resource.local_at(instance_id as usize) {
+                        let position =
+                            Point3::new(raw.model[3][0], raw.model[3][1], raw.model[3][2]);
+                        let distance = position.distance(viewport.camera.position);
+                        levels[model.lod_level(distance)].push(raw);
+                    }
+                }
+
+                let (resource, ranges) = targets[model_id].get_or_insert_with(|| {
+                    (
+                        Resource::new_sized(
+                            Arc::clone(device),
+                            Arc::clone(queue),
+                            1,
+                            ResourceType::Instance,
+                        ),
+                        vec![],
+                    )
+                });
+                resource.clear();
+                ranges.clear();
+                for level in levels {
+                    let start = resource.get_cpu_length() as u32;
+                    let end = start + level.len() as u32;
+                    resource.add_to_buffer(level);
+                    ranges.push(start..end);
+                }
+                resource.sync_gpu();
+            }
+        }
+    }
+
+    // Swaps in the sorted instances for the models prepare regrouped.
+    pub fn apply<'a>(&'a self, viewport_id: usize, visibility: &mut [Visibility<'a>]) {
+        let targets = match self.targets.get(viewport_id) {
+            Some(targets) => targets,
+            None => return,
+        };
+        for (model_visibility, target) in visibility.iter_mut().zip(targets) {
+            if let Some((resource, ranges)) = target {
+                *model_visibility = Visibility::Lod {
+                    instances: resource.get_gpu_buffer(),
+                    levels: ranges,
+                };
+            }
+        }
+    }
+}
@@ -1,11 +1,102 @@
-use crate::renderer::texture::Texture;
+use crate::renderer::texture::{DefaultTextures, SamplerConfig, Texture};
+use std::collections::HashMap;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+// Slots of the textures in the bind group, see Renderer::default_bindgroup_layout.
+pub const DIFFUSE_SLOT: u32 = 0;
+pub const NORMAL_SLOT: u32 = 1;
+pub const EMISSIVE_SLOT: u32 = 2;
+pub const METALLIC_ROUGHNESS_SLOT: u32 = 3;
+pub const OCCLUSION_SLOT: u32 = 4;
+
+// Base color of primitives without a material, see Material::from_gltf.
+const DEFAULT_GRAY: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+// The scalar material properties, bound next to the textures in the material bind group.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color_factor: [f32; 4],
+    pub emissive_factor: [f32; 3],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // How much of the occlusion texture is applied, 0 leaves the ambient light as is.
+    pub occlusion_strength: f32,
+    // See Material::tex_coord_sets.
+    pub tex_coord_sets: u32,
+    // Non-zero for Material::unlit.
+    pub unlit: u32,
+}
+
+// The textures of one gltf file uploaded so far, by gltf texture index. Model::load makes
+//  one for the whole file, so materials (and primitives, which get a material each) that
+//  use the same texture share one upload.
+pub struct TextureCache<'a> {
+    images: &'a [gltf::image::Data],
+    textures: HashMap<usize, Arc<Texture>>,
+}
+
+impl<'a> TextureCache<'a> {
+    pub fn new(images: &'a [gltf::image::Data]) -> Self {
+        Self {
+            images,
+            textures: HashMap::new(),
+        }
+    }
+
+    // Uploads the texture's image the first time it's asked for. The label is the one of
+    //  the slot it was first used in.
+    pub fn get(
+        &mut self,
+        texture: &gltf::texture::Texture,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        sampler_config: SamplerConfig,
+    ) -> Arc<Texture> {
+        let images = self.images;
+        let texture = self.textures.entry(texture.index()).or_insert_with(|| {
+            Arc::new(Texture::from_gltf_image(
+                device,
+                queue,
+                &images[texture.source().index()],
+                Some(label),
+                sampler_config.with_gltf_sampler(&texture.sampler()),
+            ))
+        });
+        Arc::clone(texture)
+    }
+}
 
 pub struct Material {
     pub name: String,
-    pub diffuse_texture: Option<Texture>,
-    pub metallic_roughness_texture: Option<Texture>,
-    pub occlusion_texture: Option<Texture>,
-    pub normal_texture: Option<Texture>,
+    // Shared with the other materials of the file that use the same texture, see
+    //  TextureCache.
+    pub diffuse_texture: Option<Arc<Texture>>,
+    pub metallic_roughness_texture: Option<Arc<Texture>>,
+    pub occlusion_texture: Option<Arc<Texture>>,
+    pub normal_texture: Option<Arc<Texture>>,
+    pub emissive_texture: Option<Arc<Texture>>,
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    // Needs blending, so it's drawn after the opaque models, back to front.
+    pub transparent: bool,
+    // Drawn without backface culling.
+    pub double_sided: bool,
+    // Shows the base color without any lighting, from gltf's KHR_materials_unlit. Emissive
+    //  and the other textures are ignored then.
+    pub unlit: bool,
+    // Pulled towards the camera when drawn, for decals and other geometry lying on top of
+    //  another surface. It's drawn without backface culling. See Renderer::set_depth_bias.
+    pub depth_bias: bool,
+    // A bit per texture slot, set when the texture is sampled with the mesh's second uv
+    //  set (TEXCOORD_1) instead of the first.
+    pub tex_coord_sets: u32,
+    pub uniform_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
@@ -51,7 +142,7 @@ impl Material {
         }
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &layout_desc_entries.as_slice(),
+            entries: layout_desc_entries.as_slice(),
             label: Some("bind_group_layout"),
         });
 
@@ -66,6 +157,7 @@ impl Material {
 
     fn create_bind_group_with_layout(
         textures: Vec<&Texture>,
+        uniform_buffer: &wgpu::Buffer,
         device: &wgpu::Device,
         bind_group_layout: &wgpu::BindGroupLayout,
     ) -> wgpu::BindGroup {
@@ -81,8 +173,13 @@ impl Material {
                 resource: wgpu::BindingResource::Sampler(&t.sampler),
             });
         }
+        // The uniform sits right after the texture/sampler pairs, see Renderer::default_bindgroup_layout.
+        bind_group_entries.push(wgpu::BindGroupEntry {
+            binding: 10,
+            resource: uniform_buffer.as_entire_binding(),
+        });
         device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
+            layout: bind_group_layout,
             entries: &bind_group_entries,
             label: Some("bind_group"),
         })
@@ -90,78 +187,160 @@ impl Material {
 
     pub fn from_gltf(
         material: gltf::material::Material,
-        images: &Vec<gltf::image::Data>,
+        textures: &mut TextureCache,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bind_group_layout: &wgpu::BindGroupLayout,
+        sampler_config: SamplerConfig,
+        default_textures: &DefaultTextures,
     ) -> Self {
-        let mut textures = Vec::new();
+        // Primitives without a material get gltf's default one, which is a white metal.
+        //  That comes out dark with little to reflect, so they're
+        //  drawn plain gray like the meshes built in code instead.
+        if material.index().is_none() {
+            return Self::from_factors(
+                "Default material",
+                DEFAULT_GRAY,
+                device,
+                bind_group_layout,
+                default_textures,
+            );
+        }
+
+        let mut bound_textures = Vec::new();
+        let mut tex_coord_sets = 0;
 
         let pbr_mr = material.pbr_metallic_roughness();
         let diffuse_texture = if let Some(tex) = pbr_mr.base_color_texture() {
-            let img = &images[tex.texture().index()];
-            Some(Texture::from_gltf_image(
+            tex_coord_sets |= tex_coord_bit(DIFFUSE_SLOT, tex.tex_coord());
+            Some(textures.get(
+                &tex.texture(),
                 device,
                 queue,
-                img,
-                Some("diffuse_texture"),
+                "diffuse_texture",
+                sampler_config,
             ))
         } else {
             None
         };
 
         let metallic_roughness_texture = if let Some(tex) = pbr_mr.metallic_roughness_texture() {
-            let img = &images[tex.texture().index()];
-            Some(Texture::from_gltf_image(
+            tex_coord_sets |= tex_coord_bit(METALLIC_ROUGHNESS_SLOT, tex.tex_coord());
+            Some(textures.get(
+                &tex.texture(),
                 device,
                 queue,
-                img,
-                Some("metallic_roughness_texture"),
+                "metallic_roughness_texture",
+                sampler_config,
             ))
         } else {
             None
         };
 
         let normal_texture = if let Some(tex) = material.normal_texture() {
-            let img = &images[tex.texture().index()];
-            Some(Texture::from_gltf_image(
+            tex_coord_sets |= tex_coord_bit(NORMAL_SLOT, tex.tex_coord());
+            Some(textures.get(
+                &tex.texture(),
                 device,
                 queue,
-                img,
-                Some("normal_texture"),
+                "normal_texture",
+                sampler_config,
             ))
         } else {
             None
         };
 
+        let occlusion_strength = material
+            .occlusion_texture()
+            .map_or(1.0, |tex| tex.strength());
         let occlusion_texture = if let Some(tex) = material.occlusion_texture() {
-            let img = &images[tex.texture().index()];
-            Some(Texture::from_gltf_image(
+            tex_coord_sets |= tex_coord_bit(OCCLUSION_SLOT, tex.tex_coord());
+            Some(textures.get(
+                &tex.texture(),
                 device,
                 queue,
-                img,
-                Some("occlusion_texture"),
+                "occlusion_texture",
+                sampler_config,
             ))
         } else {
             None
         };
 
-        // Figure out what textures are present which we need to request binds for.
-        if let Some(ref t) = diffuse_texture {
-            textures.push(t);
-        }
+        let emissive_texture = if let Some(tex) = material.emissive_texture() {
+            tex_coord_sets |= tex_coord_bit(EMISSIVE_SLOT, tex.tex_coord());
+            Some(textures.get(
+                &tex.texture(),
+                device,
+                queue,
+                "emissive_texture",
+                sampler_config,
+            ))
+        } else {
+            None
+        };
+        let emissive_factor = material.emissive_factor();
+
+        // Every slot of the layout has to be bound, so missing textures get a shared 1x1
+        //  stand-in that leaves the factors as they are. Bound in the order of
+        //  Renderer::default_bindgroup_layout.
+        bound_textures.push(
+            diffuse_texture
+                .as_deref()
+                .unwrap_or(&default_textures.white),
+        );
+        bound_textures.push(
+            normal_texture
+                .as_deref()
+                .unwrap_or(&default_textures.normal),
+        );
+        bound_textures.push(
+            emissive_texture
+                .as_deref()
+                .unwrap_or(&default_textures.white),
+        );
+        bound_textures.push(
+            metallic_roughness_texture
+                .as_deref()
+                .unwrap_or(&default_textures.white),
+        );
+        bound_textures.push(
+            occlusion_texture
+                .as_deref()
+                .unwrap_or(&default_textures.white),
+        );
+
+        let base_color_factor = pbr_mr.base_color_factor();
+        let metallic_factor = pbr_mr.metallic_factor();
+        let roughness_factor = pbr_mr.roughness_factor();
+        let unlit = material.unlit();
+
+        let uniform_buffer = Material::create_uniform_buffer(
+            MaterialUniform {
+                base_color_factor,
+                emissive_factor,
+                metallic_factor,
+                roughness_factor,
+                occlusion_strength,
+                tex_coord_sets,
+                unlit: unlit as u32,
+            },
+            device,
+        );
 
-        if let Some(ref t) = normal_texture {
-            textures.push(t);
-        }
         //let (bind_group_layout, bind_group) = Material::create_bind_group_for_textures(textures, device);
-        let bind_group =
-            Material::create_bind_group_with_layout(textures, device, bind_group_layout);
+        let bind_group = Material::create_bind_group_with_layout(
+            bound_textures,
+            &uniform_buffer,
+            device,
+            bind_group_layout,
+        );
 
         let name = material
             .name()
             .unwrap_or("Very cool material name.")
             .to_string();
+        let transparent = material.alpha_mode() == gltf::material::AlphaMode::Blend;
+        let double_sided = material.double_sided();
 
         Self {
             name,
@@ -169,7 +348,109 @@ impl Material {
             metallic_roughness_texture,
             occlusion_texture,
             normal_texture,
+            emissive_texture,
+            base_color_factor,
+            metallic_factor,
+            roughness_factor,
+            occlusion_strength,
+            emissive_factor,
+            transparent,
+            double_sided,
+            unlit,
+            depth_bias: false,
+            tex_coord_sets,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    // A plain material without textures, for meshes built in code (see primitives.rs).
+    pub fn from_factors(
+        name: &str,
+        base_color_factor: [f32; 4],
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        default_textures: &DefaultTextures,
+    ) -> Self {
+        // Not metallic and fully rough, so it just shows its color.
+        let metallic_factor = 0.0;
+        let roughness_factor = 1.0;
+        let occlusion_strength = 1.0;
+        let emissive_factor = [0.0; 3];
+
+        let uniform_buffer = Material::create_uniform_buffer(
+            MaterialUniform {
+                base_color_factor,
+                emissive_factor,
+                metallic_factor,
+                roughness_factor,
+                occlusion_strength,
+                tex_coord_sets: 0,
+                unlit: 0,
+            },
+            device,
+        );
+
+        let bind_group = Material::create_bind_group_with_layout(
+            vec![
+                &default_textures.white,
+                &default_textures.normal,
+                &default_textures.white,
+                &default_textures.white,
+                &default_textures.white,
+            ],
+            &uniform_buffer,
+            device,
+            bind_group_layout,
+        );
+
+        Self {
+            name: name.to_string(),
+            diffuse_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            normal_texture: None,
+            emissive_texture: None,
+            base_color_factor,
+            metallic_factor,
+            roughness_factor,
+            occlusion_strength,
+            emissive_factor,
+            transparent: base_color_factor[3] < 1.0,
+            double_sided: false,
+            unlit: false,
+            depth_bias: false,
+            tex_coord_sets: 0,
+            uniform_buffer,
             bind_group,
         }
     }
+
+    // Whether any texture is sampled with the second uv set, meshes only read it then.
+    pub fn uses_tex_coords_1(&self) -> bool {
+        self.tex_coord_sets != 0
+    }
+
+    // The uv set, 0 or 1, the texture in slot is sampled with.
+    pub fn tex_coord(&self, slot: u32) -> u32 {
+        (self.tex_coord_sets >> slot) & 1
+    }
+
+    fn create_uniform_buffer(uniform: MaterialUniform, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material uniform buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+}
+
+// The bit of Material::tex_coord_sets for a texture in slot using the uv set tex_coord. Only
+//  two sets are read, textures using a later one get the first.
+fn tex_coord_bit(slot: u32, tex_coord: u32) -> u32 {
+    if tex_coord == 1 {
+        1 << slot
+    } else {
+        0
+    }
 }
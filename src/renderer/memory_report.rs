@@ -0,0 +1,57 @@
+use super::model::Model;
+
+use std::collections::HashSet;
+
+// Bytes allocated on the gpu, see Renderer::gpu_memory_report. Sizes are what was
+//  asked for, drivers round them up and add their own bookkeeping. Resources grow by 5x
+//  and only shrink when asked to, so buffers can be a lot larger than what's in them.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    // The instance buffers of the models.
+    pub instances: u64,
+    // Vertex and index buffers, levels of detail included.
+    pub meshes: u64,
+    // Material textures, counted once however many materials share them, and the
+    //  renderer's default textures.
+    pub textures: u64,
+    // The depth, hdr and G-buffer targets.
+    pub render_targets: u64,
+}
+
+impl MemoryReport {
+    // The buffers and textures of the models, render_targets is left to the renderer.
+    pub fn for_models(models: &[Model]) -> Self {
+        let mut report = Self::default();
+        let mut seen_textures = HashSet::new();
+        for model in models {
+            report.instances += model.instance_resource.get_gpu_byte_length() as u64;
+
+            let lod_meshes = model.lods.iter().flat_map(|lod| &lod.meshes);
+            for mesh in model.meshes.iter().chain(lod_meshes) {
+                report.meshes += (mesh.vertex_resource.get_gpu_byte_length()
+                    + mesh.index_resource.get_gpu_byte_length())
+                    as u64;
+            }
+
+            for material in &model.materials {
+                let textures = [
+                    &material.diffuse_texture,
+                    &material.metallic_roughness_texture,
+                    &material.occlusion_texture,
+                    &material.normal_texture,
+                    &material.emissive_texture,
+                ];
+                for texture in textures.iter().filter_map(|texture| texture.as_ref()) {
+                    if seen_textures.insert(std::sync::Arc::as_ptr(texture)) {
+                        report.textures += texture.byte_size();
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    pub fn total(&self) -> u64 {
+        self.instances + self.meshes + self.textures + self.render_targets
+    }
+}
@@ -1,35 +1,131 @@
+pub mod bloom;
+pub mod builder;
+pub mod culling;
+pub mod debug_lines;
+#[cfg(feature = "debug-ui")]
+pub mod debug_ui;
+pub mod deferred;
+pub mod draw_constants;
+pub mod environment;
+pub mod error;
+pub mod forward_pass;
+pub mod frame_stats;
+pub mod gpu_timer;
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+pub mod hot_reload;
 pub mod instance;
+pub mod instance_compute;
 pub mod light;
+pub mod lod;
 pub mod material;
+pub mod memory_report;
 pub mod model;
+pub mod particles;
+pub mod primitives;
 pub mod resource;
+pub mod ssao;
 pub mod texture;
+pub mod viewport;
 
+use std::cell::Cell;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::camera::{Camera, CameraController, Projection};
+use crate::camera::{Camera, CameraController, CameraState, KeyBindings};
+use crate::input::InputState;
 
 use resource::{Resource, ResourceType};
 
-use instance::InstanceRaw;
+use instance::{Instance, InstanceRaw};
+use instance_compute::InstanceCompute;
 use model::*;
 
-use light::Light;
-use texture::Texture;
+use bloom::Bloom;
+use culling::{Culling, GpuCulling};
+use debug_lines::DebugLines;
+#[cfg(feature = "debug-ui")]
+use debug_ui::DebugUi;
+use deferred::{Deferred, ShadingMode};
+use draw_constants::{DrawConstants, DrawConstantsBuffer};
+use environment::Environment;
+use forward_pass::RenderTarget;
+use frame_stats::FrameStats;
+use memory_report::MemoryReport;
+pub use builder::RendererBuilder;
+pub use error::RendererError;
+use gpu_timer::GpuTimer;
+use viewport::ViewportUniforms;
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+use hot_reload::HotReload;
+use light::{Ambient, Light, LightUniform};
+use lod::LodInstances;
+use texture::{DefaultTextures, DepthBias, SamplerConfig, Texture};
 
 use cgmath::prelude::*;
-use cgmath::{Matrix4, Quaternion, Vector3};
+use cgmath::{Matrix4, Quaternion, Vector3, Vector4};
 
-use winit::{event::*, window::Window};
+use winit::window::Window;
 
-use wgpu::util::DeviceExt;
+use wgpu::util::{DeviceExt, StagingBelt};
+
+// Size of the chunks the staging belt allocates for large resource uploads.
+const STAGING_BELT_CHUNK_SIZE: wgpu::BufferAddress = 1 << 20;
+// About the gamma an sRGB surface encodes for, the shaders take it out of theirs.
+const SRGB_GAMMA: f32 = 2.2;
+// Degrees per second the light orbits the y axis at, see set_light_orbit_speed.
+const LIGHT_ORBIT_SPEED: f32 = 60.0;
+
+// What the fragment shaders show instead of the lit color, see Renderer::set_debug_view.
+//  The numbers are what shader.wgsl and deferred_lighting.wgsl check debug_view against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    None = 0,
+    // World space, after normal mapping. -1..1 is shown as 0..1.
+    Normals = 1,
+    Tangents = 2,
+    // The first uv set, wrapped to 0..1.
+    Uvs = 3,
+    // Distance to the camera, half gray at 10 units.
+    Depth = 4,
+    // Base color before lighting, like Renderer::set_debug_unlit without the exposure.
+    Albedo = 5,
+    // The material's occlusion, times what SSAO found with deferred shading.
+    Occlusion = 6,
+}
+
+impl DebugView {
+    // For cycling through them with a key, wraps back to None.
+    pub fn next(self) -> Self {
+        match self {
+            DebugView::None => DebugView::Normals,
+            DebugView::Normals => DebugView::Tangents,
+            DebugView::Tangents => DebugView::Uvs,
+            DebugView::Uvs => DebugView::Depth,
+            DebugView::Depth => DebugView::Albedo,
+            DebugView::Albedo => DebugView::Occlusion,
+            DebugView::Occlusion => DebugView::None,
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Uniforms {
     view_position: [f32; 4],
     view_proj: [[f32; 4]; 4],
+    // Used by the skybox to turn screen positions back into view directions.
+    inv_view_proj: [[f32; 4]; 4],
+    // See Renderer::set_exposure and set_gamma.
+    exposure: f32,
+    gamma: f32,
+    // Where the skybox is drawn, see Texture::far_depth.
+    far_depth: f32,
+    // Non-zero draws every material unlit, see Renderer::set_debug_unlit.
+    debug_unlit: u32,
+    // A DebugView.
+    debug_view: u32,
+    // Uniform buffers are sized in multiples of 16 bytes.
+    _padding: [u32; 3],
 }
 
 impl Uniforms {
@@ -37,52 +133,190 @@ impl Uniforms {
         Self {
             view_position: [0.0; 4],
             view_proj: Matrix4::identity().into(),
+            inv_view_proj: Matrix4::identity().into(),
+            exposure: 1.0,
+            gamma: 2.2,
+            far_depth: 1.0,
+            debug_unlit: 0,
+            debug_view: DebugView::None as u32,
+            _padding: [0; 3],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_position = camera.position.to_homogeneous().into();
-        self.view_proj = (camera.projection.calculate_matrix() * camera.calculate_matrix()).into();
+        let view_proj = camera.view_projection();
+        self.view_proj = view_proj.into();
+        self.inv_view_proj = view_proj.invert().unwrap_or_else(Matrix4::identity).into();
+        self.far_depth = Texture::far_depth(camera.projection.reverse_z());
     }
 }
 
 pub struct Renderer {
-    pub surface: wgpu::Surface,
+    // The window's surface, or a texture for headless renderers. See capture_frame.
+    pub target: RenderTarget,
+    // Sizes and format of the target, offscreen ones included.
     surface_configuration: wgpu::SurfaceConfiguration,
     pub device: Arc<wgpu::Device>,
+    // What the device ended up with, to branch on optional features.
+    pub features: wgpu::Features,
     pub queue: Arc<wgpu::Queue>,
     size: winit::dpi::PhysicalSize<u32>,
     pub clear_color: wgpu::Color,
     pub render_pipeline: wgpu::RenderPipeline,
+    pub no_cull_render_pipeline: wgpu::RenderPipeline,
+    // For materials with depth_bias, see set_depth_bias.
+    pub depth_bias_render_pipeline: wgpu::RenderPipeline,
+    depth_bias: DepthBias,
+    // For transparent materials, single and double sided. See create_render_pipeline.
+    pub transparent_render_pipeline: wgpu::RenderPipeline,
+    pub transparent_no_cull_render_pipeline: wgpu::RenderPipeline,
     pub light_render_pipeline: wgpu::RenderPipeline,
+    pub skybox_pipeline: wgpu::RenderPipeline,
+    skybox_bind_group_layout: wgpu::BindGroupLayout,
+    // What the pipelines above are built from, to build them again. See set_reverse_z and
+    //  reload_shaders.
+    render_pipeline_layout: wgpu::PipelineLayout,
+    light_render_pipeline_layout: wgpu::PipelineLayout,
+    skybox_pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    light_shader: wgpu::ShaderModule,
+    skybox_shader: wgpu::ShaderModule,
+    // Whether depth goes from 1 at the near plane to 0 at the far plane, see set_reverse_z.
+    reverse_z: bool,
+    pub skybox: Option<(Texture, wgpu::BindGroup)>,
+    pub deferred: Deferred,
+    shading_mode: ShadingMode,
+    // Also in uniforms, this is what debug_view() returns.
+    debug_view: DebugView,
+    pub debug_lines: DebugLines,
+    // Draws the scene's particle systems, see particles.rs.
+    pub particle_pipeline: wgpu::RenderPipeline,
+    // Whether draw_scene draws the bounding boxes of all instances.
+    pub debug_bounds: bool,
+    // Drives whichever camera is passed to update, usually the scene's active one.
     camera_controller: CameraController,
-    pub camera: Camera,
     pub uniforms: Uniforms,
+    // Whether the surface encodes sRGB itself, see set_gamma.
+    srgb_surface: bool,
     uniform_buffer: wgpu::Buffer,
     pub uniform_bind_group: wgpu::BindGroup,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    // One per viewport drawn last frame, grown as needed by draw_scene_viewports.
+    viewport_uniforms: Vec<ViewportUniforms>,
     pub depth_texture: texture::Texture,
+    // The scene is drawn here and then copied to the surface by bloom.
+    pub hdr_texture: Texture,
+    // With MSAA the scene is drawn here instead and resolved into hdr_texture, None
+    //  without. See RendererBuilder::sample_count.
+    pub msaa_texture: Option<Texture>,
+    // Of depth_texture, msaa_texture and every pipeline drawing into them.
+    sample_count: u32,
+    pub bloom: Bloom,
     light: Light,
+    // Degrees per second around the y axis, 0 keeps the light still.
+    light_orbit_speed: f32,
+    // Uploaded with the light, see set_ambient.
+    ambient: Ambient,
     light_buffer: wgpu::Buffer,
+    light_bind_group_layout: wgpu::BindGroupLayout,
+    // Ambient light, bound with the light. See set_environment.
+    environment: Environment,
     pub light_bind_group: wgpu::BindGroup,
-    mouse_pressed: bool,
     pub default_bind_group_layout: wgpu::BindGroupLayout,
+    // Sampler settings used for textures of models loaded after it is set.
+    pub texture_sampler_config: SamplerConfig,
+    // Stand-ins for textures materials don't have.
+    pub default_textures: DefaultTextures,
+    downlevel_capabilities: wgpu::DownlevelCapabilities,
+    pub staging_belt: StagingBelt,
+    // None when timestamp queries aren't supported.
+    pub gpu_timer: Option<GpuTimer>,
+    // Counted while drawing, a Cell so the draw helpers only need the renderer shared.
+    frame_stats: Cell<FrameStats>,
+    // Off unless turned on with set_gpu_culling.
+    pub(crate) culling: Culling,
+    // For models with levels of detail, see Model::generate_lods.
+    pub(crate) lod_instances: LodInstances,
+    // None without compute shader support, see update_instances_gpu.
+    instance_compute: Option<InstanceCompute>,
+    // Only there without push constant support, see draw_constants.rs.
+    pub draw_constants_buffer: Option<DrawConstantsBuffer>,
+    // The highlighted (model, instance).
+    pub selection: Option<(usize, usize)>,
+    #[cfg(all(feature = "hot-reload", debug_assertions))]
+    hot_reload: Option<HotReload>,
+    // Drawn over the frame by draw_scene, see debug_ui.rs.
+    #[cfg(feature = "debug-ui")]
+    pub debug_ui: DebugUi,
+    // Drives the staging belt's recall futures.
+    pub local_pool: futures::executor::LocalPool,
 }
 
 impl Renderer {
-    pub async fn new(window: &Window) -> Self {
-        let size = window.inner_size();
+    // With the defaults, see RendererBuilder to change them.
+    pub async fn new(window: &Window) -> Result<Self, RendererError> {
+        RendererBuilder::new().build(window).await
+    }
+
+    // Draws to a texture of the given size instead of a window, for tests and rendering
+    //  without a display. See capture_frame.
+    pub async fn new_headless(width: u32, height: u32) -> Result<Self, RendererError> {
+        RendererBuilder::new().build_headless(width, height).await
+    }
+
+    // Without a window the renderer draws to an offscreen texture of size.
+    async fn from_builder(
+        window: Option<&Window>,
+        size: winit::dpi::PhysicalSize<u32>,
+        builder: RendererBuilder,
+    ) -> Result<Self, RendererError> {
+        let RendererBuilder {
+            power_preference,
+            present_mode,
+            clear_color,
+            features,
+            limits,
+            light,
+            sample_count,
+        } = builder;
 
-        // Handle to gpu
-        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
-        let surface = unsafe { instance.create_surface(window) };
+        // Handle to gpu. Headless, WGPU_BACKEND can pick one, e.g. gl on machines without
+        //  a Vulkan driver.
+        let backends = match window {
+            Some(_) => wgpu::Backends::PRIMARY,
+            None => wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all),
+        };
+        let instance = wgpu::Instance::new(backends);
+        let surface = window.map(|window| unsafe { instance.create_surface(window) });
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
+                power_preference,
+                compatible_surface: surface.as_ref(),
                 force_fallback_adapter: false,
             })
             .await
-            .expect("Failed to find an appropriate adapter.");
+            .ok_or(RendererError::NoAdapter)?;
+        let downlevel_capabilities = adapter.get_downlevel_properties();
+
+        let missing_features = features - adapter.features();
+        if !missing_features.is_empty() {
+            return Err(RendererError::MissingFeatures {
+                adapter: adapter.get_info().name,
+                missing: missing_features,
+            });
+        }
+
+        // On top of that timestamp queries if we can have them, see GpuTimer. Same for push
+        //  constants, see draw_constants.rs.
+        let push_constants = features.contains(wgpu::Features::PUSH_CONSTANTS)
+            || draw_constants::push_constants_supported(&adapter);
+        let mut features = features | (adapter.features() & wgpu::Features::TIMESTAMP_QUERY);
+        let mut limits = limits;
+        if push_constants {
+            features |= wgpu::Features::PUSH_CONSTANTS;
+            limits.max_push_constant_size = limits.max_push_constant_size.max(DrawConstants::SIZE);
+        }
 
         // Use the adapter to request device and queue.
         //  You can view available features through device.features()
@@ -90,43 +324,60 @@ impl Renderer {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("Device descriptor"),
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
+                    features,
+                    limits,
                 },
                 None,
             )
-            .await
-            .expect("Failed to create device.");
+            .await?;
+
+        // Render to whatever the surface prefers. That's Bgra8UnormSrgb on most native
+        //  platforms, but browsers may only offer a format without sRGB, see set_gamma.
+        let surface_format = match &surface {
+            Some(surface) => match surface.get_preferred_format(&adapter) {
+                Some(format) => format,
+                None => {
+                    return Err(RendererError::UnsupportedSurface {
+                        adapter: adapter.get_info().name,
+                    })
+                }
+            },
+            None => Texture::OFFSCREEN_FORMAT,
+        };
+        let srgb_surface = surface_format.describe().srgb;
+        log::info!("Presenting to a {:?} surface", surface_format);
 
         let surface_configuration = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode,
         };
 
         // Use the surface confige to initialize the surface.
-        surface.configure(&device, &surface_configuration);
+        let target = match surface {
+            Some(surface) => {
+                surface.configure(&device, &surface_configuration);
+                RenderTarget::Surface(surface)
+            }
+            None => RenderTarget::Offscreen(Texture::create_offscreen_target(
+                &device,
+                &surface_configuration,
+                "offscreen_target",
+            )),
+        };
 
         // Wrap these bois in an arc so we can share 'em across threads.
         let device = Arc::new(device);
         let queue = Arc::new(queue);
 
-        // Define the camera.
+        // The cameras themselves live in the scene.
         let camera_controller = CameraController::new(4.0, 0.4);
-        let mut camera = Camera::new((0.0, 5.0, 10.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
-        let projection = Projection::new(
-            surface_configuration.width,
-            surface_configuration.height,
-            cgmath::Deg(45.0),
-            0.1,
-            100.0,
-        );
-        camera.projection = projection;
 
         // Uniform definitons start here
-        let uniforms = Uniforms::new();
+        let mut uniforms = Uniforms::new();
+        uniforms.gamma = Self::shader_gamma(srgb_surface, SRGB_GAMMA);
         let _uniform_resource = Resource::new_with_data(
             Arc::clone(&device),
             Arc::clone(&queue),
@@ -168,22 +419,23 @@ impl Renderer {
             &device,
             &surface_configuration,
             "depth_texture",
+            sample_count,
         );
 
-        let clear_color = wgpu::Color::BLACK;
-
-        // Light stuff starts here.
-        // TODO: move light stuff to scene, add support for multiple lights and figure out
-        //  How to set up a deffered pipeline. <-- will take some research.
-        let light = Light {
-            position: [2.0, 2.0, 2.0],
-            _padding: 0,
-            color: [1.0, 1.0, 1.0],
-        };
+        let hdr_texture =
+            Texture::create_hdr_texture(&device, &surface_configuration, "hdr_texture", 1);
+        let msaa_texture =
+            Self::create_msaa_texture(&device, &surface_configuration, sample_count);
 
+        // Light stuff starts here, the light itself comes from the builder.
+        // TODO: move light stuff to scene and add support for multiple lights. Deferred
+        //  shading is there for when there are many, see deferred.rs.
         let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Light vertex buffer"),
-            contents: bytemuck::cast_slice(&[light]),
+            contents: bytemuck::cast_slice(&[LightUniform {
+                light,
+                ambient: Ambient::default(),
+            }]),
             usage: wgpu::BufferUsages::UNIFORM
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::VERTEX,
@@ -191,124 +443,402 @@ impl Renderer {
 
         let light_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
+                entries: &[
+                    &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }][..],
+                    &Environment::bind_group_layout_entries(),
+                ]
+                .concat(),
                 label: None,
             });
 
-        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &light_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: light_buffer.as_entire_binding(),
-            }],
-            label: None,
+        let environment = Environment::neutral(&device, &queue);
+        let light_bind_group = Self::create_light_bind_group(
+            &device,
+            &light_bind_group_layout,
+            &light_buffer,
+            &environment,
+        );
+
+        // Load the shaders, they're compiled from wgsl at runtime. Set up render pipeline.
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("shader.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                draw_constants::shader_source(include_str!("shader_src/shader.wgsl"), push_constants)
+                    .into(),
+            ),
         });
 
-        // Load precompiled shaders (see build.rs), set up render pipeline.
-        let vs_module =
-            device.create_shader_module(&wgpu::include_spirv!("shader_src/shader.vert.spv"));
-        let fs_module =
-            device.create_shader_module(&wgpu::include_spirv!("shader_src/shader.frag.spv"));
+        // Per draw constants go in push constants, or in a 4th bind group if we can't have
+        //  those.
+        let draw_constants_buffer = if push_constants {
+            None
+        } else {
+            Some(DrawConstantsBuffer::new(&device))
+        };
+        let draw_constants_ranges = [wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::VERTEX,
+            range: 0..DrawConstants::SIZE,
+        }];
 
         // Get the pre-defined bindgroup layouts. Not sure if pre-defining is the way, but so far so good.
         let default_bind_group_layout = Self::default_bindgroup_layout(&device);
         let mut bind_group_layouts = vec![&default_bind_group_layout];
         bind_group_layouts.push(&uniform_bind_group_layout);
         bind_group_layouts.push(&light_bind_group_layout);
+        if let Some(draw_constants_buffer) = &draw_constants_buffer {
+            bind_group_layouts.push(&draw_constants_buffer.bind_group_layout);
+        }
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
                 bind_group_layouts: bind_group_layouts.as_slice(),
-                push_constant_ranges: &[],
+                push_constant_ranges: if push_constants {
+                    &draw_constants_ranges
+                } else {
+                    &[]
+                },
             });
 
         let render_pipeline = Self::create_render_pipeline(
             &device,
             &render_pipeline_layout,
-            surface_configuration.format,
+            Texture::HDR_FORMAT,
             Some(texture::Texture::DEPTH_FORMAT),
-            &vs_module,
-            &fs_module,
+            sample_count,
+            &shader,
             Some("Textured mesh render pipeline"),
+            Some(wgpu::Face::Back),
+            false,
+            wgpu::DepthBiasState::default(),
+            false,
         );
 
-        let light_render_pipeline = {
-            let light_render_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Light Pipeline Layout"),
-                    bind_group_layouts: &[&uniform_bind_group_layout, &light_bind_group_layout],
-                    push_constant_ranges: &[],
-                });
+        // Same thing without backface culling, for double sided materials.
+        let no_cull_render_pipeline = Self::create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(texture::Texture::DEPTH_FORMAT),
+            sample_count,
+            &shader,
+            Some("Double sided mesh render pipeline"),
+            None,
+            false,
+            wgpu::DepthBiasState::default(),
+            false,
+        );
 
-            let light_vs_module =
-                device.create_shader_module(&wgpu::include_spirv!("shader_src/light.vert.spv"));
-            let light_fs_module =
-                device.create_shader_module(&wgpu::include_spirv!("shader_src/light.frag.spv"));
+        // For materials with depth_bias, see set_depth_bias.
+        let depth_bias = DepthBias::default();
+        let depth_bias_render_pipeline = Self::create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(texture::Texture::DEPTH_FORMAT),
+            sample_count,
+            &shader,
+            Some("Depth biased mesh render pipeline"),
+            None,
+            false,
+            depth_bias.state(false),
+            false,
+        );
 
-            Self::create_render_pipeline(
-                &device,
-                &light_render_pipeline_layout,
-                surface_configuration.format,
-                Some(Texture::DEPTH_FORMAT),
-                &light_vs_module,
-                &light_fs_module,
-                Some("Light render pipeline"),
-            )
-        };
+        // Transparent materials, drawn after the rest.
+        let transparent_render_pipeline = Self::create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(texture::Texture::DEPTH_FORMAT),
+            sample_count,
+            &shader,
+            Some("Transparent mesh render pipeline"),
+            Some(wgpu::Face::Back),
+            false,
+            wgpu::DepthBiasState::default(),
+            true,
+        );
+        let transparent_no_cull_render_pipeline = Self::create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(texture::Texture::DEPTH_FORMAT),
+            sample_count,
+            &shader,
+            Some("Double sided transparent mesh render pipeline"),
+            None,
+            false,
+            wgpu::DepthBiasState::default(),
+            true,
+        );
 
-        // Instancing stuff starts here.
-        //const NUM_INSTANCES_PER_ROW: u32 = 50;
-        //const _NUM_INSTANCES: u32 = NUM_INSTANCES_PER_ROW * NUM_INSTANCES_PER_ROW;
-        //const INSTANCE_DISPLACEMENT: Vector3<f32> = Vector3::new(NUM_INSTANCES_PER_ROW as f32 * 0.5, 0.0, NUM_INSTANCES_PER_ROW as f32 * 0.5);
-        //let instances = (0..NUM_INSTANCES_PER_ROW).flat_map(|z| {
-        //    (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-        //        let position = Vector3 { x: x as f32, y: 0.0, z: z as f32} - INSTANCE_DISPLACEMENT;
-        //        let rotation = if position.is_zero() {
-        //            Quaternion::from_axis_angle(Vector3::unit_z(), cgmath::Deg(0.0))
-        //        } else {
-        //            cgmath::Quaternion::from_axis_angle(position.clone().normalize(), cgmath::Deg(45.0))
-        //        };
-        //        Instance {
-        //            position, rotation,
-        //        }
-        //    })
-        //}).collect::<Vec<_>>();
-
-        //let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        let light_render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout, &light_bind_group_layout],
+                push_constant_ranges: &[],
+            });
 
-        Self {
-            surface,
+        let light_shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/light.wgsl"));
+
+        let light_render_pipeline = Self::create_render_pipeline(
+            &device,
+            &light_render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            sample_count,
+            &light_shader,
+            Some("Light render pipeline"),
+            Some(wgpu::Face::Back),
+            false,
+            wgpu::DepthBiasState::default(),
+            false,
+        );
+
+        let deferred = Deferred::new(
+            &device,
+            &surface_configuration,
+            &render_pipeline_layout,
+            &uniform_bind_group_layout,
+            &light_bind_group_layout,
+            Texture::DEPTH_FORMAT,
+            sample_count,
+            push_constants,
+        );
+
+        let bloom = Bloom::new(&device, &surface_configuration, &hdr_texture.view);
+
+        // Rebuilds the pipelines above when their shaders are edited, see reload_shaders.
+        #[cfg(all(feature = "hot-reload", debug_assertions))]
+        let hot_reload = HotReload::new(&device)
+            .map_err(|e| log::warn!("Shader hot reloading is unavailable: {:?}", e))
+            .ok();
+
+        #[cfg(feature = "debug-ui")]
+        let debug_ui = DebugUi::new(
+            &device,
+            surface_configuration.format,
+            window.map_or(1.0, |window| window.scale_factor()),
+        );
+
+        let default_textures = DefaultTextures::new(&device, &queue);
+
+        let skybox_bind_group_layout = Self::skybox_bindgroup_layout(&device);
+        let skybox_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout, &skybox_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let skybox_shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/skybox.wgsl"));
+        let skybox_pipeline = Self::create_skybox_pipeline(
+            &device,
+            &skybox_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Texture::DEPTH_FORMAT,
+            sample_count,
+            &skybox_shader,
+            false,
+        );
+
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        let lod_instances = LodInstances::new(Arc::clone(&device), Arc::clone(&queue));
+        let instance_compute = if downlevel_capabilities
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && device.limits().max_storage_buffers_per_shader_stage >= 2
+        {
+            Some(InstanceCompute::new(&device))
+        } else {
+            None
+        };
+        let debug_lines = DebugLines::new(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &uniform_bind_group_layout,
+            Texture::HDR_FORMAT,
+            Texture::DEPTH_FORMAT,
+            sample_count,
+        );
+        let particle_pipeline = particles::create_pipeline(
+            &device,
+            &uniform_bind_group_layout,
+            Texture::HDR_FORMAT,
+            Texture::DEPTH_FORMAT,
+            sample_count,
+            false,
+        );
+
+        Ok(Self {
+            target,
+            features: device.features(),
             device,
             queue,
             surface_configuration,
             size,
             clear_color,
             render_pipeline,
+            no_cull_render_pipeline,
+            depth_bias_render_pipeline,
+            depth_bias,
+            transparent_render_pipeline,
+            transparent_no_cull_render_pipeline,
             light_render_pipeline,
-            camera,
+            skybox_pipeline,
+            skybox_bind_group_layout,
+            skybox: None,
+            render_pipeline_layout,
+            light_render_pipeline_layout,
+            skybox_pipeline_layout,
+            shader,
+            light_shader,
+            skybox_shader,
+            reverse_z: false,
+            deferred,
+            shading_mode: ShadingMode::Forward,
+            debug_view: DebugView::None,
+            debug_lines,
+            particle_pipeline,
+            debug_bounds: false,
             camera_controller,
             uniforms,
+            srgb_surface,
             uniform_buffer,
             uniform_bind_group,
+            uniform_bind_group_layout,
+            viewport_uniforms: vec![],
             depth_texture,
+            hdr_texture,
+            msaa_texture,
+            sample_count,
+            bloom,
             light,
+            light_orbit_speed: LIGHT_ORBIT_SPEED,
+            ambient: Ambient::default(),
             light_buffer,
+            light_bind_group_layout,
+            environment,
             light_bind_group,
-            mouse_pressed: false,
             default_bind_group_layout,
+            texture_sampler_config: SamplerConfig::default(),
+            default_textures,
+            downlevel_capabilities,
+            staging_belt: StagingBelt::new(STAGING_BELT_CHUNK_SIZE),
+            gpu_timer,
+            frame_stats: Cell::new(FrameStats::default()),
+            culling: Culling::Off,
+            lod_instances,
+            instance_compute,
+            draw_constants_buffer,
+            selection: None,
+            #[cfg(all(feature = "hot-reload", debug_assertions))]
+            hot_reload,
+            #[cfg(feature = "debug-ui")]
+            debug_ui,
+            local_pool: futures::executor::LocalPool::new(),
+        })
+    }
+
+    // Turns on anisotropic filtering for textures of models loaded from here on out,
+    //  0 or 1 turns it off again. Errors if the adapter can't do the requested amount.
+    pub fn set_anisotropy(&mut self, anisotropy_clamp: u8) -> anyhow::Result<()> {
+        let config = self.texture_sampler_config.with_anisotropy(anisotropy_clamp);
+        config.check_support(&self.downlevel_capabilities)?;
+        self.texture_sampler_config = config;
+        Ok(())
+    }
+
+    // Sets the cubemap drawn behind the scene, None goes back to the clear color.
+    pub fn set_skybox(&mut self, cubemap: Option<Texture>) {
+        self.skybox = cubemap.map(|cubemap| {
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.skybox_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&cubemap.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                    },
+                ],
+                label: Some("skybox_bind_group"),
+            });
+            (cubemap, bind_group)
+        });
+    }
+
+    // Lights the scene with a cubemap, usually the one of the skybox. None goes back to
+    //  a neutral gray. Prefilters the cubemap first, so don't call this every frame.
+    pub fn set_environment(&mut self, cubemap: Option<&Texture>) {
+        self.environment = match cubemap {
+            Some(cubemap) => Environment::from_cubemap(&self.device, &self.queue, cubemap),
+            None => Environment::neutral(&self.device, &self.queue),
+        };
+        self.light_bind_group = Self::create_light_bind_group(
+            &self.device,
+            &self.light_bind_group_layout,
+            &self.light_buffer,
+            &self.environment,
+        );
+    }
+
+    fn create_light_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        light_buffer: &wgpu::Buffer,
+        environment: &Environment,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                }][..],
+                &environment.bind_group_entries(),
+            ]
+            .concat(),
+            label: None,
+        })
+    }
+
+    // The multisampled HDR target, only there with a sample_count over 1.
+    fn create_msaa_texture(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Option<Texture> {
+        if sample_count > 1 {
+            Some(Texture::create_hdr_texture(
+                device,
+                surface_configuration,
+                "msaa_texture",
+                sample_count,
+            ))
+        } else {
+            None
         }
     }
 
+    // Samples per pixel the scene is drawn with, 1 without MSAA.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
     pub fn resize(&mut self, new_size: Option<winit::dpi::PhysicalSize<u32>>) {
         // This fn also deals with "lost" swap chain, so pick the previously known
         //  size as a default value if we don't provide any.
@@ -316,6 +846,13 @@ impl Renderer {
 
         // Update the new size to state and the swapchain descriptor.
         self.size = ns;
+
+        // A minimized window reports a size of 0, which isn't a valid surface size. Keep the
+        //  old surface around until we get a real size again.
+        if self.is_minimized() {
+            return;
+        }
+
         self.surface_configuration.width = ns.width;
         self.surface_configuration.height = ns.height;
 
@@ -324,63 +861,585 @@ impl Renderer {
             &self.device,
             &self.surface_configuration,
             "depth_texture",
+            self.sample_count,
+        );
+
+        self.hdr_texture = Texture::create_hdr_texture(
+            &self.device,
+            &self.surface_configuration,
+            "hdr_texture",
+            1,
         );
+        self.msaa_texture =
+            Self::create_msaa_texture(&self.device, &self.surface_configuration, self.sample_count);
 
-        self.surface
-            .configure(&self.device, &self.surface_configuration);
+        match &mut self.target {
+            RenderTarget::Surface(surface) => {
+                surface.configure(&self.device, &self.surface_configuration)
+            }
+            RenderTarget::Offscreen(texture) => {
+                *texture = Texture::create_offscreen_target(
+                    &self.device,
+                    &self.surface_configuration,
+                    "offscreen_target",
+                )
+            }
+        }
+        self.deferred
+            .resize(&self.device, &self.surface_configuration);
+        self.bloom.resize(
+            &self.device,
+            &self.queue,
+            &self.surface_configuration,
+            &self.hdr_texture.view,
+        );
+    }
 
-        // Update the projection
-        self.camera.projection.resize(ns.width, ns.height);
+    // Switches between lighting every mesh as it's drawn and lighting the G-buffer once,
+    //  see deferred.rs.
+    pub fn set_shading_mode(&mut self, shading_mode: ShadingMode) {
+        self.shading_mode = shading_mode;
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        // I'd merge this to Renderer::input_mouse_movement because splitting the mouse and keyboard handling
-        //  makes no sense, but unfortunately: https://github.com/rust-windowing/winit/issues/1470
-        match event {
-            WindowEvent::KeyboardInput { input, .. } => self
-                .camera_controller
-                .process_keyboard(input.virtual_keycode.unwrap(), input.state),
-            _ => false,
+    pub fn shading_mode(&self) -> ShadingMode {
+        self.shading_mode
+    }
+
+    // Screen space ambient occlusion, only applies to deferred shading. radius is in world
+    //  units, intensity scales how dark fully occluded spots get.
+    pub fn set_ssao(&mut self, enabled: bool, radius: f32, intensity: f32) {
+        self.deferred.ssao.set(&self.queue, enabled, radius, intensity);
+    }
+
+    // Makes pixels brighter than threshold glow, like emissive materials. intensity scales
+    //  how much of the glow is added to the scene.
+    pub fn set_bloom(&mut self, enabled: bool, threshold: f32, intensity: f32) {
+        self.bloom.set(&self.queue, enabled, threshold, intensity);
+    }
+
+    // Scales the lit color before gamma is applied, above 1 brightens the scene.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.uniforms.exposure = exposure;
+    }
+
+    // Shows the base color of every material without any lighting, like unlit materials
+    //  (see Material::unlit). For checking textures and uvs.
+    pub fn set_debug_unlit(&mut self, unlit: bool) {
+        self.uniforms.debug_unlit = unlit as u32;
+    }
+
+    pub fn debug_unlit(&self) -> bool {
+        self.uniforms.debug_unlit != 0
+    }
+
+    // Shows one of the inputs to the lighting instead of the lit scene, for both forward
+    //  and deferred shading. See DebugView.
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        self.debug_view = view;
+        self.uniforms.debug_view = view as u32;
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+
+    // The display gamma the scene is encoded for, 2.2 by default.
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.uniforms.gamma = Self::shader_gamma(self.srgb_surface, gamma);
+    }
+
+    // The shaders assume the surface does the sRGB part of the encoding and only apply
+    //  what's left of the gamma, 2.2 / gamma. Without an sRGB surface they have to do all
+    //  of it, 1 / gamma, which is what the same formula gives for a 2.2 times larger one.
+    fn shader_gamma(srgb_surface: bool, gamma: f32) -> f32 {
+        if srgb_surface {
+            gamma
+        } else {
+            gamma * SRGB_GAMMA
         }
     }
 
-    pub fn input_mouse_movement(&mut self, event: &DeviceEvent) -> bool {
-        // See State::input why this is seperate.
-        match event {
-            DeviceEvent::MouseMotion { delta } => {
-                if self.mouse_pressed {
-                    self.camera_controller.process_mouse(delta.0, delta.1);
+    pub fn light(&self) -> Light {
+        self.light
+    }
+
+    // Moves the light, it keeps orbiting the y axis from there unless the orbit speed is 0.
+    pub fn set_light_position(&mut self, position: [f32; 3]) {
+        self.light.position = position;
+    }
+
+    pub fn set_light_color(&mut self, color: [f32; 3]) {
+        self.light.set_color(color);
+    }
+
+    // The light's brightness at a distance of 1, see light.rs.
+    pub fn set_light_intensity(&mut self, intensity: f32) {
+        self.light.set_intensity(intensity);
+    }
+
+    // Cuts the light off past range, 0 lets it reach everything. See light.rs.
+    pub fn set_light_range(&mut self, range: f32) {
+        self.light.set_range(range);
+    }
+
+    // In degrees per second around the y axis, negative goes the other way. 0 freezes the
+    //  light for looking at static shading, or for moving it with set_light_position.
+    pub fn set_light_orbit_speed(&mut self, degrees_per_second: f32) {
+        self.light_orbit_speed = degrees_per_second;
+    }
+
+    pub fn light_orbit_speed(&self) -> f32 {
+        self.light_orbit_speed
+    }
+
+    // Multiplies the light from the environment, see light::Ambient. A strength of 0 leaves
+    //  surfaces facing away from the light black.
+    pub fn set_ambient(&mut self, color: [f32; 3], strength: f32) {
+        self.ambient = Ambient::new(color, strength);
+    }
+
+    pub fn ambient(&self) -> Ambient {
+        self.ambient
+    }
+
+    // For tweaking how the camera moves, e.g. its speed.
+    pub fn camera_controller_mut(&mut self) -> &mut CameraController {
+        &mut self.camera_controller
+    }
+
+    pub fn set_key_bindings(&mut self, bindings: KeyBindings) {
+        self.camera_controller.set_bindings(bindings);
+    }
+
+    pub fn animate_camera_to(&mut self, target: CameraState, duration: Duration) {
+        self.camera_controller.animate_to(target, duration);
+    }
+
+    pub fn frame_camera(
+        &mut self,
+        camera: &Camera,
+        bounds: (Vector3<f32>, Vector3<f32>),
+        duration: Duration,
+    ) {
+        self.camera_controller.frame(camera, bounds, duration);
+    }
+
+    // Draws the world space bounding box of every instance, green when it's in view and red
+    //  when it isn't.
+    pub fn draw_debug_bounds(&mut self, enabled: bool) {
+        self.debug_bounds = enabled;
+    }
+
+    // Rebuilds the pipelines whose shaders changed on disk. A shader that fails to compile
+    //  is reported and the pipeline keeps using the previous version.
+    #[cfg(all(feature = "hot-reload", debug_assertions))]
+    pub fn reload_shaders(&mut self) {
+        let hot_reload = match &self.hot_reload {
+            Some(hot_reload) => hot_reload,
+            None => return,
+        };
+
+        for name in hot_reload.changed_shaders() {
+            // The pipelines built from the shader, with their layout, label, cull mode, depth
+            //  bias and whether they're for transparent materials.
+            let targets = match name.as_str() {
+                "shader.wgsl" => vec![
+                    (
+                        &mut self.render_pipeline,
+                        &self.render_pipeline_layout,
+                        "Textured mesh render pipeline",
+                        Some(wgpu::Face::Back),
+                        wgpu::DepthBiasState::default(),
+                        false,
+                    ),
+                    (
+                        &mut self.no_cull_render_pipeline,
+                        &self.render_pipeline_layout,
+                        "Double sided mesh render pipeline",
+                        None,
+                        wgpu::DepthBiasState::default(),
+                        false,
+                    ),
+                    (
+                        &mut self.depth_bias_render_pipeline,
+                        &self.render_pipeline_layout,
+                        "Depth biased mesh render pipeline",
+                        None,
+                        self.depth_bias.state(self.reverse_z),
+                        false,
+                    ),
+                    (
+                        &mut self.transparent_render_pipeline,
+                        &self.render_pipeline_layout,
+                        "Transparent mesh render pipeline",
+                        Some(wgpu::Face::Back),
+                        wgpu::DepthBiasState::default(),
+                        true,
+                    ),
+                    (
+                        &mut self.transparent_no_cull_render_pipeline,
+                        &self.render_pipeline_layout,
+                        "Double sided transparent mesh render pipeline",
+                        None,
+                        wgpu::DepthBiasState::default(),
+                        true,
+                    ),
+                ],
+                "light.wgsl" => vec![(
+                    &mut self.light_render_pipeline,
+                    &self.light_render_pipeline_layout,
+                    "Light render pipeline",
+                    Some(wgpu::Face::Back),
+                    wgpu::DepthBiasState::default(),
+                    false,
+                )],
+                _ => continue,
+            };
+
+            let path = std::path::Path::new(hot_reload::SHADER_DIR).join(&name);
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) if name == "shader.wgsl" => draw_constants::shader_source(
+                    &source,
+                    self.draw_constants_buffer.is_none(),
+                ),
+                Ok(source) => source,
+                Err(e) => {
+                    log::error!("Failed to read {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            let device = &self.device;
+            let sample_count = self.sample_count;
+            let reverse_z = self.reverse_z;
+            let result = hot_reload.try_build(|| {
+                let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                    label: Some(&name),
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                });
+                let pipelines = targets
+                    .iter()
+                    .map(|(_, layout, label, cull_mode, depth_bias, transparent)| {
+                        Self::create_render_pipeline(
+                            device,
+                            layout,
+                            Texture::HDR_FORMAT,
+                            Some(Texture::DEPTH_FORMAT),
+                            sample_count,
+                            &shader,
+                            Some(label),
+                            *cull_mode,
+                            reverse_z,
+                            *depth_bias,
+                            *transparent,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                (shader, pipelines)
+            });
+            match result {
+                Ok((shader, new_pipelines)) => {
+                    for ((pipeline, ..), new_pipeline) in targets.into_iter().zip(new_pipelines) {
+                        *pipeline = new_pipeline;
+                    }
+                    // Kept for set_reverse_z, which builds the pipelines again.
+                    if name == "light.wgsl" {
+                        self.light_shader = shader;
+                    } else {
+                        self.shader = shader;
+                    }
+                    log::info!("Reloaded {}", name);
+                }
+                Err(errors) => {
+                    log::error!("Failed to reload {}, keeping the old pipelines:", name);
+                    for error in errors {
+                        log::error!("{}", error);
+                    }
                 }
-                true
-            }
-            DeviceEvent::MouseWheel { delta, .. } => {
-                self.camera_controller.process_scroll(&delta);
-                true
             }
-            DeviceEvent::Button {
-                button: 1, state, ..
-            } => {
-                self.mouse_pressed = *state == ElementState::Pressed;
-                true
+        }
+    }
+
+    // Highlights an instance, given as (model, instance). None clears it.
+    pub fn set_selection(&mut self, selection: Option<(usize, usize)>) {
+        self.selection = selection;
+    }
+
+    // How long the last measured scene pass took on the gpu. None without timestamp query
+    //  support, or before the first measurement came back.
+    pub fn last_gpu_frame_time(&self) -> Option<Duration> {
+        self.gpu_timer.as_ref().and_then(GpuTimer::last)
+    }
+
+    // Culls the instances of opaque models against the frustum of each viewport on the
+    //  gpu, and draws the ones left indirectly. When the adapter can't draw indirectly
+    //  they're culled on the cpu instead.
+    pub fn set_gpu_culling(&mut self, enabled: bool) {
+        let supported =
+            culling::gpu_culling_supported(&self.downlevel_capabilities, &self.device.limits());
+        if !enabled {
+            self.culling = Culling::Off;
+        } else if !supported {
+            log::warn!("Indirect drawing isn't supported, culling on the cpu instead.");
+            self.culling = Culling::Cpu;
+        } else if !matches!(self.culling, Culling::Gpu(_)) {
+            self.culling = Culling::Gpu(GpuCulling::new(&self.device));
+        }
+    }
+
+    // Whether instances are culled, on the gpu or the cpu.
+    pub fn culling_enabled(&self) -> bool {
+        !matches!(self.culling, Culling::Off)
+    }
+
+    // Maps the near plane to depth 1 and the far plane to 0 instead of the other way around.
+    //  Float depth has most of its precision near 0, and a perspective projection bunches
+    //  depth up near 1, so flipping it spreads the precision out over the distance instead
+    //  of wasting it right in front of the camera. Far away surfaces stop fighting, which
+    //  helps most with a small near plane or a large far plane. The depth clear value, the
+    //  depth compare of every pipeline and the projection of the viewports drawn follow it.
+    pub fn set_reverse_z(&mut self, enabled: bool) {
+        if enabled == self.reverse_z {
+            return;
+        }
+        self.reverse_z = enabled;
+
+        self.render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &self.shader,
+            Some("Textured mesh render pipeline"),
+            Some(wgpu::Face::Back),
+            enabled,
+            wgpu::DepthBiasState::default(),
+            false,
+        );
+        self.no_cull_render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &self.shader,
+            Some("Double sided mesh render pipeline"),
+            None,
+            enabled,
+            wgpu::DepthBiasState::default(),
+            false,
+        );
+        self.depth_bias_render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &self.shader,
+            Some("Depth biased mesh render pipeline"),
+            None,
+            enabled,
+            self.depth_bias.state(enabled),
+            false,
+        );
+        self.transparent_render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &self.shader,
+            Some("Transparent mesh render pipeline"),
+            Some(wgpu::Face::Back),
+            enabled,
+            wgpu::DepthBiasState::default(),
+            true,
+        );
+        self.transparent_no_cull_render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &self.shader,
+            Some("Double sided transparent mesh render pipeline"),
+            None,
+            enabled,
+            wgpu::DepthBiasState::default(),
+            true,
+        );
+        self.light_render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.light_render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &self.light_shader,
+            Some("Light render pipeline"),
+            Some(wgpu::Face::Back),
+            enabled,
+            wgpu::DepthBiasState::default(),
+            false,
+        );
+        self.skybox_pipeline = Self::create_skybox_pipeline(
+            &self.device,
+            &self.skybox_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Texture::DEPTH_FORMAT,
+            self.sample_count,
+            &self.skybox_shader,
+            enabled,
+        );
+        self.particle_pipeline = particles::create_pipeline(
+            &self.device,
+            &self.uniform_bind_group_layout,
+            Texture::HDR_FORMAT,
+            Texture::DEPTH_FORMAT,
+            self.sample_count,
+            enabled,
+        );
+        self.deferred
+            .set_reverse_z(&self.device, &self.render_pipeline_layout, enabled);
+        self.debug_lines.set_reverse_z(
+            &self.device,
+            &self.uniform_bind_group_layout,
+            Texture::HDR_FORMAT,
+            Texture::DEPTH_FORMAT,
+            enabled,
+        );
+    }
+
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    // How far materials with depth_bias are pulled towards the camera, see DepthBias. Raise
+    //  it when a decal still flickers through its surface, lower it when it shows through
+    //  things in front of it.
+    pub fn set_depth_bias(&mut self, depth_bias: DepthBias) {
+        self.depth_bias = depth_bias;
+        self.depth_bias_render_pipeline = Self::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            Texture::HDR_FORMAT,
+            Some(Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &self.shader,
+            Some("Depth biased mesh render pipeline"),
+            None,
+            self.reverse_z,
+            depth_bias.state(self.reverse_z),
+            false,
+        );
+        self.deferred.set_depth_bias(
+            &self.device,
+            &self.render_pipeline_layout,
+            depth_bias,
+        );
+    }
+
+    pub fn depth_bias(&self) -> DepthBias {
+        self.depth_bias
+    }
+
+    // What the depth buffer is cleared to, the depth of the far plane.
+    pub fn depth_clear_value(&self) -> f32 {
+        Texture::far_depth(self.reverse_z)
+    }
+
+    // Computes the matrices of the model's first instances.len() instances on the gpu and
+    //  writes them to its instance buffer, instead of the scene graph doing the math and
+    //  uploading them. Meant for lots of instances moving every frame, like particles.
+    //  The cpu copy of the instances isn't updated, so picking and saving still see the
+    //  old transforms, and the next sync of the model overwrites these. Without compute
+    //  shader support the matrices are computed here and uploaded instead. Instances the
+    //  model's instance buffer has no room for are left out.
+    pub fn update_instances_gpu(&mut self, model: &Model, instances: &[Instance]) {
+        let resource = &model.instance_resource;
+        let count = instances
+            .len()
+            .min(resource.get_cpu_length())
+            .min(resource._get_gpu_length());
+        let instances = &instances[..count];
+
+        match &mut self.instance_compute {
+            Some(instance_compute) => instance_compute.update(
+                &self.device,
+                &self.queue,
+                resource.get_gpu_buffer(),
+                instances,
+            ),
+            None => {
+                let raw: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+                self.queue
+                    .write_buffer(resource.get_gpu_buffer(), 0, bytemuck::cast_slice(&raw));
             }
-            _ => false,
         }
     }
 
-    pub fn update(&mut self, dt: Duration) {
-        self.camera_controller.update_camera(&mut self.camera, dt);
-        self.uniforms.update_view_proj(&self.camera);
+    // What the models (usually the scene's) and the renderer's own targets have allocated on
+    //  the gpu, to keep an eye on growing buffers. Bloom, ssao, the environment maps and
+    //  the pipelines aren't counted.
+    pub fn gpu_memory_report(&self, models: &[Model]) -> MemoryReport {
+        let mut report = MemoryReport::for_models(models);
+        report.textures += self.default_textures.white.byte_size()
+            + self.default_textures.normal.byte_size();
+        let msaa_bytes = self.msaa_texture.as_ref().map_or(0, |msaa_texture| {
+            msaa_texture.byte_size() * self.sample_count as u64
+        });
+        report.render_targets = self.depth_texture.byte_size() * self.sample_count as u64
+            + self.hdr_texture.byte_size()
+            + msaa_bytes
+            + Deferred::target_bytes(&self.surface_configuration, self.sample_count);
+        report
+    }
+
+    // Draw calls, instances and indices of the last draw_scene, all viewports together.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats.get()
+    }
+
+    pub(crate) fn reset_frame_stats(&self) {
+        self.frame_stats.set(FrameStats::default());
+    }
+
+    pub(crate) fn record_draw(&self, instances: u32, indices: u32) {
+        let mut stats = self.frame_stats.get();
+        stats.record_draw(instances, indices);
+        self.frame_stats.set(stats);
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.size.width == 0 || self.size.height == 0
+    }
+
+    // Moves the given camera, usually the scene's active one, with the input.
+    pub fn update(&mut self, dt: Duration, input: &InputState, camera: &mut Camera) {
+        #[cfg(all(feature = "hot-reload", debug_assertions))]
+        self.reload_shaders();
+
+        // Scene cameras don't know about the window, keep the aspect ratio in sync here.
+        if !self.is_minimized() {
+            camera.projection.resize(self.size.width, self.size.height);
+        }
+        self.camera_controller.update_camera(camera, input, dt);
+        self.uniforms.update_view_proj(camera);
 
         // Update the light
         let old_position: Vector3<_> = self.light.position.into();
         self.light.position = (Quaternion::from_axis_angle(
             (0.0, 1.0, 0.0).into(),
-            cgmath::Deg(60.0 * dt.as_secs_f32()),
+            cgmath::Deg(self.light_orbit_speed * dt.as_secs_f32()),
         ) * old_position)
             .into();
 
+        let light_uniform = LightUniform {
+            light: self.light,
+            ambient: self.ambient,
+        };
         self.queue
-            .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light]));
+            .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[light_uniform]));
         self.queue.write_buffer(
             &self.uniform_buffer,
             0,
@@ -388,88 +1447,172 @@ impl Renderer {
         );
     }
 
-    // For now this isn't really used as all the handles to the resources are owned by scene.
-    //  Maybe come up with something to keep scene an abstract representation of the scene
-    //  without any handles to gpu buffers etc? How?... Want to prevent introducing
-    //  refference lifetime bloat.
-    /*pub fn _render(&mut self) -> Result<(), wgpu::SwapChainError> {
-        // Analog of ogl fbo I guess?
-        let frame = self.swap_chain.get_current_frame()?.output;
-
-        // Encoders can create a commandbuffer.
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
-        let mut _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Default Renderpass"),
-            color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &frame.view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(self.clear_color),
-                    store: true,
-                },
-            }],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: true,
-                }),
-                stencil_ops: None,
+    // Transparent ones blend with what's behind them and don't write depth, they're drawn
+    //  after everything else, back to front.
+    #[allow(clippy::too_many_arguments)]
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+        shader: &wgpu::ShaderModule,
+        label: Option<&str>,
+        cull_mode: Option<wgpu::Face>,
+        reverse_z: bool,
+        depth_bias: wgpu::DepthBiasState,
+        transparent: bool,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::layout(), InstanceRaw::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(if transparent {
+                        wgpu::BlendState::ALPHA_BLENDING
+                    } else {
+                        wgpu::BlendState::REPLACE
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
             }),
-        });
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode,
+                strip_index_format: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: !transparent,
+                depth_compare: Texture::depth_compare(reverse_z, false),
+                stencil: wgpu::StencilState::default(),
+                bias: depth_bias,
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        })
+    }
 
-        // Draw light (as an avocado... :o )
-        // render_pass.set_pipeline(&self.light_render_pipeline);
-        // render_pass.set_vertex_buffer(1, self.light_buffer.slice(..));
+    // Unprojects a screen coordinate (in physical pixels) into a world space ray, through
+    //  the camera of the viewport drawn into rect. Returns the ray origin on the near plane
+    //  and the normalized ray direction, None when the camera's view can't be inverted.
+    pub fn screen_ray(
+        &self,
+        camera: &Camera,
+        rect: viewport::Rect,
+        x: f32,
+        y: f32,
+    ) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let inverse_view_proj = camera.view_projection().invert()?;
 
-        // render_pass.draw_light_model(
-        //     &self.gltf_model,
-        //     &self.uniform_bind_group,
-        //     &self.light_bind_group,
-        // );
+        let ndc_x = 2.0 * (x - rect.x as f32) / rect.width as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * (y - rect.y as f32) / rect.height as f32;
 
-        // render_pass.set_pipeline(&self.render_pipeline);
-        // // Set the "model_matrix" atribute:
-        //render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        // Clip space depth in wgpu goes from 0 (near) to 1 (far).
+        let near = inverse_view_proj * Vector4::new(ndc_x, ndc_y, 0.0, 1.0);
+        let far = inverse_view_proj * Vector4::new(ndc_x, ndc_y, 1.0, 1.0);
+        let near = near.truncate() / near.w;
+        let far = far.truncate() / far.w;
 
-        // // Draw mesh instances.
-        // render_pass.draw_model_instanced(
-        //     &self.gltf_model,
-        //     0..self.instances.len() as u32,
-        //     &self.uniform_bind_group,
-        //     &self.light_bind_group
-        // );
+        Some((near, (far - near).normalize()))
+    }
 
-        drop(_render_pass);
-        self.queue.submit(std::iter::once(encoder.finish()));
+    // Writes the camera of every viewport to its own uniform buffer, creating more of
+    //  those if there are more viewports than before.
+    pub fn prepare_viewports(&mut self, viewports: &[viewport::Viewport]) {
+        while self.viewport_uniforms.len() < viewports.len() {
+            self.viewport_uniforms.push(ViewportUniforms::new(
+                &self.device,
+                &self.uniform_bind_group_layout,
+            ));
+        }
+        for (viewport, uniforms) in viewports.iter().zip(&self.viewport_uniforms) {
+            uniforms.write(&self.queue, self.uniforms, &viewport.camera);
+        }
+    }
 
-        Ok(())
-    }*/
+    // Ambient occlusion for deferred shading, see Deferred::draw_ssao. Call after
+    //  prepare_viewports.
+    pub fn draw_ssao(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        viewports: &[viewport::Viewport],
+    ) {
+        let viewports: Vec<_> = viewports
+            .iter()
+            .zip(&self.viewport_uniforms)
+            .map(|(viewport, uniforms)| (viewport.rect, &uniforms.bind_group))
+            .collect();
+        self.deferred.draw_ssao(encoder, &viewports);
+    }
 
-    fn create_render_pipeline(
+    pub fn viewport_bind_group(&self, i: usize) -> &wgpu::BindGroup {
+        &self.viewport_uniforms[i].bind_group
+    }
+
+    // What the frame ends up in, the format the adapter prefers for the window. Pipelines
+    //  drawing straight to the surface need it as their color format.
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.surface_configuration.format
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (
+            self.surface_configuration.width,
+            self.surface_configuration.height,
+        )
+    }
+
+    // Copies the last frame of a headless renderer back, None when it draws to a window.
+    //  Blocks until the gpu is done with it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn capture_frame(&self) -> Option<image::RgbaImage> {
+        let texture = match &self.target {
+            RenderTarget::Offscreen(texture) => texture,
+            RenderTarget::Surface(_) => return None,
+        };
+        let frame = texture.read_back(&self.device, &self.queue);
+        self.device.poll(wgpu::Maintain::Wait);
+        Some(futures::executor::block_on(frame))
+    }
+
+    // The skybox doesn't use any vertex buffers and is drawn on the far plane, so it
+    //  gets its own pipeline which tests depth but never writes it.
+    fn create_skybox_pipeline(
         device: &wgpu::Device,
         layout: &wgpu::PipelineLayout,
         color_format: wgpu::TextureFormat,
-        depth_format: Option<wgpu::TextureFormat>,
-        vs_module: &wgpu::ShaderModule,
-        fs_module: &wgpu::ShaderModule,
-        label: Option<&str>,
+        depth_format: wgpu::TextureFormat,
+        sample_count: u32,
+        shader: &wgpu::ShaderModule,
+        reverse_z: bool,
     ) -> wgpu::RenderPipeline {
         device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: label,
-            layout: Some(&layout),
+            label: Some("Skybox render pipeline"),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &vs_module,
-                entry_point: "main",
-                buffers: &[ModelVertex::layout(), InstanceRaw::layout()],
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &fs_module,
-                entry_point: "main",
+                module: shader,
+                entry_point: "fs_main",
                 targets: &[wgpu::ColorTargetState {
                     format: color_format,
                     blend: Some(wgpu::BlendState::REPLACE),
@@ -479,37 +1622,59 @@ impl Renderer {
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                cull_mode: None,
                 strip_index_format: None,
                 polygon_mode: wgpu::PolygonMode::Fill,
                 clamp_depth: false,
                 conservative: false,
             },
-            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
-                format: format,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: Texture::depth_compare(reverse_z, true),
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
         })
     }
 
-    pub fn _get_size(&self) -> (u32, u32) {
-        (
-            self.surface_configuration.width,
-            self.surface_configuration.height,
-        )
+    fn skybox_bindgroup_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("skybox_bindgroup_layout"),
+        })
     }
 
     // Creates a default layout for 4 texture views and their samplers.
+    // Diffuse, normal, emissive, metallic roughness and occlusion texture/sampler pairs,
+    //  followed by the material uniform.
     fn default_bindgroup_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        let layout_entries = (0..2)
+        let mut layout_entries = (0..5)
             .step_by(1)
             .map(|i| {
                 vec![
@@ -538,6 +1703,16 @@ impl Renderer {
             .into_iter()
             .flatten()
             .collect::<Vec<wgpu::BindGroupLayoutEntry>>();
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 10,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
 
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &layout_entries,
@@ -545,3 +1720,12 @@ impl Renderer {
         })
     }
 }
+
+// A renderer drawing to a texture, for the unit tests that need a device. Those tests are
+//  ignored by default, `cargo test -- --ignored` runs them where there's an adapter.
+//  WGPU_BACKEND=gl picks the software OpenGL driver where there's no Vulkan one.
+#[cfg(test)]
+pub(crate) fn test_renderer() -> Renderer {
+    futures::executor::block_on(Renderer::new_headless(64, 64))
+        .unwrap_or_else(|e| panic!("Couldn't start a headless renderer: {}", e))
+}
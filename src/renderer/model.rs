@@ -1,16 +1,21 @@
 use crate::renderer::{
+    draw_constants::PerDraw,
     instance::{Instance, InstanceRaw},
-    material::Material,
+    lod::Lod,
+    material::{Material, TextureCache},
     resource::{Resource, ResourceType},
     Renderer,
 };
 
 use anyhow::*;
+use gltf::mesh::util::ReadIndices;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::ops::Range;
-use std::path::Path;
-use wgpu::util::DeviceExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use cgmath::SquareMatrix;
+use cgmath::{InnerSpace, MetricSpace, Matrix4, Point3, SquareMatrix, Vector3};
 
 pub trait Vertex {
     fn layout<'a>() -> wgpu::VertexBufferLayout<'a>;
@@ -31,6 +36,7 @@ where
         material: &'b Material,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     );
 
     fn draw_mesh_instanced(
@@ -40,6 +46,21 @@ where
         material: &'b Material,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
+    );
+
+    // Like draw_mesh_instanced, with the draw's arguments read from indirect_buffer at
+    //  offset, see culling.rs.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        indirect_buffer: &'b wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        material: &'b Material,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     );
 
     fn draw_model(
@@ -47,6 +68,7 @@ where
         model: &'b Model,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     );
 
     fn draw_model_instanced(
@@ -55,6 +77,7 @@ where
         instances: Range<u32>,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     );
 }
 impl<'a, 'b> DrawModel<'a, 'b> for wgpu::RenderPass<'a>
@@ -67,8 +90,9 @@ where
         material: &'b Material,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     ) {
-        self.draw_mesh_instanced(mesh, 0..1, material, uniforms, light);
+        self.draw_mesh_instanced(mesh, 0..1, material, uniforms, light, draw);
     }
 
     fn draw_mesh_instanced(
@@ -78,13 +102,24 @@ where
         material: &'b Material,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
+    ) {
+        set_mesh_bindings(self, mesh, material, uniforms, light, draw);
+        self.draw_indexed(0..mesh.num_elements(), 0, instances);
+    }
+
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        indirect_buffer: &'b wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        material: &'b Material,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     ) {
-        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        self.set_bind_group(0, &material.bind_group, &[]);
-        self.set_bind_group(1, &uniforms, &[]);
-        self.set_bind_group(2, &light, &[]);
-        self.draw_indexed(0..mesh.num_elements, 0, instances);
+        set_mesh_bindings(self, mesh, material, uniforms, light, draw);
+        self.draw_indexed_indirect(indirect_buffer, offset);
     }
 
     fn draw_model(
@@ -92,8 +127,9 @@ where
         model: &'b Model,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     ) {
-        self.draw_model_instanced(model, 0..1, uniforms, light);
+        self.draw_model_instanced(model, 0..1, uniforms, light, draw);
     }
 
     fn draw_model_instanced(
@@ -102,15 +138,43 @@ where
         instances: Range<u32>,
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
+        draw: PerDraw<'b>,
     ) {
         for mesh in &model.meshes {
             let material = &model.materials[mesh.material];
             self.set_vertex_buffer(1, model.instance_resource.get_gpu_buffer().slice(..));
-            self.draw_mesh_instanced(mesh, instances.clone(), material, uniforms, light);
+            self.draw_mesh_instanced(mesh, instances.clone(), material, uniforms, light, draw);
         }
     }
 }
 
+// Everything a mesh draw needs besides the instances.
+fn set_mesh_bindings<'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    mesh: &'a Mesh,
+    material: &'a Material,
+    uniforms: &'a wgpu::BindGroup,
+    light: &'a wgpu::BindGroup,
+    draw: PerDraw<'a>,
+) {
+    render_pass.set_vertex_buffer(0, mesh.vertex_resource.get_gpu_buffer().slice(..));
+    render_pass.set_index_buffer(
+        mesh.index_resource.get_gpu_buffer().slice(..),
+        mesh.index_resource.format(),
+    );
+    render_pass.set_bind_group(0, &material.bind_group, &[]);
+    render_pass.set_bind_group(1, uniforms, &[]);
+    render_pass.set_bind_group(2, light, &[]);
+    match draw {
+        PerDraw::PushConstants(constants) => render_pass.set_push_constants(
+            wgpu::ShaderStages::VERTEX,
+            0,
+            bytemuck::bytes_of(&constants),
+        ),
+        PerDraw::Uniform(bind_group, offset) => render_pass.set_bind_group(3, bind_group, &[offset]),
+    }
+}
+
 pub trait DrawLight<'a, 'b>
 where
     'b: 'a,
@@ -165,11 +229,14 @@ where
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
     ) {
-        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_vertex_buffer(0, mesh.vertex_resource.get_gpu_buffer().slice(..));
+        self.set_index_buffer(
+            mesh.index_resource.get_gpu_buffer().slice(..),
+            mesh.index_resource.format(),
+        );
         self.set_bind_group(0, uniforms, &[]);
         self.set_bind_group(1, light, &[]);
-        self.draw_indexed(0..mesh.num_elements, 0, instances);
+        self.draw_indexed(0..mesh.num_elements(), 0, instances);
     }
 
     fn draw_light_model(
@@ -196,19 +263,167 @@ where
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ModelVertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
-    normal: [f32; 3],
-    tangent: [f32; 3],
-    bitangent: [f32; 3],
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
+    // Multiplied into the base color like the instance color, white when a mesh has none.
+    pub color: [f32; 4],
+    // For textures the material samples with TEXCOORD_1, the same as tex_coords when a
+    //  mesh has one set.
+    pub tex_coords_1: [f32; 2],
+}
+
+impl ModelVertex {
+    // The bitangent follows from the normal and tangent, same as when loading a gltf. The
+    //  color is white and both uv sets are the same.
+    pub fn new(
+        position: [f32; 3],
+        tex_coords: [f32; 2],
+        normal: [f32; 3],
+        tangent: [f32; 3],
+    ) -> Self {
+        let bitangent = Vector3::from(tangent).cross(Vector3::from(normal));
+        Self {
+            position,
+            tex_coords,
+            normal,
+            tangent,
+            bitangent: bitangent.into(),
+            color: [1.0; 4],
+            tex_coords_1: tex_coords,
+        }
+    }
+}
+
+// Where a model came from, so a saved scene can make it again (see Scene::save).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ModelSource {
+    // The path as given to Model::load.
+    Gltf(PathBuf),
+    Cube {
+        size: f32,
+    },
+    UvSphere {
+        radius: f32,
+        rings: u32,
+        sectors: u32,
+    },
+    Plane {
+        size: f32,
+        subdivisions: u32,
+    },
+    // Built from geometry in some other way, there's no making it again.
+    Geometry,
+}
+
+impl ModelSource {
+    pub fn load(&self, renderer: &Renderer) -> Result<Model> {
+        Ok(match self {
+            ModelSource::Gltf(path) => Model::load(renderer, path)?,
+            ModelSource::Cube { size } => Model::cube(renderer, *size),
+            ModelSource::UvSphere {
+                radius,
+                rings,
+                sectors,
+            } => Model::uv_sphere(renderer, *radius, *rings, *sectors),
+            ModelSource::Plane { size, subdivisions } => {
+                Model::plane(renderer, *size, *subdivisions)
+            }
+            ModelSource::Geometry => bail!("Models built from geometry can't be loaded again."),
+        })
+    }
 }
 
 pub struct Model {
+    pub source: ModelSource,
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
     // For a singular model this would be resource.cpu_buffer.len() == 1
     //  vector containing just a model matrix
     pub instance_resource: Resource<InstanceRaw>,
+    // Axis aligned bounding box (min, max) enclosing all meshes, in model space.
+    pub bounds: (Vector3<f32>, Vector3<f32>),
+    // Coarser versions of meshes for instances further away, see lod.rs. Sorted by
+    //  distance, meshes is used below the first one.
+    pub lods: Vec<Lod>,
+    // What Model::load had to make up for, per primitive, like normals the file didn't
+    //  have. Empty when the asset loaded as is.
+    pub warnings: Vec<String>,
+    // Ids of instances left out when drawing and picking, see set_instance_visible.
+    pub hidden_instances: HashSet<usize>,
+}
+
+// Grows the (min, max) box so it also encloses p.
+fn grow_bounds(bounds: &mut (Vector3<f32>, Vector3<f32>), p: Vector3<f32>) {
+    bounds.0.x = bounds.0.x.min(p.x);
+    bounds.0.y = bounds.0.y.min(p.y);
+    bounds.0.z = bounds.0.z.min(p.z);
+    bounds.1.x = bounds.1.x.max(p.x);
+    bounds.1.y = bounds.1.y.max(p.y);
+    bounds.1.z = bounds.1.z.max(p.z);
+}
+
+// Reads at most count items from one of the gltf crate's accessor iterators. Its iterator
+//  over a sparse accessor without a buffer view (all zeros apart from the sparse values)
+//  never ends, and its size_hint underflows once it's past the last sparse value. So
+//  this pushes one item at a time, collect() would ask for the size_hint.
+fn read_accessor<T>(items: impl Iterator<Item = T>, count: usize) -> Vec<T> {
+    let mut read = Vec::with_capacity(count);
+    for item in items.take(count) {
+        read.push(item);
+    }
+    read
+}
+
+// Vertex normals for meshes that don't have them, the average of the faces around each
+//  vertex weighted by their area. Gltf asks for flat normals there, but that would mean
+//  splitting up the vertices.
+fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        if triangle.iter().any(|&index| index as usize >= positions.len()) {
+            continue;
+        }
+        let [a, b, c] = [
+            Vector3::from(positions[triangle[0] as usize]),
+            Vector3::from(positions[triangle[1] as usize]),
+            Vector3::from(positions[triangle[2] as usize]),
+        ];
+        // Twice the area long, so bigger faces count for more.
+        let face_normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += face_normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                [0.0, 1.0, 0.0]
+            }
+        })
+        .collect()
+}
+
+// Some unit vector at a right angle to normal, the tangent of meshes without tangents.
+fn perpendicular(normal: Vector3<f32>) -> Vector3<f32> {
+    let other = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    normal.cross(other).normalize()
+}
+
+fn empty_bounds() -> (Vector3<f32>, Vector3<f32>) {
+    (
+        Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+        Vector3::new(f32::MIN, f32::MIN, f32::MIN),
+    )
 }
 
 impl Model {
@@ -216,84 +431,166 @@ impl Model {
         let queue = &renderer.queue;
         let device = &renderer.device;
 
+        // Reads .glb and .gltf alike. External .bin buffers and images are resolved against
+        //  the file's directory and the images decoded here, so materials only ever look in
+        //  `images`. src/res/avocado has the same model both ways.
         let (document, buffers, images) = gltf::import(path.as_ref())?;
 
         let mut meshes = Vec::new();
         let mut materials = Vec::new();
+        let mut bounds = empty_bounds();
+        let mut textures = TextureCache::new(&images);
+        let mut warnings = Vec::new();
 
         for mesh in document.meshes() {
             for primitive in mesh.primitives() {
+                let mut warn = |message: &str| {
+                    warnings.push(format!(
+                        "mesh {:?} primitive {} {}",
+                        mesh.name().unwrap_or("without a name"),
+                        primitive.index(),
+                        message
+                    ))
+                };
+
                 // Deal with material.
                 materials.push(Material::from_gltf(
                     primitive.material(),
-                    &images,
+                    &mut textures,
                     device,
                     queue,
                     &renderer.default_bind_group_layout,
+                    renderer.texture_sampler_config,
+                    &renderer.default_textures,
                 ));
 
                 let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
                 let mut _vertices = Vec::new();
+                // The count the attributes should all have. Sparse accessors are read up to
+                //  it, see read_accessor.
+                let vertex_count = primitive
+                    .get(&gltf::Semantic::Positions)
+                    .map_or(0, |accessor| accessor.count());
+                // Meshes without vertex colors are left white.
+                let colors: Vec<[f32; 4]> = reader
+                    .read_colors(0)
+                    .map(|colors| read_accessor(colors.into_rgba_f32(), vertex_count))
+                    .unwrap_or_default();
+                // The second uv set is only read when the material uses it.
+                let tex_coords_1: Vec<[f32; 2]> = match reader.read_tex_coords(1) {
+                    Some(tex_coords) if materials[materials.len() - 1].uses_tex_coords_1() => {
+                        read_accessor(tex_coords.into_f32(), vertex_count)
+                    }
+                    _ => Vec::new(),
+                };
 
-                // Read positions, put them in a ModelVertex struct.
-                _vertices = if let Some(pos_iter) = reader.read_positions() {
-                    if let Some(tc_iter) = reader.read_tex_coords(0) {
-                        if let Some(n_iter) = reader.read_normals() {
-                            if let Some(tangent_iter) = reader.read_tangents() {
-                                pos_iter
-                                    .zip(tc_iter.into_f32().zip(n_iter.zip(tangent_iter)))
-                                    .map(|(p, (tc, (n, t)))| {
-                                        let tangent = cgmath::Vector3::from([t[0], t[1], t[2]]);
-                                        let normal = cgmath::Vector3::from(n);
-                                        let bitangent = tangent.cross(normal);
-                                        ModelVertex {
-                                            position: p,
-                                            tex_coords: tc,
-                                            normal: n,
-                                            tangent: tangent.into(),
-                                            bitangent: bitangent.into(),
-                                        }
-                                    })
-                                    .collect()
-                            } else {
-                                Vec::new()
-                            }
-                        } else {
-                            Vec::new()
-                        }
-                    } else {
+                // Read positions, put them in a ModelVertex struct. Without positions there's
+                //  nothing to draw, the other attributes get a stand-in when they're missing
+                //  so plain meshes (like ones exported without a material) still show up.
+                let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                    Some(positions) => read_accessor(positions, vertex_count),
+                    None => {
+                        warn("has no positions, it's left empty");
                         Vec::new()
                     }
-                } else {
-                    Vec::new()
                 };
-
-                // Read indices.
-                let mut indices = Vec::new();
-                if let Some(iter) = reader.read_indices() {
-                    for index in iter.into_u32() {
-                        indices.push(index);
+                // Primitives without indices draw their vertices in order.
+                let index_count = primitive.indices().map_or(0, |accessor| accessor.count());
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => read_accessor(indices.into_u32(), index_count),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                let tex_coords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                    Some(tex_coords) => read_accessor(tex_coords.into_f32(), vertex_count),
+                    None => {
+                        warn("has no texture coordinates, they're all 0");
+                        Vec::new()
+                    }
+                };
+                let normals: Vec<[f32; 3]> = match reader.read_normals() {
+                    Some(normals) => read_accessor(normals, vertex_count),
+                    None => {
+                        warn("has no normals, they're computed from the faces");
+                        smooth_normals(&positions, &indices)
+                    }
+                };
+                let tangents: Vec<[f32; 4]> = match reader.read_tangents() {
+                    Some(tangents) => read_accessor(tangents, vertex_count),
+                    None => {
+                        warn("has no tangents, normal mapping will be off");
+                        Vec::new()
+                    }
+                };
+                // Attributes are supposed to have the same count, vertices past the end of
+                //  a shorter one get the same stand-in as when it's missing.
+                for (attribute, count) in [
+                    ("positions", positions.len()),
+                    ("texture coordinates", tex_coords.len()),
+                    ("normals", normals.len()),
+                    ("tangents", tangents.len()),
+                ]
+                .iter()
+                {
+                    if *count != 0 && *count != vertex_count {
+                        warn(&format!(
+                            "has {} {} for {} vertices",
+                            count, attribute, vertex_count
+                        ));
                     }
                 }
+                _vertices = positions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &p)| {
+                        let tc = tex_coords.get(i).copied().unwrap_or([0.0, 0.0]);
+                        let normal = cgmath::Vector3::from(
+                            normals.get(i).copied().unwrap_or([0.0, 1.0, 0.0]),
+                        );
+                        let tangent = match tangents.get(i) {
+                            Some(t) => cgmath::Vector3::new(t[0], t[1], t[2]),
+                            None => perpendicular(normal),
+                        };
+                        let bitangent = tangent.cross(normal);
+                        ModelVertex {
+                            position: p,
+                            tex_coords: tc,
+                            normal: normal.into(),
+                            tangent: tangent.into(),
+                            bitangent: bitangent.into(),
+                            color: colors.get(i).copied().unwrap_or([1.0; 4]),
+                            tex_coords_1: tex_coords_1.get(i).copied().unwrap_or(tc),
+                        }
+                    })
+                    .collect();
 
-                // Create buffers.
-                let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some(&format!("{:?} Vertex Buffer", path.as_ref())),
-                    contents: bytemuck::cast_slice(&_vertices),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
+                for v in &_vertices {
+                    grow_bounds(&mut bounds, v.position.into());
+                }
 
-                let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some(&format!("{:?} Index Buffer", path.as_ref())),
-                    contents: bytemuck::cast_slice(&indices),
-                    usage: wgpu::BufferUsages::INDEX,
-                });
+                // Create resources, keeping a cpu copy around so the geometry can be
+                //  edited and synced at runtime.
+                let vertex_resource = Resource::new_with_data(
+                    device.clone(),
+                    queue.clone(),
+                    _vertices,
+                    ResourceType::Vertex,
+                );
+                // Indices keep their format, except u8 which wgpu can't draw with.
+                let index_resource = match reader.read_indices() {
+                    Some(ReadIndices::U8(_)) | Some(ReadIndices::U16(_)) => {
+                        let indices = indices.iter().map(|&i| i as u16).collect();
+                        IndexResource::new_u16(device.clone(), queue.clone(), indices)
+                    }
+                    Some(ReadIndices::U32(_)) | None => {
+                        IndexResource::new_u32(device.clone(), queue.clone(), indices)
+                    }
+                }
+                .with_context(|| format!("Failed to load mesh {:?}", mesh.name()))?;
 
                 meshes.push(Mesh {
                     name: mesh.name().unwrap_or("Cool mesh name").to_string(),
-                    vertex_buffer,
-                    index_buffer,
-                    num_elements: indices.len() as u32,
+                    vertex_resource,
+                    index_resource,
                     material: materials.len() - 1,
                 });
             }
@@ -314,15 +611,112 @@ impl Model {
         // );
 
         let instance_resource =
-            Resource::new_sized(device.clone(), queue.clone(), 1, ResourceType::Vertex);
+            Resource::new_sized(device.clone(), queue.clone(), 1, ResourceType::Instance);
+
+        // A model without any vertices gets a degenerate box at the origin.
+        if bounds.0.x > bounds.1.x {
+            bounds = (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+        }
+
+        for warning in &warnings {
+            log::warn!("{}: {}", path.as_ref().display(), warning);
+        }
 
         Ok(Self {
+            source: ModelSource::Gltf(path.as_ref().to_path_buf()),
             meshes,
             materials,
             instance_resource,
+            bounds,
+            lods: vec![],
+            warnings,
+            hidden_instances: HashSet::new(),
         })
     }
 
+    // Loads every path with load, one result per path in the same order, so a bad file
+    //  doesn't stop the rest from loading. They all use the renderer's
+    //  default_bind_group_layout like load does, nothing is made per model.
+    pub fn load_many<P: AsRef<Path>>(renderer: &Renderer, paths: &[P]) -> Vec<Result<Self>> {
+        paths
+            .iter()
+            .map(|path| {
+                Self::load(renderer, path)
+                    .with_context(|| format!("loading {}", path.as_ref().display()))
+            })
+            .collect()
+    }
+
+    // Loads the model again from its source, e.g. after the file was edited, and swaps in
+    //  the new meshes and materials. The instances, and which ones are hidden, are kept.
+    //  Levels of detail are generated again at the same distances, since they were made
+    //  from the old meshes. Changes made to the meshes or materials after loading are lost.
+    //  On error the model is left as it was.
+    pub fn reload(&mut self, renderer: &Renderer) -> Result<()> {
+        let reloaded = self.source.load(renderer)?;
+        self.meshes = reloaded.meshes;
+        self.materials = reloaded.materials;
+        self.bounds = reloaded.bounds;
+        self.warnings = reloaded.warnings;
+        if !self.lods.is_empty() {
+            let distances: Vec<f32> = self.lods.iter().map(|lod| lod.distance).collect();
+            self.generate_lods(renderer, &distances);
+        }
+        Ok(())
+    }
+
+    // Builds a model with a single mesh from geometry made in code, see primitives.rs.
+    pub fn from_geometry(
+        renderer: &Renderer,
+        name: &str,
+        vertices: Vec<ModelVertex>,
+        indices: Vec<u32>,
+        material: Material,
+    ) -> Self {
+        let device = &renderer.device;
+        let queue = &renderer.queue;
+
+        let mut bounds = empty_bounds();
+        for v in &vertices {
+            grow_bounds(&mut bounds, v.position.into());
+        }
+        if bounds.0.x > bounds.1.x {
+            bounds = (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+        }
+
+        let vertex_resource = Resource::new_with_data(
+            device.clone(),
+            queue.clone(),
+            vertices,
+            ResourceType::Vertex,
+        );
+        let index_resource = IndexResource::U32(Resource::new_with_data(
+            device.clone(),
+            queue.clone(),
+            indices,
+            ResourceType::Index,
+        ));
+
+        let instance_resource =
+            Resource::new_sized(device.clone(), queue.clone(), 1, ResourceType::Instance);
+
+        Self {
+            source: ModelSource::Geometry,
+            meshes: vec![Mesh {
+                name: name.to_string(),
+                vertex_resource,
+                index_resource,
+                material: 0,
+            }],
+            materials: vec![material],
+            instance_resource,
+            bounds,
+            lods: vec![],
+            warnings: vec![],
+            hidden_instances: HashSet::new(),
+        }
+    }
+
     pub fn add_instance(&mut self) {
         // For now we'll default the new instances to be positioned next to the
         //  previous instance.
@@ -345,6 +739,39 @@ impl Model {
         self.instance_resource.add_to_buffer(vec![new.to_raw()]);
     }
 
+    // Adds an instance with the given model matrix, tinted white unless specified otherwise.
+    pub fn add_instance_with_transform(&mut self, transform: Matrix4<f32>, tint: Option<[f32; 4]>) {
+        self.instance_resource.add_to_buffer(vec![InstanceRaw {
+            model: transform.into(),
+            inverse_model: transform.invert().unwrap().into(),
+            color: tint.unwrap_or([1.0, 1.0, 1.0, 1.0]),
+        }]);
+    }
+
+    pub fn set_instance_color(&mut self, id: usize, color: [f32; 4]) {
+        if let Some(i) = self.instance_resource._mut_local_at(id) {
+            i.color = color;
+        }
+    }
+
+    // Hidden instances keep their place in the buffer and their id, they're skipped when
+    //  drawing (see Culling::visibility) and by Scene::raycast.
+    pub fn set_instance_visible(&mut self, id: usize, visible: bool) {
+        if visible {
+            self.hidden_instances.remove(&id);
+        } else {
+            self.hidden_instances.insert(id);
+        }
+    }
+
+    pub fn is_instance_visible(&self, id: usize) -> bool {
+        !self.hidden_instances.contains(&id)
+    }
+
+    pub fn has_hidden_instances(&self) -> bool {
+        !self.hidden_instances.is_empty()
+    }
+
     pub fn _change_instance(&mut self, id: usize, instance: Instance) {
         if let Some(i) = self.instance_resource._mut_local_at(id) {
             *i = instance.to_raw();
@@ -353,69 +780,467 @@ impl Model {
 
     pub fn change_instance_raw(&mut self, id: usize, instance_raw: cgmath::Matrix4<f32>) {
         if let Some(i) = self.instance_resource._mut_local_at(id) {
+            // Only the transform changes, the tint stays.
             *i = InstanceRaw {
                 model: instance_raw.into(),
                 inverse_model: instance_raw.invert().unwrap().into(),
+                color: i.color,
             };
         }
     }
 
     pub fn _remove_instance(&mut self) {
         // Remove the last instance for testing purposes.
-        self.instance_resource
-            ._remove_from_buffer(self.instance_resource.get_cpu_length() - 1);
+        let id = self.instance_resource.get_cpu_length() - 1;
+        self.instance_resource._remove_from_buffer(id);
+        self.hidden_instances.remove(&id);
     }
 
     pub fn get_num_instances(&self) -> usize {
         self.instance_resource.get_cpu_length()
     }
+
+    pub fn is_transparent(&self) -> bool {
+        self.materials.iter().any(|material| material.transparent)
+    }
+
+    // Ids of the visible instances ordered from furthest to nearest to eye, by the
+    //  instances' origins.
+    pub fn instances_back_to_front(&self, eye: Point3<f32>) -> Vec<usize> {
+        let mut distances: Vec<(usize, f32)> = (0..self.get_num_instances())
+            .filter(|&id| self.is_instance_visible(id))
+            .filter_map(|id| {
+                let model = self.instance_resource.local_at(id)?.model;
+                let position = Point3::new(model[3][0], model[3][1], model[3][2]);
+                Some((id, position.distance2(eye)))
+            })
+            .collect();
+        distances.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        distances.into_iter().map(|(id, _)| id).collect()
+    }
+
+    // Transforms the corners of the model space bounds by the instance's model matrix
+    //  and returns the world space axis aligned box enclosing them.
+    pub fn instance_bounds(&self, id: usize) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let model: Matrix4<f32> = self.instance_resource.local_at(id)?.model.into();
+        let (min, max) = self.bounds;
+
+        let mut world_bounds = empty_bounds();
+        for corner in 0..8 {
+            let p = Vector3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+            grow_bounds(&mut world_bounds, (model * p.extend(1.0)).truncate());
+        }
+
+        Some(world_bounds)
+    }
 }
 
-// TODO: Do I want to use the renderer::Resource abstraction for mesh buffer?
-//  - Seems unnessecary as mesh vertices, indices dont really change...
-//  - Would ruin the draw code.
-//  - Yeah probably a bad idea.
-//  - It might be worthwhile to employ resources for animation skinning though... If I get around to it.
+// Mesh geometry lives in resources, so vertices and indices can be edited on the cpu side
+//  and synced (see SyncJob::Vertex and SyncJob::Index). Handy for procedural deformation
+//  and maybe animation skinning later on.
 pub struct Mesh {
     pub name: String,
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer,
-    pub num_elements: u32,
+    pub vertex_resource: Resource<ModelVertex>,
+    pub index_resource: IndexResource,
     pub material: usize,
 }
 
+impl Mesh {
+    pub fn num_elements(&self) -> u32 {
+        self.index_resource.get_cpu_length() as u32
+    }
+}
+
+// Indices are kept in the format the mesh came with, most gltf files use u16 for small
+//  meshes and widening those would double their memory.
+pub enum IndexResource {
+    U16(Resource<u16>),
+    U32(Resource<u32>),
+}
+
+impl IndexResource {
+    // Errors when there are more indices than a draw call can take.
+    pub fn new_u16(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        indices: Vec<u16>,
+    ) -> Result<Self> {
+        check_index_count(indices.len())?;
+        Ok(IndexResource::U16(Resource::new_with_data(
+            device,
+            queue,
+            indices,
+            ResourceType::Index,
+        )))
+    }
+
+    pub fn new_u32(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        indices: Vec<u32>,
+    ) -> Result<Self> {
+        check_index_count(indices.len())?;
+        Ok(IndexResource::U32(Resource::new_with_data(
+            device,
+            queue,
+            indices,
+            ResourceType::Index,
+        )))
+    }
+
+    // The indices widened to u32, e.g. to build new geometry from.
+    pub fn to_u32(&self) -> Vec<u32> {
+        match self {
+            IndexResource::U16(resource) => {
+                resource.local_slice().iter().map(|&i| u32::from(i)).collect()
+            }
+            IndexResource::U32(resource) => resource.local_slice().to_vec(),
+        }
+    }
+
+    pub fn format(&self) -> wgpu::IndexFormat {
+        match self {
+            IndexResource::U16(_) => wgpu::IndexFormat::Uint16,
+            IndexResource::U32(_) => wgpu::IndexFormat::Uint32,
+        }
+    }
+
+    pub fn get_gpu_buffer(&self) -> &wgpu::Buffer {
+        match self {
+            IndexResource::U16(resource) => resource.get_gpu_buffer(),
+            IndexResource::U32(resource) => resource.get_gpu_buffer(),
+        }
+    }
+
+    pub fn get_cpu_length(&self) -> usize {
+        match self {
+            IndexResource::U16(resource) => resource.get_cpu_length(),
+            IndexResource::U32(resource) => resource.get_cpu_length(),
+        }
+    }
+
+    pub fn get_cpu_byte_length(&self) -> usize {
+        match self {
+            IndexResource::U16(resource) => resource.get_cpu_byte_length(),
+            IndexResource::U32(resource) => resource.get_cpu_byte_length(),
+        }
+    }
+
+    pub fn get_gpu_byte_length(&self) -> usize {
+        match self {
+            IndexResource::U16(resource) => resource.get_gpu_byte_length(),
+            IndexResource::U32(resource) => resource.get_gpu_byte_length(),
+        }
+    }
+
+    pub fn needs_grow(&self) -> bool {
+        match self {
+            IndexResource::U16(resource) => resource.needs_grow(),
+            IndexResource::U32(resource) => resource.needs_grow(),
+        }
+    }
+
+    pub fn sync_gpu(&mut self) {
+        match self {
+            IndexResource::U16(resource) => resource.sync_gpu(),
+            IndexResource::U32(resource) => resource.sync_gpu(),
+        }
+    }
+
+    pub fn sync_gpu_staged(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut wgpu::util::StagingBelt,
+    ) {
+        match self {
+            IndexResource::U16(resource) => resource.sync_gpu_staged(encoder, belt),
+            IndexResource::U32(resource) => resource.sync_gpu_staged(encoder, belt),
+        }
+    }
+}
+
+// Draw calls take the index range as u32s.
+fn check_index_count(count: usize) -> Result<()> {
+    if count > u32::MAX as usize {
+        bail!(
+            "Mesh has {} indices, more than the {} a draw call can take.",
+            count,
+            u32::MAX
+        );
+    }
+    Ok(())
+}
+
+impl ModelVertex {
+    // Offsets come from the fields, so they follow along when the struct changes.
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = [
+        wgpu::VertexAttribute {
+            offset: std::mem::offset_of!(ModelVertex, position) as wgpu::BufferAddress,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::offset_of!(ModelVertex, tex_coords) as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x2,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::offset_of!(ModelVertex, normal) as wgpu::BufferAddress,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::offset_of!(ModelVertex, tangent) as wgpu::BufferAddress,
+            shader_location: 3,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::offset_of!(ModelVertex, bitangent) as wgpu::BufferAddress,
+            shader_location: 4,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        // 5 to 13 are taken by InstanceRaw.
+        wgpu::VertexAttribute {
+            offset: std::mem::offset_of!(ModelVertex, color) as wgpu::BufferAddress,
+            shader_location: 14,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        // With the instance attributes that's the 16 a pipeline can have on downlevel
+        //  devices, there's no room for more.
+        wgpu::VertexAttribute {
+            offset: std::mem::offset_of!(ModelVertex, tex_coords_1) as wgpu::BufferAddress,
+            shader_location: 15,
+            format: wgpu::VertexFormat::Float32x2,
+        },
+    ];
+}
+
 impl Vertex for ModelVertex {
     fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        // A field missing from the attributes, or one with the wrong format, shows up as
+        //  a gap in the stride.
+        debug_assert_eq!(
+            Self::ATTRIBUTES
+                .iter()
+                .map(|attribute| attribute.format.size())
+                .sum::<wgpu::BufferAddress>(),
+            std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress,
+            "ModelVertex attributes don't cover the whole vertex"
+        );
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 3,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
-                    shader_location: 4,
-                    format: wgpu::VertexFormat::Float32x3,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::test_renderer;
+
+    use std::borrow::Cow;
+
+    // Writes a .glb with the given json and binary chunk to the temp directory.
+    fn write_glb(name: &str, json: serde_json::Value, bin: &[u8]) -> PathBuf {
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                length: 0,
+            },
+            json: Cow::Owned(json.to_string().into_bytes()),
+            bin: Some(Cow::Borrowed(bin)),
+        };
+        let path = std::env::temp_dir().join(name);
+        glb.to_writer(std::fs::File::create(&path).unwrap()).unwrap();
+        path
+    }
+
+    // A triangle with nothing but positions and no material still loads. It gets normals
+    //  from its face and the plain gray material.
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn untextured_glb() {
+        let renderer = test_renderer();
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 36 }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+            "accessors": [{
+                "bufferView": 0,
+                "componentType": 5126,
+                "count": 3,
+                "type": "VEC3",
+                "min": [0.0, 0.0, 0.0],
+                "max": [1.0, 1.0, 0.0],
+            }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+            "nodes": [{ "mesh": 0 }],
+            "scenes": [{ "nodes": [0] }],
+        });
+        let path = write_glb("untextured.glb", json, bytemuck::cast_slice(&positions));
+        let model = Model::load(&renderer, path).unwrap();
+
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.num_elements(), 3);
+        for vertex in mesh.vertex_resource.local_slice() {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+        let material = &model.materials[mesh.material];
+        assert_eq!(material.base_color_factor, [0.8, 0.8, 0.8, 1.0]);
+        assert_eq!(material.metallic_factor, 0.0);
+    }
+
+    // 30 positions that are all zero but for the two the sparse part of the accessor
+    //  sets, without a buffer view underneath. All 30 are read, not more.
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn sparse_positions() {
+        let renderer = test_renderer();
+        let mut bin: Vec<u8> = bytemuck::cast_slice(&[1u16, 2]).to_vec();
+        bin.extend_from_slice(bytemuck::cast_slice(&[1.0f32, 0.0, 0.0, 0.0, 1.0, 0.0]));
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 28 }],
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 4 },
+                { "buffer": 0, "byteOffset": 4, "byteLength": 24 },
+            ],
+            "accessors": [{
+                "componentType": 5126,
+                "count": 30,
+                "type": "VEC3",
+                "min": [0.0, 0.0, 0.0],
+                "max": [1.0, 1.0, 0.0],
+                "sparse": {
+                    "count": 2,
+                    "indices": { "bufferView": 0, "componentType": 5123 },
+                    "values": { "bufferView": 1 },
                 },
+            }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+            "nodes": [{ "mesh": 0 }],
+            "scenes": [{ "nodes": [0] }],
+        });
+        let model = Model::load(&renderer, write_glb("sparse.glb", json, &bin)).unwrap();
+
+        let vertices = model.meshes[0].vertex_resource.local_slice();
+        assert_eq!(vertices.len(), 30);
+        assert_eq!(vertices[1].position, [1.0, 0.0, 0.0]);
+        assert_eq!(vertices[2].position, [0.0, 1.0, 0.0]);
+        assert!(vertices[3..].iter().all(|vertex| vertex.position == [0.0; 3]));
+        assert_eq!(model.meshes[0].num_elements(), 30);
+    }
+
+    // The avocado as .gltf with its .bin and pngs next to it, copied somewhere that isn't
+    //  the working directory. It loads the same as the .glb, textures included.
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn split_gltf() {
+        let renderer = test_renderer();
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/res/avocado");
+        let dir = std::env::temp_dir().join("wgpu_practice_split_gltf");
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in &[
+            "Avocado.gltf",
+            "Avocado.bin",
+            "Avocado_baseColor.png",
+            "Avocado_normal.png",
+            "Avocado_roughnessMetallic.png",
+        ] {
+            std::fs::copy(source.join(file), dir.join(file)).unwrap();
+        }
+
+        let split = Model::load(&renderer, dir.join("Avocado.gltf")).unwrap();
+        let glb = Model::load(&renderer, source.join("Avocado.glb")).unwrap();
+        assert_eq!(split.meshes.len(), glb.meshes.len());
+        for (split, glb) in split.meshes.iter().zip(&glb.meshes) {
+            assert_eq!(split.num_elements(), glb.num_elements());
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(split.vertex_resource.local_slice()),
+                bytemuck::cast_slice::<_, u8>(glb.vertex_resource.local_slice())
+            );
+        }
+        let material = &split.materials[split.meshes[0].material];
+        assert!(material.diffuse_texture.is_some());
+        assert!(material.normal_texture.is_some());
+    }
+
+    // Ten materials with the same base color texture, one primitive each. The image is
+    //  uploaded once and all of them hold that upload.
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn materials_share_textures() {
+        let renderer = test_renderer();
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let mut bin: Vec<u8> = bytemuck::cast_slice(&positions).to_vec();
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(&mut bin, image::ImageOutputFormat::Png)
+            .unwrap();
+        let png_length = bin.len() - 36;
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let primitives: Vec<_> = (0..10)
+            .map(|material| {
+                serde_json::json!({ "attributes": { "POSITION": 0 }, "material": material })
+            })
+            .collect();
+        let material = serde_json::json!({
+            "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } },
+        });
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": bin.len() }],
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": png_length },
             ],
+            "accessors": [{
+                "bufferView": 0,
+                "componentType": 5126,
+                "count": 3,
+                "type": "VEC3",
+                "min": [0.0, 0.0, 0.0],
+                "max": [1.0, 1.0, 0.0],
+            }],
+            "images": [{ "bufferView": 1, "mimeType": "image/png" }],
+            "textures": [{ "source": 0 }],
+            "materials": vec![material; 10],
+            "meshes": [{ "primitives": primitives }],
+            "nodes": [{ "mesh": 0 }],
+            "scenes": [{ "nodes": [0] }],
+        });
+        let model = Model::load(&renderer, write_glb("shared_texture.glb", json, &bin)).unwrap();
+
+        assert_eq!(model.materials.len(), 10);
+        let first = model.materials[0].diffuse_texture.as_ref().unwrap();
+        for material in &model.materials {
+            assert!(Arc::ptr_eq(first, material.diffuse_texture.as_ref().unwrap()));
         }
     }
+
+    // The attributes line up with the fields of the #[repr(C)] struct and the locations
+    //  shader.wgsl reads them from, with the instance attributes in between.
+    #[test]
+    fn model_vertex_layout() {
+        let layout = ModelVertex::layout();
+        assert_eq!(layout.array_stride, 80);
+        let attributes: Vec<(wgpu::BufferAddress, u32)> = layout
+            .attributes
+            .iter()
+            .map(|attribute| (attribute.offset, attribute.shader_location))
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![(0, 0), (12, 1), (20, 2), (32, 3), (44, 4), (56, 14), (72, 15)]
+        );
+    }
 }
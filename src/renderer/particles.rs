@@ -0,0 +1,252 @@
+use super::instance::InstanceRaw;
+use super::resource::{Resource, ResourceType};
+use super::texture::Texture;
+use super::Renderer;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use cgmath::{Matrix4, Vector3};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: Vector3<f32>,
+    // Units per second.
+    pub velocity: Vector3<f32>,
+    // Can go above 1 for sparks that glow, the alpha fades to 0 over the lifetime.
+    pub color: [f32; 4],
+    // Half the width of the quad it's drawn as.
+    pub size: f32,
+    // Seconds until the particle is removed.
+    pub lifetime: f32,
+    age: f32,
+}
+
+impl Particle {
+    pub fn new(
+        position: Vector3<f32>,
+        velocity: Vector3<f32>,
+        color: [f32; 4],
+        size: f32,
+        lifetime: f32,
+    ) -> Self {
+        Self {
+            position,
+            velocity,
+            color,
+            size,
+            lifetime,
+            age: 0.0,
+        }
+    }
+
+    // Only the translation and scale are used by particles.wgsl, the inverse is there
+    //  because the instance layout has it.
+    fn to_raw(self) -> InstanceRaw {
+        let model = Matrix4::from_translation(self.position) * Matrix4::from_scale(self.size);
+        let inverse_model =
+            Matrix4::from_scale(1.0 / self.size) * Matrix4::from_translation(-self.position);
+        let [r, g, b, a] = self.color;
+        let fade = 1.0 - self.age / self.lifetime;
+        InstanceRaw {
+            model: model.into(),
+            inverse_model: inverse_model.into(),
+            color: [r, g, b, a * fade],
+        }
+    }
+}
+
+// Camera facing quads, one instance each. They're moved on the cpu and uploaded as
+//  InstanceRaw every update, so they're drawn like the instances of a model but with
+//  Renderer::particle_pipeline. Emitting past max_count drops the new particles.
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    instances: Resource<InstanceRaw>,
+    max_count: usize,
+    // Added to the velocity of every particle per second, like gravity.
+    pub acceleration: Vector3<f32>,
+    // State of the generator emit_burst scatters particles with.
+    seed: u32,
+}
+
+impl ParticleSystem {
+    pub fn new(renderer: &Renderer, max_count: usize) -> Self {
+        Self {
+            particles: Vec::with_capacity(max_count),
+            instances: Resource::new_sized(
+                Arc::clone(&renderer.device),
+                Arc::clone(&renderer.queue),
+                max_count.max(1),
+                ResourceType::Vertex,
+            ),
+            max_count,
+            acceleration: Vector3::new(0.0, 0.0, 0.0),
+            seed: 0x9e37_79b9,
+        }
+    }
+
+    pub fn emit(&mut self, particle: Particle) {
+        if self.particles.len() < self.max_count {
+            self.particles.push(particle);
+        }
+    }
+
+    // Emits count particles from position in random directions, at up to speed.
+    pub fn emit_burst(
+        &mut self,
+        position: Vector3<f32>,
+        count: usize,
+        speed: f32,
+        color: [f32; 4],
+        size: f32,
+        lifetime: f32,
+    ) {
+        for _ in 0..count {
+            // Uniform on the sphere: a random height and a random angle around it.
+            let z = self.random() * 2.0 - 1.0;
+            let angle = self.random() * std::f32::consts::TAU;
+            let radius = (1.0 - z * z).sqrt();
+            let direction = Vector3::new(radius * angle.cos(), radius * angle.sin(), z);
+            let velocity = direction * speed * (0.5 + self.random() * 0.5);
+            self.emit(Particle::new(position, velocity, color, size, lifetime));
+        }
+    }
+
+    // Moves and ages the particles, removes the ones past their lifetime and uploads
+    //  the rest.
+    pub fn update(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity += self.acceleration * dt;
+            particle.position += particle.velocity * dt;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+
+        self.instances.clear();
+        if self.particles.is_empty() {
+            return;
+        }
+        let raw = self
+            .particles
+            .iter()
+            .map(|particle| particle.to_raw())
+            .collect();
+        self.instances.add_to_buffer(raw);
+        self.instances.sync_gpu();
+    }
+
+    // Lowering it removes the oldest particles over the limit.
+    pub fn set_max_count(&mut self, max_count: usize) {
+        if self.particles.len() > max_count {
+            self.particles.drain(..self.particles.len() - max_count);
+        }
+        self.max_count = max_count;
+    }
+
+    pub fn max_count(&self) -> usize {
+        self.max_count
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    // Draws what the last update uploaded, returns how many particles that was.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+        uniforms: &'a wgpu::BindGroup,
+    ) -> u32 {
+        let count = self.instances.get_cpu_length() as u32;
+        if count == 0 {
+            return 0;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, uniforms, &[]);
+        render_pass.set_vertex_buffer(0, self.instances.get_gpu_buffer().slice(..));
+        render_pass.draw(0..4, 0..count);
+        count
+    }
+
+    // Xorshift, enough to scatter particles. Between 0 and 1.
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / u32::MAX as f32
+    }
+}
+
+// Draws ParticleSystems into the HDR target. The particles are added to what's behind them,
+//  so they don't need sorting, and they're depth tested but don't write depth. sample_count
+//  is the one of that target, see RendererBuilder::sample_count.
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    color_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+    reverse_z: bool,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Particle pipeline layout"),
+        bind_group_layouts: &[uniform_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let shader = device.create_shader_module(&wgpu::include_wgsl!("shader_src/particles.wgsl"));
+
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Particle render pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[InstanceRaw::layout()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            strip_index_format: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            clamp_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: depth_format,
+            depth_write_enabled: false,
+            depth_compare: Texture::depth_compare(reverse_z, true),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+    })
+}
@@ -0,0 +1,178 @@
+// Meshes built in code, handy for testing lighting and the scene graph without
+//  having to load a gltf.
+use crate::renderer::{
+    material::Material,
+    model::{Model, ModelSource, ModelVertex},
+    Renderer,
+};
+
+use cgmath::Vector3;
+
+impl Model {
+    // An axis aligned cube with edges of the given size, centered on the origin. Every face
+    //  has its own vertices so the normals stay flat, and is fully covered by the texture.
+    pub fn cube(renderer: &Renderer, size: f32) -> Self {
+        let half_size = size * 0.5;
+
+        // Normal and tangent (the u direction) per face, the v direction follows from them.
+        let faces = [
+            (Vector3::unit_x(), -Vector3::unit_z()),
+            (-Vector3::unit_x(), Vector3::unit_z()),
+            (Vector3::unit_y(), Vector3::unit_x()),
+            (-Vector3::unit_y(), Vector3::unit_x()),
+            (Vector3::unit_z(), Vector3::unit_x()),
+            (-Vector3::unit_z(), -Vector3::unit_x()),
+        ];
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for (normal, tangent) in faces.iter() {
+            let up = normal.cross(*tangent);
+            let first = vertices.len() as u32;
+            for (u, v) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].iter() {
+                let position =
+                    (normal + tangent * (u * 2.0 - 1.0) + up * (v * 2.0 - 1.0)) * half_size;
+                vertices.push(ModelVertex::new(
+                    position.into(),
+                    [*u, 1.0 - v],
+                    (*normal).into(),
+                    (*tangent).into(),
+                ));
+            }
+            // tangent x up == normal, so these wind counter clockwise seen from outside.
+            indices.extend_from_slice(&[first, first + 1, first + 2]);
+            indices.extend_from_slice(&[first + 1, first + 3, first + 2]);
+        }
+
+        let material = Material::from_factors(
+            "Cube material",
+            [1.0, 1.0, 1.0, 1.0],
+            &renderer.device,
+            &renderer.default_bind_group_layout,
+            &renderer.default_textures,
+        );
+
+        Model {
+            source: ModelSource::Cube { size },
+            ..Model::from_geometry(renderer, "Cube", vertices, indices, material)
+        }
+    }
+
+    // A sphere made of rings (top to bottom) and sectors (around the y axis), centered on the
+    //  origin. The texture wraps around it once.
+    pub fn uv_sphere(renderer: &Renderer, radius: f32, rings: u32, sectors: u32) -> Self {
+        let rings = rings.max(2);
+        let sectors = sectors.max(3);
+
+        let mut vertices = Vec::new();
+        for ring in 0..=rings {
+            let phi = std::f32::consts::PI * ring as f32 / rings as f32;
+            for sector in 0..=sectors {
+                let theta = 2.0 * std::f32::consts::PI * sector as f32 / sectors as f32;
+                let normal =
+                    Vector3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+                // Points along increasing theta, which is where u goes.
+                let tangent = Vector3::new(-theta.sin(), 0.0, theta.cos());
+                vertices.push(ModelVertex::new(
+                    (normal * radius).into(),
+                    [sector as f32 / sectors as f32, ring as f32 / rings as f32],
+                    normal.into(),
+                    tangent.into(),
+                ));
+            }
+        }
+
+        // The top and bottom rings collapse into a point, skip the triangles that would be
+        //  degenerate there.
+        let row = sectors + 1;
+        let mut indices = Vec::new();
+        for ring in 0..rings {
+            for sector in 0..sectors {
+                let a = ring * row + sector;
+                let b = a + 1;
+                let c = a + row;
+                let d = c + 1;
+                if ring != 0 {
+                    indices.extend_from_slice(&[a, b, c]);
+                }
+                if ring != rings - 1 {
+                    indices.extend_from_slice(&[b, d, c]);
+                }
+            }
+        }
+
+        let material = Material::from_factors(
+            "Sphere material",
+            [1.0, 1.0, 1.0, 1.0],
+            &renderer.device,
+            &renderer.default_bind_group_layout,
+            &renderer.default_textures,
+        );
+
+        Model {
+            source: ModelSource::UvSphere {
+                radius,
+                rings,
+                sectors,
+            },
+            ..Model::from_geometry(renderer, "Sphere", vertices, indices, material)
+        }
+    }
+
+    // A flat, square grid of size x size facing up (+y), centered on the origin and
+    //  split into subdivisions x subdivisions quads. The texture covers the whole plane.
+    pub fn plane(renderer: &Renderer, size: f32, subdivisions: u32) -> Self {
+        let subdivisions = subdivisions.max(1);
+        let half_size = size * 0.5;
+        let step = size / subdivisions as f32;
+
+        let mut vertices = Vec::new();
+        for z in 0..=subdivisions {
+            for x in 0..=subdivisions {
+                let u = x as f32 / subdivisions as f32;
+                let v = z as f32 / subdivisions as f32;
+                vertices.push(ModelVertex::new(
+                    [
+                        -half_size + x as f32 * step,
+                        0.0,
+                        -half_size + z as f32 * step,
+                    ],
+                    [u, v],
+                    [0.0, 1.0, 0.0],
+                    [1.0, 0.0, 0.0],
+                ));
+            }
+        }
+
+        // Two counter clockwise (seen from above) triangles per quad.
+        let row = subdivisions + 1;
+        let mut indices = Vec::new();
+        for z in 0..subdivisions {
+            for x in 0..subdivisions {
+                let a = z * row + x;
+                let b = a + 1;
+                let c = a + row;
+                let d = c + 1;
+                indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        let mut material = Material::from_factors(
+            "Plane material",
+            [0.5, 0.5, 0.5, 1.0],
+            &renderer.device,
+            &renderer.default_bind_group_layout,
+            &renderer.default_textures,
+        );
+        // A plane has no inside, so show it from below too.
+        material.double_sided = true;
+
+        Model {
+            source: ModelSource::Plane {
+                size,
+                subdivisions,
+            },
+            ..Model::from_geometry(renderer, "Plane", vertices, indices, material)
+        }
+    }
+}
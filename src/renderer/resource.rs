@@ -1,4 +1,6 @@
 use bytemuck::{Pod, Zeroable};
+use std::borrow::Cow;
+use std::future::Future;
 use std::ops::Range;
 use std::sync::Arc;
 use wgpu::util::DeviceExt;
@@ -8,6 +10,8 @@ use wgpu::util::DeviceExt;
 #[derive(Clone, Copy)]
 pub enum ResourceType {
     Vertex,
+    // Per instance vertex data, which compute passes can also write, see instance_compute.rs.
+    Instance,
     Index,
     Uniform,
 }
@@ -16,12 +20,23 @@ impl From<ResourceType> for wgpu::BufferUsages {
     fn from(item: ResourceType) -> wgpu::BufferUsages {
         match item {
             ResourceType::Vertex => wgpu::BufferUsages::VERTEX,
+            ResourceType::Instance => wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
             ResourceType::Index => wgpu::BufferUsages::INDEX,
             ResourceType::Uniform => wgpu::BufferUsages::UNIFORM,
         }
     }
 }
 
+// Buffer copies and writes have to come in multiples of wgpu::COPY_BUFFER_ALIGNMENT, which
+//  items smaller than that (u16 indices) don't always add up to. Sizes are rounded up.
+fn aligned(bytes: usize) -> wgpu::BufferAddress {
+    let alignment = wgpu::COPY_BUFFER_ALIGNMENT as usize;
+    (bytes.div_ceil(alignment) * alignment) as wgpu::BufferAddress
+}
+
+// The gpu buffer is shrunk once the cpu side uses less than 1/SHRINK_THRESHOLD of it.
+const SHRINK_THRESHOLD: usize = 4;
+
 // Abstraction that wraps GPU buffers.
 // Also holds Arc to the device and queue so we can conveniently
 //  perform operations on the resources on the GPU.
@@ -39,6 +54,13 @@ pub struct Resource<T: Pod + Zeroable> {
 }
 
 impl<T: Pod + Zeroable> Resource<T> {
+    // Besides its type specific usage a resource can be written to and read back.
+    fn usage(resource_type: ResourceType) -> wgpu::BufferUsages {
+        wgpu::BufferUsages::from(resource_type)
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC
+    }
+
     // Constructs a new Resource with a size
     pub fn new_sized(
         device: Arc<wgpu::Device>,
@@ -46,14 +68,14 @@ impl<T: Pod + Zeroable> Resource<T> {
         size: usize,
         resource_type: ResourceType,
     ) -> Self {
-        let usage = wgpu::BufferUsages::from(resource_type) | wgpu::BufferUsages::COPY_DST;
+        let usage = Self::usage(resource_type);
         let cpu_buffer: Vec<T> = Vec::with_capacity(size);
         let gpu_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Nicely sized buffer"),
-            size: (size * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            size: aligned(size * std::mem::size_of::<T>()),
             // Wether the mem block is accesible by ArrayBuffer (according to spec...?)
             mapped_at_creation: false,
-            usage: usage,
+            usage,
         });
 
         Self {
@@ -75,7 +97,7 @@ impl<T: Pod + Zeroable> Resource<T> {
     where
         T: Pod + Zeroable,
     {
-        let usage = wgpu::BufferUsages::from(resource_type) | wgpu::BufferUsages::COPY_DST;
+        let usage = Self::usage(resource_type);
         let size = cpu_buffer.len();
 
         let gpu_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -102,28 +124,171 @@ impl<T: Pod + Zeroable> Resource<T> {
         self.cpu_buffer.remove(id);
     }
 
+    // Empties the cpu side, the gpu buffer keeps its size for the next sync.
+    pub fn clear(&mut self) {
+        self.cpu_buffer.clear();
+    }
+
     pub fn sync_gpu(&mut self) {
-        if self.size < self.cpu_buffer.len() {
+        self.grow_gpu();
+
+        self.queue.write_buffer(
+            &self.gpu_buffer,
+            0 as wgpu::BufferAddress,
+            &self.padded_bytes(),
+        );
+    }
+
+    // Same as sync_gpu, but records the upload into the encoder as a copy from the staging
+    //  belt. queue.write_buffer goes through an internal staging buffer of its own which is
+    //  allocated per write, so prefer this path for large uploads (think thousands of
+    //  instances or a big mesh) that happen often. For small or one-off writes
+    //  sync_gpu is simpler and just as fast.
+    //  The belt has to be finished before the encoder is submitted, and recalled after.
+    pub fn sync_gpu_staged(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut wgpu::util::StagingBelt,
+    ) {
+        self.grow_gpu();
+
+        let bytes = self.padded_bytes();
+        if let Some(size) = wgpu::BufferSize::new(bytes.len() as wgpu::BufferAddress) {
+            belt.write_buffer(encoder, &self.gpu_buffer, 0, size, &self.device)
+                .copy_from_slice(&bytes);
+        }
+    }
+
+    // Makes room for additional items on top of what the cpu side holds, so adding them
+    //  doesn't reallocate the gpu buffer on the next sync. Grows the same way sync_gpu does
+    //  and never shrinks, the items already there are uploaded to the new buffer.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.cpu_buffer.len() + additional;
+        self.cpu_buffer.reserve(additional);
+        if needed <= self.size {
+            return;
+        }
+
+        self.grow_to(needed);
+        if !self.cpu_buffer.is_empty() {
+            self.queue.write_buffer(
+                &self.gpu_buffer,
+                0 as wgpu::BufferAddress,
+                &self.padded_bytes(),
+            );
+        }
+    }
+
+    fn grow_gpu(&mut self) {
+        if self.needs_grow() {
             dbg!("adjusting buffer size");
-            // Recreate the gpu_buffer with five time the size to prevent overflow.
-            //  TODO: Needs to be something better... because crash if we add >5 things on the first frame...
-            self.size *= 5;
-            self.gpu_buffer.destroy();
-            let usage = wgpu::BufferUsages::from(self.resource_type) | wgpu::BufferUsages::COPY_DST;
-
-            self.gpu_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some("Nicely sized buffer"),
-                size: (self.size * std::mem::size_of::<T>()) as wgpu::BufferAddress,
-                mapped_at_creation: false,
-                usage: usage,
-            });
+            self.grow_to(self.cpu_buffer.len());
+        }
+    }
+
+    // Recreate the gpu_buffer with five time the size to prevent overflow, or exactly big
+    //  enough when even that doesn't fit.
+    fn grow_to(&mut self, needed: usize) {
+        self.size = (self.size * 5).max(needed);
+        self.recreate_gpu_buffer();
+    }
+
+    // Reallocates a smaller gpu buffer when the cpu side dropped below a quarter of its size,
+    //  and uploads the data to it. The new buffer is twice the current length, so it takes
+    //  doubling or quartering the item count before reallocating again. This prevents
+    //  thrashing when the count keeps going up and down (e.g. spawning/despawning).
+    //  Returns whether the buffer was reallocated.
+    pub fn shrink_gpu(&mut self) -> bool {
+        let length = self.cpu_buffer.len();
+        // Keep at least room for one, empty buffers can't be bound. A buffer that small
+        //  already has nothing to give back.
+        let size = (length * 2).max(1);
+        if length * SHRINK_THRESHOLD >= self.size || self.size <= size {
+            return false;
         }
 
+        self.size = size;
+        self.recreate_gpu_buffer();
         self.queue.write_buffer(
             &self.gpu_buffer,
             0 as wgpu::BufferAddress,
-            bytemuck::cast_slice(&self.cpu_buffer),
+            &self.padded_bytes(),
         );
+        true
+    }
+
+    // The cpu side as bytes, with zeroes after it up to the next aligned size.
+    fn padded_bytes(&self) -> Cow<'_, [u8]> {
+        let bytes: &[u8] = bytemuck::cast_slice(&self.cpu_buffer);
+        let size = aligned(bytes.len()) as usize;
+        if size == bytes.len() {
+            Cow::Borrowed(bytes)
+        } else {
+            let mut padded = bytes.to_vec();
+            padded.resize(size, 0);
+            Cow::Owned(padded)
+        }
+    }
+
+    // Replaces the gpu buffer with an empty one of the current size.
+    fn recreate_gpu_buffer(&mut self) {
+        self.gpu_buffer.destroy();
+        let usage = Self::usage(self.resource_type);
+
+        self.gpu_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Nicely sized buffer"),
+            size: aligned(self.size * std::mem::size_of::<T>()),
+            mapped_at_creation: false,
+            usage,
+        });
+    }
+
+    // Copies the gpu buffer back to the cpu, for debugging or checking what actually landed
+    //  on the gpu after a sync. Reads as many items as the cpu buffer holds (as far as they
+    //  fit in the gpu buffer). On native the future only resolves once the device is polled,
+    //  e.g. with device.poll(wgpu::Maintain::Wait).
+    pub fn read_back(&self) -> impl Future<Output = Vec<T>> {
+        let length = self.cpu_buffer.len().min(self.size);
+        let size = aligned(length * std::mem::size_of::<T>());
+
+        let read_back = if size > 0 {
+            let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Read back buffer"),
+                size,
+                mapped_at_creation: false,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            });
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Read back encoder"),
+                });
+            encoder.copy_buffer_to_buffer(&self.gpu_buffer, 0, &staging_buffer, 0, size);
+            self.queue.submit(std::iter::once(encoder.finish()));
+
+            // Request the mapping right away rather than on the first poll of the future,
+            //  otherwise polling the device before awaiting would have nothing to wait for.
+            let mapping = staging_buffer.slice(..).map_async(wgpu::MapMode::Read);
+            Some((staging_buffer, mapping))
+        } else {
+            None
+        };
+
+        async move {
+            match read_back {
+                Some((staging_buffer, mapping)) => {
+                    mapping.await.expect("Failed to map read back buffer.");
+                    let data =
+                        bytemuck::cast_slice::<u8, T>(&staging_buffer.slice(..).get_mapped_range())
+                            [..length]
+                            .to_vec();
+                    staging_buffer.unmap();
+                    data
+                }
+                None => vec![],
+            }
+        }
     }
 
     pub fn get_gpu_buffer(&self) -> &wgpu::Buffer {
@@ -132,7 +297,15 @@ impl<T: Pod + Zeroable> Resource<T> {
 
     // I'd say there is a good to fair chance that there would be situations where we want to
     //  sync an interval of instances, but not all.
+    //  Offset is in bytes into the gpu buffer.
     pub fn _partial_sync_gpu(&mut self, range: Range<usize>, offset: usize) {
+        // If the cpu side outgrew the gpu buffer it has to be recreated, which means
+        //  a full sync anyway.
+        if self.needs_grow() {
+            self.sync_gpu();
+            return;
+        }
+
         self.queue.write_buffer(
             &self.gpu_buffer,
             offset as wgpu::BufferAddress,
@@ -157,11 +330,115 @@ impl<T: Pod + Zeroable> Resource<T> {
         }
     }
 
+    // The whole local resource, e.g. to write it out somewhere.
+    pub fn local_slice(&self) -> &[T] {
+        &self.cpu_buffer
+    }
+
     pub fn get_cpu_length(&self) -> usize {
         self.cpu_buffer.len()
     }
 
+    pub fn get_cpu_byte_length(&self) -> usize {
+        self.cpu_buffer.len() * std::mem::size_of::<T>()
+    }
+
     pub fn _get_gpu_length(&self) -> usize {
         self.size
     }
+
+    // What the gpu buffer takes up, which can be more than the cpu side holds. See
+    //  Renderer::gpu_memory_report.
+    pub fn get_gpu_byte_length(&self) -> usize {
+        aligned(self.size * std::mem::size_of::<T>()) as usize
+    }
+
+    // Whether the cpu side outgrew the gpu buffer, so the next sync reallocates it.
+    pub fn needs_grow(&self) -> bool {
+        self.size < self.cpu_buffer.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::test_renderer;
+
+    fn read_back(device: &wgpu::Device, resource: &Resource<u32>) -> Vec<u32> {
+        let data = resource.read_back();
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(data)
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn grows_five_times_and_keeps_data() {
+        let renderer = test_renderer();
+        let (device, queue) = (renderer.device.clone(), renderer.queue.clone());
+        let mut resource = Resource::new_sized(device.clone(), queue, 1, ResourceType::Vertex);
+        let mut sizes = vec![resource._get_gpu_length()];
+        for item in 0..100u32 {
+            resource.add_to_buffer(vec![item]);
+            let grows = resource.needs_grow();
+            resource.sync_gpu();
+            if grows {
+                sizes.push(resource._get_gpu_length());
+                assert_eq!(
+                    read_back(&device, &resource),
+                    (0..=item).collect::<Vec<_>>()
+                );
+            }
+        }
+        assert_eq!(sizes, vec![1, 5, 25, 125]);
+        assert_eq!(read_back(&device, &resource), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn shrink_gpu_once() {
+        let renderer = test_renderer();
+        let (device, queue) = (renderer.device.clone(), renderer.queue.clone());
+        let mut resource = Resource::new_sized(device.clone(), queue, 100, ResourceType::Vertex);
+        resource.add_to_buffer(vec![1u32, 2]);
+        assert!(resource.shrink_gpu());
+        assert_eq!(resource._get_gpu_length(), 4);
+        assert!(!resource.shrink_gpu());
+        assert_eq!(read_back(&device, &resource), vec![1, 2]);
+
+        // Emptied, it goes down to room for one and stays there.
+        resource.clear();
+        assert!(resource.shrink_gpu());
+        assert_eq!(resource._get_gpu_length(), 1);
+        assert!(!resource.shrink_gpu());
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn local_at_out_of_bounds() {
+        let renderer = test_renderer();
+        let (device, queue) = (renderer.device.clone(), renderer.queue.clone());
+        let mut resource =
+            Resource::new_with_data(device, queue, vec![1u32, 2, 3], ResourceType::Vertex);
+        assert_eq!(resource.local_at(2), Some(3));
+        assert_eq!(resource.local_at(3), None);
+        assert!(resource._mut_local_at(2).is_some());
+        assert!(resource._mut_local_at(3).is_none());
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn remove_keeps_order() {
+        let renderer = test_renderer();
+        let (device, queue) = (renderer.device.clone(), renderer.queue.clone());
+        let mut resource = Resource::new_with_data(
+            device.clone(),
+            queue,
+            vec![1u32, 2, 3, 4],
+            ResourceType::Vertex,
+        );
+        resource._remove_from_buffer(1);
+        assert_eq!(resource.local_slice(), &[1, 3, 4]);
+        resource.sync_gpu();
+        assert_eq!(read_back(&device, &resource), vec![1, 3, 4]);
+    }
 }
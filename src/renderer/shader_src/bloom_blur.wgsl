@@ -0,0 +1,58 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[block]]
+struct BloomUniform {
+    threshold: f32;
+    intensity: f32;
+    filter_radius: f32;
+    aspect_ratio: f32;
+};
+
+// The level of the mip chain being downsampled or upsampled, see bloom.rs.
+[[group(0), binding(0)]] var t_source: texture_2d<f32>;
+[[group(0), binding(1)]] var s_source: sampler;
+[[group(0), binding(2)]] var<uniform> bloom: BloomUniform;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, same as the skybox.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = position * vec2<f32>(0.5, -0.5) + 0.5;
+    return out;
+}
+
+// Halves the level. Each of the 4 taps lands between source pixels, so the linear sampler
+//  averages 4x4 of them.
+[[stage(fragment)]]
+fn fs_downsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let color = (textureSample(t_source, s_source, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb) * 0.25;
+    return vec4<f32>(color, 1.0);
+}
+
+// Blurs the smaller level with a 3x3 tent onto the larger one, which adds it to what's
+//  there. The radius is a fraction of the screen height rather than a number of pixels, so
+//  the bloom spreads just as far at any resolution.
+[[stage(fragment)]]
+fn fs_upsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let r = vec2<f32>(bloom.filter_radius / bloom.aspect_ratio, bloom.filter_radius);
+    var color = textureSample(t_source, s_source, in.uv).rgb * 4.0;
+    color = color + (textureSample(t_source, s_source, in.uv + vec2<f32>(-r.x, 0.0)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(r.x, 0.0)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(0.0, -r.y)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(0.0, r.y)).rgb) * 2.0;
+    color = color + textureSample(t_source, s_source, in.uv + vec2<f32>(-r.x, -r.y)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(r.x, -r.y)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(-r.x, r.y)).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(r.x, r.y)).rgb;
+    return vec4<f32>(color / 16.0, 1.0);
+}
@@ -0,0 +1,47 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[block]]
+struct BloomUniform {
+    threshold: f32;
+    intensity: f32;
+    filter_radius: f32;
+    aspect_ratio: f32;
+};
+
+// The HDR target the scene was drawn to.
+[[group(0), binding(0)]] var t_source: texture_2d<f32>;
+[[group(0), binding(1)]] var s_source: sampler;
+[[group(0), binding(2)]] var<uniform> bloom: BloomUniform;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, same as the skybox.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = position * vec2<f32>(0.5, -0.5) + 0.5;
+    return out;
+}
+
+// Keeps what's brighter than the threshold, with a soft knee so pixels don't pop in
+//  and out of the bloom as they cross it.
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // The target is half the size of the source, average the 2x2 pixels under this one.
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let color = (textureSample(t_source, s_source, in.uv + vec2<f32>(-0.5, -0.5) * texel).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(0.5, -0.5) * texel).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(-0.5, 0.5) * texel).rgb
+        + textureSample(t_source, s_source, in.uv + vec2<f32>(0.5, 0.5) * texel).rgb) * 0.25;
+
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = bloom.threshold * 0.5;
+    var soft = clamp(brightness - bloom.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.0001);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
@@ -0,0 +1,39 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[block]]
+struct BloomUniform {
+    threshold: f32;
+    intensity: f32;
+    filter_radius: f32;
+    aspect_ratio: f32;
+};
+
+// The HDR target the scene was drawn to.
+[[group(0), binding(0)]] var t_scene: texture_2d<f32>;
+[[group(0), binding(1)]] var s_scene: sampler;
+[[group(0), binding(2)]] var<uniform> bloom: BloomUniform;
+// The largest level of the mip chain, everything smaller has been added into it.
+[[group(0), binding(3)]] var t_bloom: texture_2d<f32>;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, same as the skybox.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = position * vec2<f32>(0.5, -0.5) + 0.5;
+    return out;
+}
+
+// Copies the scene to the surface with the bloom added on top. Intensity is 0 while bloom
+//  is off.
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let scene = textureSample(t_scene, s_scene, in.uv).rgb;
+    let bloom_color = textureSample(t_bloom, s_scene, in.uv).rgb;
+    return vec4<f32>(scene + bloom_color * bloom.intensity, 1.0);
+}
@@ -0,0 +1,72 @@
+// Copies the instances whose bounds are (partly) in view to the front of the visible
+//  buffer, counting them for the indirect draws, see culling.rs. Same test as
+//  outside_frustum there, but on the corners of the instance's own box.
+struct InstanceRaw {
+    model: mat4x4<f32>;
+    inverse_model: mat4x4<f32>;
+    color: vec4<f32>;
+};
+
+[[block]]
+struct Instances {
+    instances: array<InstanceRaw>;
+};
+
+[[block]]
+struct CullParams {
+    view_proj: mat4x4<f32>;
+    // The model space bounds, w is unused.
+    bounds_min: vec4<f32>;
+    bounds_max: vec4<f32>;
+};
+
+[[block]]
+struct Counter {
+    count: atomic<u32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: CullParams;
+// Bound to exactly the model's instances.
+[[group(0), binding(1)]] var<storage, read> instances: Instances;
+[[group(0), binding(2)]] var<storage, read_write> visible: Instances;
+[[group(0), binding(3)]] var<storage, read_write> counter: Counter;
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let i = id.x;
+    if (i >= arrayLength(&instances.instances)) {
+        return;
+    }
+    let instance = instances.instances[i];
+    let transform = params.view_proj * instance.model;
+    let min = params.bounds_min.xyz;
+    let max = params.bounds_max.xyz;
+
+    // How many corners lie outside each clip plane, -w <= x, y <= w and 0 <= z <= w.
+    var left = 0u;
+    var right = 0u;
+    var bottom = 0u;
+    var top = 0u;
+    var near = 0u;
+    var far = 0u;
+    for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+        let p = vec3<f32>(
+            select(min.x, max.x, (corner & 1u) != 0u),
+            select(min.y, max.y, (corner & 2u) != 0u),
+            select(min.z, max.z, (corner & 4u) != 0u),
+        );
+        let c = transform * vec4<f32>(p, 1.0);
+        left = left + select(0u, 1u, c.x < -c.w);
+        right = right + select(0u, 1u, c.x > c.w);
+        bottom = bottom + select(0u, 1u, c.y < -c.w);
+        top = top + select(0u, 1u, c.y > c.w);
+        near = near + select(0u, 1u, c.z < 0.0);
+        far = far + select(0u, 1u, c.z > c.w);
+    }
+    if (left == 8u || right == 8u || bottom == 8u || top == 8u || near == 8u || far == 8u) {
+        return;
+    }
+
+    let slot = atomicAdd(&counter.count, 1u);
+    visible.instances[slot] = instance;
+}
@@ -0,0 +1,224 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    view_position: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    exposure: f32;
+    gamma: f32;
+    far_depth: f32;
+    // See Renderer::set_debug_unlit.
+    debug_unlit: u32;
+    // A DebugView, see Renderer::set_debug_view.
+    debug_view: u32;
+};
+
+[[block]]
+struct Light {
+    position: vec3<f32>;
+    intensity: f32;
+    color: vec3<f32>;
+    // 0 is no cutoff.
+    range: f32;
+    // Multiplies the environment's light, see Renderer::set_ambient.
+    ambient_color: vec3<f32>;
+    ambient_strength: f32;
+};
+
+// Written by gbuffer.wgsl.
+[[group(0), binding(0)]] var t_albedo: texture_2d<f32>;
+[[group(0), binding(1)]] var t_normal: texture_2d<f32>;
+[[group(0), binding(2)]] var t_position: texture_2d<f32>;
+[[group(0), binding(3)]] var t_emissive: texture_2d<f32>;
+// Written by ssao_blur.wgsl, all white while SSAO is off.
+[[group(0), binding(4)]] var t_ambient_occlusion: texture_2d<f32>;
+
+[[group(1), binding(0)]] var<uniform> uniforms: Uniforms;
+
+[[group(2), binding(0)]] var<uniform> light: Light;
+// Prefiltered from the environment cubemap by environment_prefilter.wgsl.
+[[group(2), binding(1)]] var t_irradiance: texture_cube<f32>;
+[[group(2), binding(2)]] var t_specular: texture_cube<f32>;
+[[group(2), binding(3)]] var s_environment: sampler;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, same as the skybox.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+let PI: f32 = 3.14159265;
+
+// Cook-Torrance with a GGX distribution, the Smith-Schlick geometry term and Schlick's
+//  fresnel, plus a lambertian diffuse that metals don't have. Gives the light reflected
+//  towards view_dir per unit of light color.
+fn cook_torrance(
+    normal: vec3<f32>,
+    light_dir: vec3<f32>,
+    view_dir: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let half_dir = normalize(view_dir + light_dir);
+    let n_dot_l = max(dot(normal, light_dir), 0.0);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+    let h_dot_v = max(dot(half_dir, view_dir), 0.0);
+
+    // Gltf roughness is perceptual, squared it's the alpha of GGX. Kept off zero, a
+    //  perfect mirror has no highlight to speak of for a point light.
+    let alpha = max(roughness * roughness, 0.002);
+    let alpha2 = alpha * alpha;
+    let d_denom = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d_denom * d_denom);
+
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+    // Dielectrics reflect about 4% head on, metals tint the reflection with their color.
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let fresnel = f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - h_dot_v, 5.0);
+
+    let specular = fresnel * distribution * geometry / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic) * albedo / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// What reaches position from the point light, falling off with the square of the distance.
+//  With a range it's faded to 0 at that distance, with the window of KHR_lights_punctual.
+fn radiance(light_position: vec3<f32>, light_color: vec3<f32>, intensity: f32, range: f32, position: vec3<f32>) -> vec3<f32> {
+    let to_light = light_position - position;
+    let distance_squared = dot(to_light, to_light);
+    var cutoff = 1.0;
+    if (range > 0.0) {
+        let ratio_squared = distance_squared / (range * range);
+        cutoff = clamp(1.0 - ratio_squared * ratio_squared, 0.0, 1.0);
+        cutoff = cutoff * cutoff;
+    }
+    return light_color * intensity * cutoff / max(distance_squared, 0.0001);
+}
+
+// The lighting of shader.wgsl, for a single light.
+fn shade(
+    light_position: vec3<f32>,
+    light_color: vec3<f32>,
+    light_intensity: f32,
+    light_range: f32,
+    albedo: vec3<f32>,
+    normal: vec3<f32>,
+    position: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let light_dir = normalize(light_position - position);
+    let view_dir = normalize(uniforms.view_position - position);
+
+    return radiance(light_position, light_color, light_intensity, light_range, position) * cook_torrance(normal, light_dir, view_dir, albedo, metallic, roughness);
+}
+
+// Mip count of t_specular, see environment.rs.
+let SPECULAR_MIP_LEVELS: f32 = 5.0;
+
+// Light from the environment all around. The split of specular and diffuse comes from
+//  Karis' analytic fit of the environment BRDF, so there's no lookup texture.
+fn ambient(
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let c0 = vec4<f32>(-1.0, -0.0275, -0.572, 0.022);
+    let c1 = vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let r = roughness * c0 + c1;
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let scale_bias = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    let specular_color = f0 * scale_bias.x + scale_bias.y;
+
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, normal, 0.0).rgb;
+    let reflect_dir = reflect(-view_dir, normal);
+    let reflection = textureSampleLevel(t_specular, s_environment, reflect_dir, roughness * (SPECULAR_MIP_LEVELS - 1.0)).rgb;
+
+    let diffuse = (vec3<f32>(1.0) - specular_color) * (1.0 - metallic) * albedo * irradiance;
+    return (diffuse + reflection * specular_color) * light.ambient_color * light.ambient_strength;
+}
+
+// Applies the exposure and gamma of Renderer::set_exposure/set_gamma. The surface is sRGB
+//  and already encodes for a gamma of about 2.2, so that much is taken back out again.
+//  The default gamma of 2.2 leaves the image as is.
+fn exposure_gamma(color: vec3<f32>) -> vec3<f32> {
+    return pow(max(color * uniforms.exposure, vec3<f32>(0.0)), vec3<f32>(2.2 / uniforms.gamma));
+}
+
+// What Renderer::set_debug_view shows, the views are numbered as in DebugView. The G-buffer
+//  has no tangents or uvs, gbuffer.wgsl writes those in place of the albedo for their views.
+fn debug_color(normal: vec3<f32>, position: vec3<f32>, albedo: vec3<f32>, occlusion: f32) -> vec3<f32> {
+    let view = uniforms.debug_view;
+    if (view == 1u) {
+        return normal * 0.5 + 0.5;
+    }
+    if (view == 4u) {
+        let distance_to_camera = distance(uniforms.view_position, position);
+        return vec3<f32>(distance_to_camera / (distance_to_camera + 10.0));
+    }
+    if (view == 6u) {
+        return vec3<f32>(occlusion);
+    }
+    return albedo;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let position = textureLoad(t_position, coords, 0);
+    // Nothing was drawn here, leave the skybox or clear color.
+    if (position.w == 0.0) {
+        discard;
+    }
+    let albedo = textureLoad(t_albedo, coords, 0);
+    let normal = textureLoad(t_normal, coords, 0);
+    let emissive = textureLoad(t_emissive, coords, 0);
+    // gbuffer.wgsl adds 2 to the highlight of unlit materials, they keep their base color.
+    let unlit = emissive.a >= 2.0;
+    let highlight = emissive.a - select(0.0, 2.0, unlit);
+    // The material's own occlusion, times what SSAO found.
+    let ambient_occlusion = (position.w - 1.0) * textureLoad(t_ambient_occlusion, coords, 0).r;
+
+    if (uniforms.debug_view != 0u) {
+        return vec4<f32>(debug_color(normal.xyz, position.xyz, albedo.rgb, ambient_occlusion), 1.0);
+    }
+    if (unlit || uniforms.debug_unlit != 0u) {
+        let color = mix(albedo.rgb, vec3<f32>(1.0, 0.6, 0.0), highlight * 0.4);
+        return vec4<f32>(exposure_gamma(color), 1.0);
+    }
+
+    let view_dir = normalize(uniforms.view_position - position.xyz);
+    var result = ambient(normal.xyz, view_dir, albedo.rgb, albedo.a, normal.w) * ambient_occlusion;
+
+    // Every light adds its contribution here. There's only the one so far.
+    result = result + shade(
+        light.position,
+        light.color,
+        light.intensity,
+        light.range,
+        albedo.rgb,
+        normal.xyz,
+        position.xyz,
+        albedo.a,
+        normal.w,
+    );
+    result = result + emissive.rgb;
+    result = mix(result, vec3<f32>(1.0, 0.6, 0.0), highlight * 0.4);
+
+    return vec4<f32>(exposure_gamma(result), 1.0);
+}
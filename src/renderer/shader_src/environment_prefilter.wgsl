@@ -0,0 +1,116 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    // -1..1 across the face, y down like the rows of the texture.
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[block]]
+struct Prefilter {
+    face: u32;
+    roughness: f32;
+};
+
+[[group(0), binding(0)]] var t_environment: texture_cube<f32>;
+[[group(0), binding(1)]] var s_environment: sampler;
+[[group(0), binding(2)]] var<uniform> prefilter: Prefilter;
+
+let PI: f32 = 3.14159265;
+let SPECULAR_SAMPLES: u32 = 128u;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, same as the skybox.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x, -position.y);
+    return out;
+}
+
+// The direction a texel of a cube face looks in, faces in the +X, -X, +Y, -Y, +Z, -Z
+//  order of the array layers.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    var direction: vec3<f32>;
+    if (face == 0u) {
+        direction = vec3<f32>(1.0, -uv.y, -uv.x);
+    } elseif (face == 1u) {
+        direction = vec3<f32>(-1.0, -uv.y, uv.x);
+    } elseif (face == 2u) {
+        direction = vec3<f32>(uv.x, 1.0, uv.y);
+    } elseif (face == 3u) {
+        direction = vec3<f32>(uv.x, -1.0, -uv.y);
+    } elseif (face == 4u) {
+        direction = vec3<f32>(uv.x, -uv.y, 1.0);
+    } else {
+        direction = vec3<f32>(-uv.x, -uv.y, -1.0);
+    }
+    return normalize(direction);
+}
+
+// Two vectors perpendicular to normal and each other.
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(normal.y) > 0.999);
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+// The light reaching a surface facing the texel's direction, divided by pi so it can be
+//  multiplied with the albedo as is. Walks the hemisphere in even steps.
+[[stage(fragment)]]
+fn fs_irradiance(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let frame = tangent_frame(face_direction(prefilter.face, in.uv));
+    let step = 0.05;
+
+    var irradiance = vec3<f32>(0.0);
+    var samples = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi = phi + step) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta = theta + step) {
+            let direction = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let color = textureSampleLevel(t_environment, s_environment, frame * direction, 0.0).rgb;
+            // Weighed by the cosine of the angle to the normal, and the sine for the smaller
+            //  patches close to it.
+            irradiance = irradiance + color * cos(theta) * sin(theta);
+            samples = samples + 1.0;
+        }
+    }
+    return vec4<f32>(PI * irradiance / samples, 1.0);
+}
+
+// Van der Corput sequence, the second coordinate of a Hammersley set.
+fn radical_inverse(i: u32) -> f32 {
+    var bits = (i << 16u) | (i >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+// The reflection of the environment off a surface of the given roughness, looked at head
+//  on. Samples halfway vectors the way the GGX distribution of shader.wgsl spreads them.
+[[stage(fragment)]]
+fn fs_specular(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let normal = face_direction(prefilter.face, in.uv);
+    let frame = tangent_frame(normal);
+    let alpha = prefilter.roughness * prefilter.roughness;
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SPECULAR_SAMPLES; i = i + 1u) {
+        let phi = 2.0 * PI * f32(i) / f32(SPECULAR_SAMPLES);
+        let xi = radical_inverse(i);
+        let cos_theta = sqrt((1.0 - xi) / (1.0 + (alpha * alpha - 1.0) * xi));
+        let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        let half_dir = frame * vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+        let light_dir = 2.0 * dot(normal, half_dir) * half_dir - normal;
+
+        let n_dot_l = dot(normal, light_dir);
+        if (n_dot_l > 0.0) {
+            color = color + textureSampleLevel(t_environment, s_environment, light_dir, 0.0).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    return vec4<f32>(color / weight, 1.0);
+}
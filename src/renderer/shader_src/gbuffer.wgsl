@@ -0,0 +1,190 @@
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tangent: vec3<f32>;
+    [[location(4)]] bitangent: vec3<f32>;
+    // After the instance locations.
+    [[location(14)]] color: vec4<f32>;
+    // A copy of tex_coords for meshes with one set.
+    [[location(15)]] tex_coords_1: vec2<f32>;
+};
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(9)]] inverse_model_matrix_0: vec4<f32>;
+    [[location(10)]] inverse_model_matrix_1: vec4<f32>;
+    [[location(11)]] inverse_model_matrix_2: vec4<f32>;
+    [[location(12)]] inverse_model_matrix_3: vec4<f32>;
+    [[location(13)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] position: vec3<f32>;
+    [[location(2)]] tangent: vec3<f32>;
+    [[location(3)]] bitangent: vec3<f32>;
+    [[location(4)]] normal: vec3<f32>;
+    [[location(5)]] instance_color: vec4<f32>;
+    // 1.0 for the selected instance, 0.0 otherwise.
+    [[location(6)]] highlight: f32;
+    [[location(7)]] tex_coords_1: vec2<f32>;
+};
+
+// Everything the lighting pass needs, in world space. See deferred.rs for the formats.
+struct GBufferOutput {
+    // Base color, metallic in alpha.
+    [[location(0)]] albedo: vec4<f32>;
+    // Roughness in w.
+    [[location(1)]] normal: vec4<f32>;
+    // Material ambient occlusion plus one in w, so w is only 0.0 where nothing was drawn.
+    [[location(2)]] position: vec4<f32>;
+    // Selection highlight in alpha, plus 2 for unlit materials.
+    [[location(3)]] emissive: vec4<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    view_position: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    exposure: f32;
+    gamma: f32;
+    far_depth: f32;
+    debug_unlit: u32;
+    // A DebugView, see Renderer::set_debug_view.
+    debug_view: u32;
+};
+
+[[block]]
+struct MaterialUniform {
+    base_color_factor: vec4<f32>;
+    emissive_factor: vec3<f32>;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    occlusion_strength: f32;
+    // A bit per texture, set for the ones sampled with tex_coords_1. See tex_coords.
+    tex_coord_sets: u32;
+    // Non-zero to skip the lighting, see Material::unlit.
+    unlit: u32;
+};
+
+[[group(0), binding(0)]] var t_diffuse: texture_2d<f32>;
+[[group(0), binding(1)]] var s_diffuse: sampler;
+[[group(0), binding(2)]] var t_normal: texture_2d<f32>;
+[[group(0), binding(3)]] var s_normal: sampler;
+[[group(0), binding(4)]] var t_emissive: texture_2d<f32>;
+[[group(0), binding(5)]] var s_emissive: sampler;
+[[group(0), binding(6)]] var t_metallic_roughness: texture_2d<f32>;
+[[group(0), binding(7)]] var s_metallic_roughness: sampler;
+[[group(0), binding(8)]] var t_occlusion: texture_2d<f32>;
+[[group(0), binding(9)]] var s_occlusion: sampler;
+[[group(0), binding(10)]] var<uniform> material: MaterialUniform;
+
+[[group(1), binding(0)]] var<uniform> uniforms: Uniforms;
+
+[[block]]
+struct DrawConstants {
+    // Instance to highlight, -1 for none.
+    selected_instance: i32;
+};
+
+// Same as in shader.wgsl.
+var<push_constant> draw: DrawConstants;
+
+[[stage(vertex)]]
+fn vs_main(
+    vertex: VertexInput,
+    instance: InstanceInput,
+    [[builtin(instance_index)]] instance_index: u32,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let inverse_model_matrix = mat4x4<f32>(
+        instance.inverse_model_matrix_0,
+        instance.inverse_model_matrix_1,
+        instance.inverse_model_matrix_2,
+        instance.inverse_model_matrix_3,
+    );
+
+    let normal_matrix = transpose(mat3x3<f32>(
+        inverse_model_matrix[0].xyz,
+        inverse_model_matrix[1].xyz,
+        inverse_model_matrix[2].xyz,
+    ));
+
+    let model_space = model_matrix * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = vertex.tex_coords;
+    out.tex_coords_1 = vertex.tex_coords_1;
+    // The vertex color tints the same way, so they go to the fragment shader as one.
+    out.instance_color = instance.color * vertex.color;
+    out.highlight = select(0.0, 1.0, i32(instance_index) == draw.selected_instance);
+    // Unlike shader.wgsl the lighting happens later in world space, so pass the tangent
+    //  frame along instead of moving everything into tangent space.
+    out.position = model_space.xyz;
+    out.tangent = normal_matrix * vertex.tangent;
+    out.bitangent = normal_matrix * vertex.bitangent;
+    out.normal = normal_matrix * vertex.normal;
+    out.clip_position = uniforms.view_proj * model_space;
+    return out;
+}
+
+// The uvs the material samples a texture with. Slots go in binding order: diffuse, normal,
+//  emissive, metallic roughness and occlusion.
+fn tex_coords(slot: u32, tex_coords_0: vec2<f32>, tex_coords_1: vec2<f32>) -> vec2<f32> {
+    return select(tex_coords_0, tex_coords_1, (material.tex_coord_sets & (1u << slot)) != 0u);
+}
+
+[[stage(fragment)]]
+fn fs_main(
+    in: VertexOutput,
+    [[builtin(front_facing)]] front_facing: bool,
+) -> GBufferOutput {
+    let diffuse = textureSample(t_diffuse, s_diffuse, tex_coords(0u, in.tex_coords, in.tex_coords_1)) * material.base_color_factor * in.instance_color;
+    // Normal maps store tangent space normals in 0..1.
+    let tangent_normal = textureSample(t_normal, s_normal, tex_coords(1u, in.tex_coords, in.tex_coords_1)).rgb * 2.0 - 1.0;
+    let tangent_matrix = mat3x3<f32>(
+        normalize(in.tangent),
+        normalize(in.bitangent),
+        normalize(in.normal),
+    );
+    var normal = normalize(tangent_matrix * tangent_normal);
+    // Back faces only show up for double sided materials, light them like the front.
+    if (!front_facing) {
+        normal = -normal;
+    }
+
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, tex_coords(3u, in.tex_coords, in.tex_coords_1));
+    let roughness = metallic_roughness.g * material.roughness_factor;
+    let metallic = metallic_roughness.b * material.metallic_factor;
+    let occlusion = mix(1.0, textureSample(t_occlusion, s_occlusion, tex_coords(4u, in.tex_coords, in.tex_coords_1)).r, material.occlusion_strength);
+
+    let emissive = textureSample(t_emissive, s_emissive, tex_coords(2u, in.tex_coords, in.tex_coords_1)).rgb * material.emissive_factor;
+
+    // The tangent and uv debug views show the albedo, see debug_color in
+    //  deferred_lighting.wgsl.
+    var albedo = diffuse.rgb;
+    if (uniforms.debug_view == 2u) {
+        albedo = normalize(in.tangent) * 0.5 + 0.5;
+    }
+    if (uniforms.debug_view == 3u) {
+        albedo = vec3<f32>(fract(in.tex_coords), 0.0);
+    }
+
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(albedo, metallic);
+    out.normal = vec4<f32>(normal, roughness);
+    out.position = vec4<f32>(in.position, 1.0 + occlusion);
+    out.emissive = vec4<f32>(emissive, in.highlight + select(0.0, 2.0, material.unlit != 0u));
+    return out;
+}
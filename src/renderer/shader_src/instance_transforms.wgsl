@@ -0,0 +1,74 @@
+// Turns instance positions, rotations and scales into the matrices the vertex shaders
+//  read, see instance_compute.rs. Same math as Instance::to_raw.
+struct InstanceTrs {
+    // w is unused.
+    position: vec4<f32>;
+    // x, y, z, w.
+    rotation: vec4<f32>;
+    // w is unused.
+    scale: vec4<f32>;
+    color: vec4<f32>;
+};
+
+[[block]]
+struct TrsBuffer {
+    instances: array<InstanceTrs>;
+};
+
+struct InstanceRaw {
+    model: mat4x4<f32>;
+    inverse_model: mat4x4<f32>;
+    color: vec4<f32>;
+};
+
+[[block]]
+struct InstanceBuffer {
+    instances: array<InstanceRaw>;
+};
+
+// Bound to exactly the instances being updated.
+[[group(0), binding(0)]] var<storage, read> trs: TrsBuffer;
+[[group(0), binding(1)]] var<storage, read_write> raw: InstanceBuffer;
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let i = id.x;
+    if (i >= arrayLength(&trs.instances)) {
+        return;
+    }
+    let instance = trs.instances[i];
+
+    let q = instance.rotation;
+    let rotation = mat3x3<f32>(
+        vec3<f32>(1.0 - 2.0 * (q.y * q.y + q.z * q.z), 2.0 * (q.x * q.y + q.w * q.z), 2.0 * (q.x * q.z - q.w * q.y)),
+        vec3<f32>(2.0 * (q.x * q.y - q.w * q.z), 1.0 - 2.0 * (q.x * q.x + q.z * q.z), 2.0 * (q.y * q.z + q.w * q.x)),
+        vec3<f32>(2.0 * (q.x * q.z + q.w * q.y), 2.0 * (q.y * q.z - q.w * q.x), 1.0 - 2.0 * (q.x * q.x + q.y * q.y)),
+    );
+    let scale = instance.scale.xyz;
+    let position = instance.position.xyz;
+
+    // Translation * rotation * scale.
+    raw.instances[i].model = mat4x4<f32>(
+        vec4<f32>(rotation[0] * scale.x, 0.0),
+        vec4<f32>(rotation[1] * scale.y, 0.0),
+        vec4<f32>(rotation[2] * scale.z, 0.0),
+        vec4<f32>(position, 1.0),
+    );
+
+    // Undone in reverse: 1/scale * transposed rotation * -translation.
+    let inverse_scale = 1.0 / scale;
+    let rotation_t = transpose(rotation);
+    let inverse = mat3x3<f32>(
+        rotation_t[0] * inverse_scale,
+        rotation_t[1] * inverse_scale,
+        rotation_t[2] * inverse_scale,
+    );
+    raw.instances[i].inverse_model = mat4x4<f32>(
+        vec4<f32>(inverse[0], 0.0),
+        vec4<f32>(inverse[1], 0.0),
+        vec4<f32>(inverse[2], 0.0),
+        vec4<f32>(-(inverse * position), 1.0),
+    );
+
+    raw.instances[i].color = instance.color;
+}
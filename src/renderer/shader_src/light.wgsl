@@ -0,0 +1,41 @@
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec3<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    view_position: vec3<f32>; // Unused
+    view_proj: mat4x4<f32>;
+};
+
+[[block]]
+struct Light {
+    position: vec3<f32>;
+    intensity: f32;
+    color: vec3<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: Uniforms;
+
+[[group(1), binding(0)]] var<uniform> light: Light;
+
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    let scale = 10.0;
+    let position = vertex.position * scale + light.position;
+
+    var out: VertexOutput;
+    out.clip_position = uniforms.view_proj * vec4<f32>(position, 1.0);
+    out.color = light.color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
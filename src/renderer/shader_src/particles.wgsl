@@ -0,0 +1,59 @@
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(13)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] corner: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    view_position: vec3<f32>;
+    view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: Uniforms;
+
+[[stage(vertex)]]
+fn vs_main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    instance: InstanceInput,
+) -> VertexOutput {
+    // A quad as a triangle strip, the corners go from -1 to 1.
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32((vertex_index >> 1u) & 1u)) * 2.0 - 1.0;
+
+    // Only the translation and the (uniform) scale of the model matrix are used, the quad
+    //  is turned towards the camera.
+    let center = instance.model_matrix_3.xyz;
+    let size = length(instance.model_matrix_0.xyz);
+    let to_camera = normalize(uniforms.view_position - center);
+    var right = cross(vec3<f32>(0.0, 1.0, 0.0), to_camera);
+    // Looking straight up or down at it.
+    if (length(right) < 0.001) {
+        right = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    right = normalize(right);
+    let up = cross(to_camera, right);
+
+    let world_position = center + (right * corner.x + up * corner.y) * size;
+
+    var out: VertexOutput;
+    out.clip_position = uniforms.view_proj * vec4<f32>(world_position, 1.0);
+    out.corner = corner;
+    out.color = instance.color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // A soft round spot, brightest in the middle.
+    let falloff = 1.0 - smoothStep(0.0, 1.0, length(in.corner));
+    // Added to what's behind it, so the alpha fades it by darkening.
+    return vec4<f32>(in.color.rgb * in.color.a * falloff, 0.0);
+}
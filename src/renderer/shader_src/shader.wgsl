@@ -0,0 +1,322 @@
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tangent: vec3<f32>;
+    [[location(4)]] bitangent: vec3<f32>;
+    // After the instance locations.
+    [[location(14)]] color: vec4<f32>;
+    // A copy of tex_coords for meshes with one set.
+    [[location(15)]] tex_coords_1: vec2<f32>;
+};
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(9)]] inverse_model_matrix_0: vec4<f32>;
+    [[location(10)]] inverse_model_matrix_1: vec4<f32>;
+    [[location(11)]] inverse_model_matrix_2: vec4<f32>;
+    [[location(12)]] inverse_model_matrix_3: vec4<f32>;
+    [[location(13)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    // World space position and tangent frame.
+    [[location(1)]] position: vec3<f32>;
+    [[location(2)]] tangent: vec3<f32>;
+    [[location(3)]] bitangent: vec3<f32>;
+    [[location(4)]] normal: vec3<f32>;
+    [[location(5)]] instance_color: vec4<f32>;
+    // 1.0 for the selected instance, 0.0 otherwise.
+    [[location(6)]] highlight: f32;
+    [[location(7)]] tex_coords_1: vec2<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    view_position: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    exposure: f32;
+    gamma: f32;
+    far_depth: f32;
+    // See Renderer::set_debug_unlit.
+    debug_unlit: u32;
+    // A DebugView, see Renderer::set_debug_view.
+    debug_view: u32;
+};
+
+[[block]]
+struct Light {
+    position: vec3<f32>;
+    intensity: f32;
+    color: vec3<f32>;
+    // 0 is no cutoff.
+    range: f32;
+    // Multiplies the environment's light, see Renderer::set_ambient.
+    ambient_color: vec3<f32>;
+    ambient_strength: f32;
+};
+
+[[block]]
+struct MaterialUniform {
+    base_color_factor: vec4<f32>;
+    emissive_factor: vec3<f32>;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    occlusion_strength: f32;
+    // A bit per texture, set for the ones sampled with tex_coords_1. See tex_coords.
+    tex_coord_sets: u32;
+    // Non-zero to skip the lighting, see Material::unlit.
+    unlit: u32;
+};
+
+[[group(0), binding(0)]] var t_diffuse: texture_2d<f32>;
+[[group(0), binding(1)]] var s_diffuse: sampler;
+[[group(0), binding(2)]] var t_normal: texture_2d<f32>;
+[[group(0), binding(3)]] var s_normal: sampler;
+[[group(0), binding(4)]] var t_emissive: texture_2d<f32>;
+[[group(0), binding(5)]] var s_emissive: sampler;
+[[group(0), binding(6)]] var t_metallic_roughness: texture_2d<f32>;
+[[group(0), binding(7)]] var s_metallic_roughness: sampler;
+[[group(0), binding(8)]] var t_occlusion: texture_2d<f32>;
+[[group(0), binding(9)]] var s_occlusion: sampler;
+[[group(0), binding(10)]] var<uniform> material: MaterialUniform;
+
+[[group(1), binding(0)]] var<uniform> uniforms: Uniforms;
+
+[[group(2), binding(0)]] var<uniform> light: Light;
+// Prefiltered from the environment cubemap by environment_prefilter.wgsl.
+[[group(2), binding(1)]] var t_irradiance: texture_cube<f32>;
+[[group(2), binding(2)]] var t_specular: texture_cube<f32>;
+[[group(2), binding(3)]] var s_environment: sampler;
+
+[[block]]
+struct DrawConstants {
+    // Instance to highlight, -1 for none.
+    selected_instance: i32;
+};
+
+// Set per draw. Without push constant support this becomes a dynamic uniform in group 3,
+//  see draw_constants.rs.
+var<push_constant> draw: DrawConstants;
+
+[[stage(vertex)]]
+fn vs_main(
+    vertex: VertexInput,
+    instance: InstanceInput,
+    [[builtin(instance_index)]] instance_index: u32,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let inverse_model_matrix = mat4x4<f32>(
+        instance.inverse_model_matrix_0,
+        instance.inverse_model_matrix_1,
+        instance.inverse_model_matrix_2,
+        instance.inverse_model_matrix_3,
+    );
+
+    let normal_matrix = transpose(mat3x3<f32>(
+        inverse_model_matrix[0].xyz,
+        inverse_model_matrix[1].xyz,
+        inverse_model_matrix[2].xyz,
+    ));
+    let model_space = model_matrix * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = vertex.tex_coords;
+    out.tex_coords_1 = vertex.tex_coords_1;
+    // The vertex color tints the same way, so they go to the fragment shader as one.
+    out.instance_color = instance.color * vertex.color;
+    out.highlight = select(0.0, 1.0, i32(instance_index) == draw.selected_instance);
+    // Lighting happens in world space. The tangent frame goes along so the fragment shader
+    //  can take the normal map from tangent space to world space, the same as gbuffer.wgsl.
+    out.position = model_space.xyz;
+    out.tangent = normal_matrix * vertex.tangent;
+    out.bitangent = normal_matrix * vertex.bitangent;
+    out.normal = normal_matrix * vertex.normal;
+    out.clip_position = uniforms.view_proj * model_space;
+    return out;
+}
+
+let PI: f32 = 3.14159265;
+
+// Cook-Torrance with a GGX distribution, the Smith-Schlick geometry term and Schlick's
+//  fresnel, plus a lambertian diffuse that metals don't have. Gives the light reflected
+//  towards view_dir per unit of light color.
+fn cook_torrance(
+    normal: vec3<f32>,
+    light_dir: vec3<f32>,
+    view_dir: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let half_dir = normalize(view_dir + light_dir);
+    let n_dot_l = max(dot(normal, light_dir), 0.0);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+    let h_dot_v = max(dot(half_dir, view_dir), 0.0);
+
+    // Gltf roughness is perceptual, squared it's the alpha of GGX. Kept off zero, a
+    //  perfect mirror has no highlight to speak of for a point light.
+    let alpha = max(roughness * roughness, 0.002);
+    let alpha2 = alpha * alpha;
+    let d_denom = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d_denom * d_denom);
+
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+    // Dielectrics reflect about 4% head on, metals tint the reflection with their color.
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let fresnel = f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - h_dot_v, 5.0);
+
+    let specular = fresnel * distribution * geometry / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic) * albedo / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// What reaches position from the point light, falling off with the square of the distance.
+//  With a range it's faded to 0 at that distance, with the window of KHR_lights_punctual.
+fn radiance(light_position: vec3<f32>, light_color: vec3<f32>, intensity: f32, range: f32, position: vec3<f32>) -> vec3<f32> {
+    let to_light = light_position - position;
+    let distance_squared = dot(to_light, to_light);
+    var cutoff = 1.0;
+    if (range > 0.0) {
+        let ratio_squared = distance_squared / (range * range);
+        cutoff = clamp(1.0 - ratio_squared * ratio_squared, 0.0, 1.0);
+        cutoff = cutoff * cutoff;
+    }
+    return light_color * intensity * cutoff / max(distance_squared, 0.0001);
+}
+
+// Mip count of t_specular, see environment.rs.
+let SPECULAR_MIP_LEVELS: f32 = 5.0;
+
+// Light from the environment all around. The split of specular and diffuse comes from
+//  Karis' analytic fit of the environment BRDF, so there's no lookup texture.
+fn ambient(
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let c0 = vec4<f32>(-1.0, -0.0275, -0.572, 0.022);
+    let c1 = vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let r = roughness * c0 + c1;
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let scale_bias = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    let specular_color = f0 * scale_bias.x + scale_bias.y;
+
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, normal, 0.0).rgb;
+    let reflect_dir = reflect(-view_dir, normal);
+    let reflection = textureSampleLevel(t_specular, s_environment, reflect_dir, roughness * (SPECULAR_MIP_LEVELS - 1.0)).rgb;
+
+    let diffuse = (vec3<f32>(1.0) - specular_color) * (1.0 - metallic) * albedo * irradiance;
+    return (diffuse + reflection * specular_color) * light.ambient_color * light.ambient_strength;
+}
+
+// Applies the exposure and gamma of Renderer::set_exposure/set_gamma. The surface is sRGB
+//  and already encodes for a gamma of about 2.2, so that much is taken back out again.
+//  The default gamma of 2.2 leaves the image as is.
+fn exposure_gamma(color: vec3<f32>) -> vec3<f32> {
+    return pow(max(color * uniforms.exposure, vec3<f32>(0.0)), vec3<f32>(2.2 / uniforms.gamma));
+}
+
+// What Renderer::set_debug_view shows, the views are numbered as in DebugView.
+fn debug_color(
+    normal: vec3<f32>,
+    tangent: vec3<f32>,
+    uv: vec2<f32>,
+    position: vec3<f32>,
+    albedo: vec3<f32>,
+    occlusion: f32,
+) -> vec3<f32> {
+    let view = uniforms.debug_view;
+    if (view == 1u) {
+        return normal * 0.5 + 0.5;
+    }
+    if (view == 2u) {
+        return tangent * 0.5 + 0.5;
+    }
+    if (view == 3u) {
+        return vec3<f32>(fract(uv), 0.0);
+    }
+    if (view == 4u) {
+        let distance_to_camera = distance(uniforms.view_position, position);
+        return vec3<f32>(distance_to_camera / (distance_to_camera + 10.0));
+    }
+    if (view == 5u) {
+        return albedo;
+    }
+    return vec3<f32>(occlusion);
+}
+
+// The uvs the material samples a texture with. Slots go in binding order: diffuse, normal,
+//  emissive, metallic roughness and occlusion.
+fn tex_coords(slot: u32, tex_coords_0: vec2<f32>, tex_coords_1: vec2<f32>) -> vec2<f32> {
+    return select(tex_coords_0, tex_coords_1, (material.tex_coord_sets & (1u << slot)) != 0u);
+}
+
+[[stage(fragment)]]
+fn fs_main(
+    in: VertexOutput,
+    [[builtin(front_facing)]] front_facing: bool,
+) -> [[location(0)]] vec4<f32> {
+    let diffuse = textureSample(t_diffuse, s_diffuse, tex_coords(0u, in.tex_coords, in.tex_coords_1)) * material.base_color_factor * in.instance_color;
+    // Unlit materials, or all of them with Renderer::set_debug_unlit, show the base color
+    //  as is.
+    if ((material.unlit != 0u || uniforms.debug_unlit != 0u) && uniforms.debug_view == 0u) {
+        let color = mix(diffuse.rgb, vec3<f32>(1.0, 0.6, 0.0), in.highlight * 0.4);
+        return vec4<f32>(exposure_gamma(color), diffuse.a);
+    }
+    // Normal maps store tangent space normals in 0..1.
+    let tangent_normal = textureSample(t_normal, s_normal, tex_coords(1u, in.tex_coords, in.tex_coords_1)).rgb * 2.0 - 1.0;
+    let tangent_matrix = mat3x3<f32>(
+        normalize(in.tangent),
+        normalize(in.bitangent),
+        normalize(in.normal),
+    );
+    var normal = normalize(tangent_matrix * tangent_normal);
+    // Back faces only show up for double sided materials, light them like the front.
+    if (!front_facing) {
+        normal = -normal;
+    }
+    let light_dir = normalize(light.position - in.position);
+    let view_dir = normalize(uniforms.view_position - in.position);
+
+    // Metallic roughness as in gltf: roughness in green, metallic in blue.
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, tex_coords(3u, in.tex_coords, in.tex_coords_1));
+    let roughness = metallic_roughness.g * material.roughness_factor;
+    let metallic = metallic_roughness.b * material.metallic_factor;
+
+    // Occlusion is in red, strength blends it in as the gltf spec has it.
+    let occlusion = mix(1.0, textureSample(t_occlusion, s_occlusion, tex_coords(4u, in.tex_coords, in.tex_coords_1)).r, material.occlusion_strength);
+
+    if (uniforms.debug_view != 0u) {
+        return vec4<f32>(debug_color(normal, normalize(in.tangent), in.tex_coords, in.position, diffuse.rgb, occlusion), 1.0);
+    }
+
+    let ambient_color = ambient(normal, view_dir, diffuse.rgb, metallic, roughness) * occlusion;
+
+    let lit_color = radiance(light.position, light.color, light.intensity, light.range, in.position) * cook_torrance(normal, light_dir, view_dir, diffuse.rgb, metallic, roughness);
+
+    let emissive = textureSample(t_emissive, s_emissive, tex_coords(2u, in.tex_coords, in.tex_coords_1)).rgb * material.emissive_factor;
+
+    var result = ambient_color + lit_color + emissive;
+    result = mix(result, vec3<f32>(1.0, 0.6, 0.0), in.highlight * 0.4);
+
+    return vec4<f32>(exposure_gamma(result), diffuse.a);
+}
@@ -0,0 +1,48 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] direction: vec3<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    view_position: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    exposure: f32;
+    gamma: f32;
+    // 1.0, or 0.0 with reversed z.
+    far_depth: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: Uniforms;
+
+[[group(1), binding(0)]] var t_skybox: texture_cube<f32>;
+[[group(1), binding(1)]] var s_skybox: sampler;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, generated from the vertex index so no vertex buffer is needed.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    // Unproject the far plane to get the world space view direction for this vertex.
+    let far_position = uniforms.inv_view_proj * vec4<f32>(position, uniforms.far_depth, 1.0);
+
+    var out: VertexOutput;
+    out.direction = far_position.xyz / far_position.w - uniforms.view_position;
+    // Put the skybox on the far plane, so everything else ends up in front of it.
+    out.clip_position = vec4<f32>(position, uniforms.far_depth, 1.0);
+    return out;
+}
+
+
+// Applies the exposure and gamma of Renderer::set_exposure/set_gamma. The surface is sRGB
+//  and already encodes for a gamma of about 2.2, so that much is taken back out again.
+//  The default gamma of 2.2 leaves the image as is.
+fn exposure_gamma(color: vec3<f32>) -> vec3<f32> {
+    return pow(max(color * uniforms.exposure, vec3<f32>(0.0)), vec3<f32>(2.2 / uniforms.gamma));
+}
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_skybox, s_skybox, normalize(in.direction));
+    return vec4<f32>(exposure_gamma(color.rgb), color.a);
+}
@@ -0,0 +1,84 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    view_position: vec3<f32>;
+    view_proj: mat4x4<f32>;
+};
+
+[[block]]
+struct SsaoUniform {
+    // Offsets in a unit hemisphere around +z, see ssao.rs.
+    kernel: array<vec4<f32>, 16>;
+    radius: f32;
+    intensity: f32;
+    bias: f32;
+};
+
+// Written by gbuffer.wgsl, only the normal and position are used here.
+[[group(0), binding(0)]] var t_albedo: texture_2d<f32>;
+[[group(0), binding(1)]] var t_normal: texture_2d<f32>;
+[[group(0), binding(2)]] var t_position: texture_2d<f32>;
+[[group(0), binding(3)]] var t_emissive: texture_2d<f32>;
+
+[[group(1), binding(0)]] var<uniform> uniforms: Uniforms;
+
+[[group(2), binding(0)]] var<uniform> ssao: SsaoUniform;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, same as the skybox.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let position = textureLoad(t_position, coords, 0);
+    // Nothing to occlude.
+    if (position.w == 0.0) {
+        return vec4<f32>(1.0);
+    }
+    let normal = normalize(textureLoad(t_normal, coords, 0).xyz);
+    let size = vec2<f32>(textureDimensions(t_position));
+    // The w of a perspective projection is the view space depth.
+    let depth = (uniforms.view_proj * vec4<f32>(position.xyz, 1.0)).w;
+
+    // Turn the kernel a different way for each pixel in a 4x4 tile, the blur pass
+    //  averages the pattern out again.
+    let tile = vec2<u32>(coords) % vec2<u32>(4u);
+    let angle = f32(tile.x * 4u + tile.y) * 0.39269908 + f32(tile.y) * 1.3;
+    let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    var tangent = random - normal * dot(random, normal);
+    // random is parallel to the normal, any other direction will do.
+    if (dot(tangent, tangent) < 0.0001) {
+        tangent = vec3<f32>(0.0, 0.0, 1.0) - normal * normal.z;
+    }
+    tangent = normalize(tangent);
+    let bitangent = cross(normal, tangent);
+    let tangent_matrix = mat3x3<f32>(tangent, bitangent, normal);
+
+    var occlusion = 0.0;
+    for (var i = 0; i < 16; i = i + 1) {
+        let sample = position.xyz + tangent_matrix * ssao.kernel[i].xyz * ssao.radius;
+        let clip = uniforms.view_proj * vec4<f32>(sample, 1.0);
+        let ndc = clip.xy / clip.w;
+        let sample_coords = vec2<i32>(vec2<f32>(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * size);
+        let occluder = textureLoad(t_position, sample_coords, 0);
+        if (occluder.w != 0.0) {
+            let occluder_depth = (uniforms.view_proj * vec4<f32>(occluder.xyz, 1.0)).w;
+            // Geometry far in front of the sample doesn't count, it's just in the way.
+            let in_range = smoothStep(0.0, 1.0, ssao.radius / abs(depth - occluder_depth));
+            occlusion = occlusion + select(0.0, 1.0, occluder_depth <= clip.w - ssao.bias) * in_range;
+        }
+    }
+
+    let ambient_occlusion = clamp(1.0 - occlusion / 16.0 * ssao.intensity, 0.0, 1.0);
+    return vec4<f32>(ambient_occlusion, 0.0, 0.0, 1.0);
+}
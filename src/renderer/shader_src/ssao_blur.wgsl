@@ -0,0 +1,31 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+};
+
+// Written by ssao.wgsl.
+[[group(0), binding(0)]] var t_ambient_occlusion: texture_2d<f32>;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle, same as the skybox.
+    let position = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+// Averages 4x4 pixels, the size of the tile ssao.wgsl rotates its kernel over.
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let max_coords = textureDimensions(t_ambient_occlusion) - vec2<i32>(1);
+    var sum = 0.0;
+    for (var x = -2; x < 2; x = x + 1) {
+        for (var y = -2; y < 2; y = y + 1) {
+            let sample_coords = clamp(coords + vec2<i32>(x, y), vec2<i32>(0), max_coords);
+            sum = sum + textureLoad(t_ambient_occlusion, sample_coords, 0).r;
+        }
+    }
+    return vec4<f32>(sum / 16.0, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,319 @@
+use std::cell::Cell;
+
+use super::viewport::{set_viewport, Rect};
+
+use wgpu::util::DeviceExt;
+
+const KERNEL_SIZE: usize = 16;
+// Both targets only hold the ambient occlusion factor.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    kernel: [[f32; 4]; KERNEL_SIZE],
+    radius: f32,
+    intensity: f32,
+    // Keeps flat surfaces from occluding themselves.
+    bias: f32,
+    _padding: f32,
+}
+
+// Screen space ambient occlusion on top of the G-buffer of deferred shading, see
+//  ssao.wgsl. Renders into one target, which ssao_blur.wgsl smooths into a second one
+//  that the lighting pass darkens its ambient term with.
+pub struct Ssao {
+    enabled: bool,
+    uniform: SsaoUniform,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    blur_bind_group_layout: wgpu::BindGroupLayout,
+    view: wgpu::TextureView,
+    blurred_view: wgpu::TextureView,
+    blur_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    // The lighting pass always reads the blurred target, so it's cleared to white once
+    //  SSAO is turned off. A Cell so drawing doesn't need the renderer mutably.
+    cleared: Cell<bool>,
+}
+
+impl Ssao {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        gbuffer_bind_group_layout: &wgpu::BindGroupLayout,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform = SsaoUniform {
+            kernel: kernel(),
+            radius: 0.5,
+            intensity: 1.0,
+            bias: 0.025,
+            _padding: 0.0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("SSAO uniform buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let ssao_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("SSAO bind group layout"),
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &ssao_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("SSAO bind group"),
+        });
+
+        let blur_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                }],
+                label: Some("SSAO blur bind group layout"),
+            });
+        let (view, blurred_view, blur_bind_group) =
+            Self::create_targets(device, surface_configuration, &blur_bind_group_layout);
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO pipeline layout"),
+            bind_group_layouts: &[
+                gbuffer_bind_group_layout,
+                uniform_bind_group_layout,
+                &ssao_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("shader_src/ssao.wgsl"));
+        let pipeline = Self::create_pipeline(device, &layout, &shader, "SSAO render pipeline");
+
+        let blur_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO blur pipeline layout"),
+            bind_group_layouts: &[&blur_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let blur_shader =
+            device.create_shader_module(&wgpu::include_wgsl!("shader_src/ssao_blur.wgsl"));
+        let blur_pipeline = Self::create_pipeline(
+            device,
+            &blur_layout,
+            &blur_shader,
+            "SSAO blur render pipeline",
+        );
+
+        Self {
+            enabled: false,
+            uniform,
+            uniform_buffer,
+            uniform_bind_group,
+            blur_bind_group_layout,
+            view,
+            blurred_view,
+            blur_bind_group,
+            pipeline,
+            blur_pipeline,
+            cleared: Cell::new(false),
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        blur_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> (wgpu::TextureView, wgpu::TextureView, wgpu::BindGroup) {
+        let create_view = |label| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: surface_configuration.width,
+                        height: surface_configuration.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let view = create_view("SSAO target");
+        let blurred_view = create_view("SSAO blurred target");
+
+        let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: blur_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+            label: Some("SSAO blur bind group"),
+        });
+
+        (view, blurred_view, blur_bind_group)
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        label: &str,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                strip_index_format: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        })
+    }
+
+    // radius is in world units, intensity scales how dark fully occluded spots get.
+    pub fn set(&mut self, queue: &wgpu::Queue, enabled: bool, radius: f32, intensity: f32) {
+        self.enabled = enabled;
+        self.uniform.radius = radius;
+        self.uniform.intensity = intensity;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    // What the lighting pass multiplies its ambient term with.
+    pub fn ambient_occlusion_view(&self) -> &wgpu::TextureView {
+        &self.blurred_view
+    }
+
+    // Call before recreating the G-buffer bind group, it holds ambient_occlusion_view().
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+    ) {
+        let (view, blurred_view, blur_bind_group) =
+            Self::create_targets(device, surface_configuration, &self.blur_bind_group_layout);
+        self.view = view;
+        self.blurred_view = blurred_view;
+        self.blur_bind_group = blur_bind_group;
+        self.cleared.set(false);
+    }
+
+    // Computes and blurs the occlusion of every viewport, given as its rect and the bind
+    //  group of its camera. Only clears the result while disabled.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        gbuffer_bind_group: &wgpu::BindGroup,
+        viewports: &[(Rect, &wgpu::BindGroup)],
+    ) {
+        if !self.enabled {
+            if !self.cleared.get() {
+                target_pass(encoder, &self.blurred_view);
+                self.cleared.set(true);
+            }
+            return;
+        }
+        self.cleared.set(false);
+
+        {
+            let mut render_pass = target_pass(encoder, &self.view);
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, gbuffer_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.uniform_bind_group, &[]);
+            for &(rect, uniform_bind_group) in viewports {
+                set_viewport(&mut render_pass, rect);
+                render_pass.set_bind_group(1, uniform_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+
+        let mut render_pass = target_pass(encoder, &self.blurred_view);
+        render_pass.set_pipeline(&self.blur_pipeline);
+        render_pass.set_bind_group(0, &self.blur_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Starts a pass drawing to one of the targets, cleared to unoccluded.
+fn target_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("SSAO render pass"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    })
+}
+
+// Offsets in the unit hemisphere around +z, spread out with a Hammersley set. They're
+//  scaled so more of them land close to the surface, where occlusion matters most.
+fn kernel() -> [[f32; 4]; KERNEL_SIZE] {
+    let mut kernel = [[0.0; 4]; KERNEL_SIZE];
+    for (i, offset) in kernel.iter_mut().enumerate() {
+        let z = (i as f32 + 0.5) / KERNEL_SIZE as f32;
+        let phi = (i as u32).reverse_bits() as f32 / 4_294_967_296.0 * std::f32::consts::TAU;
+        let r = (1.0 - z * z).sqrt();
+        let t = ((i * 7) % KERNEL_SIZE) as f32 / KERNEL_SIZE as f32;
+        let scale = 0.1 + 0.9 * t * t;
+        *offset = [r * phi.cos() * scale, r * phi.sin() * scale, z * scale, 0.0];
+    }
+    kernel
+}
@@ -1,22 +1,247 @@
 use anyhow::*;
 use image::GenericImageView;
-use std::num::NonZeroU32;
+use std::future::Future;
+use std::num::{NonZeroU32, NonZeroU8};
 use std::path::Path;
+use std::sync::Arc;
+
+// Describes how a texture is sampled. The default is what color textures have always used,
+//  depth() gives the comparison sampler depth textures need.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerConfig {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    // Max anisotropy, None turns it off. Should be a power of two up to 16.
+    pub anisotropy_clamp: Option<NonZeroU8>,
+    pub compare: Option<wgpu::CompareFunction>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            anisotropy_clamp: None,
+            compare: None,
+        }
+    }
+}
+
+impl SamplerConfig {
+    pub fn depth() -> Self {
+        Self {
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_anisotropy(self, anisotropy_clamp: u8) -> Self {
+        Self {
+            anisotropy_clamp: NonZeroU8::new(anisotropy_clamp),
+            ..self
+        }
+    }
+
+    // Takes the wrap modes and filters from a gltf sampler, anything else stays as is. The gltf
+    //  crate hands out its default sampler (repeat, no filters) for textures without one,
+    //  filters it leaves unspecified keep their current setting.
+    pub fn with_gltf_sampler(self, sampler: &gltf::texture::Sampler) -> Self {
+        use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+        let address_mode = |mode| match mode {
+            WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+            WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+        };
+
+        let mag_filter = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+            Some(MagFilter::Linear) => wgpu::FilterMode::Linear,
+            None => self.mag_filter,
+        };
+
+        let (min_filter, mipmap_filter) = match sampler.min_filter() {
+            Some(MinFilter::Nearest) => (wgpu::FilterMode::Nearest, self.mipmap_filter),
+            Some(MinFilter::Linear) => (wgpu::FilterMode::Linear, self.mipmap_filter),
+            Some(MinFilter::NearestMipmapNearest) => {
+                (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+            }
+            Some(MinFilter::LinearMipmapNearest) => {
+                (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest)
+            }
+            Some(MinFilter::NearestMipmapLinear) => {
+                (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear)
+            }
+            Some(MinFilter::LinearMipmapLinear) => {
+                (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear)
+            }
+            None => (self.min_filter, self.mipmap_filter),
+        };
+
+        Self {
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            ..self
+        }
+    }
+
+    // Wgpu quietly ignores the anisotropy clamp on adapters without anisotropic filtering,
+    //  so check against the adapter's downlevel capabilities up front.
+    pub fn check_support(&self, downlevel: &wgpu::DownlevelCapabilities) -> Result<()> {
+        if let Some(clamp) = self.anisotropy_clamp {
+            let clamp = clamp.get();
+            if clamp > 16 || !clamp.is_power_of_two() {
+                bail!("Anisotropy clamp {}x is invalid, use 1, 2, 4, 8 or 16.", clamp);
+            }
+            if clamp > 1
+                && !downlevel
+                    .flags
+                    .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING)
+            {
+                bail!("Anisotropy clamp {}x requested, but the adapter does not support anisotropic filtering.", clamp);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            compare: self.compare,
+            anisotropy_clamp: self.anisotropy_clamp,
+            ..Default::default()
+        })
+    }
+
+    // The binding type a bind group layout needs for a sampler made from this config,
+    //  wgpu refuses to bind a comparison or filtering sampler to a slot not declared as such.
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Sampler {
+            comparison: self.compare.is_some(),
+            filtering: self.mag_filter == wgpu::FilterMode::Linear
+                || self.min_filter == wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+// How far materials with Material::depth_bias are pulled towards the camera, so they win
+//  the depth test against the surface they lie on. constant is in the smallest steps depth
+//  can take, slope_scale is multiplied by how steep the triangle is in depth, which is
+//  what grazing views need. See Renderer::set_depth_bias.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthBias {
+    pub constant: i32,
+    pub slope_scale: f32,
+}
+
+impl Default for DepthBias {
+    fn default() -> Self {
+        Self {
+            constant: 4,
+            slope_scale: 2.0,
+        }
+    }
+}
+
+impl DepthBias {
+    // wgpu adds the bias to the depth, so it has to go the way depth shrinks towards the
+    //  camera. That's up with reversed z.
+    pub fn state(self, reverse_z: bool) -> wgpu::DepthBiasState {
+        let sign = if reverse_z { 1 } else { -1 };
+        wgpu::DepthBiasState {
+            constant: sign * self.constant,
+            slope_scale: sign as f32 * self.slope_scale,
+            clamp: 0.0,
+        }
+    }
+}
+
+// 1x1 textures bound in place of the ones a material doesn't have. Created once on the
+//  Renderer and shared by all materials, in an Arc like the textures of gltf materials
+//  so they can be handed out the same way.
+#[derive(Debug)]
+pub struct DefaultTextures {
+    pub white: Arc<Texture>,
+    pub normal: Arc<Texture>,
+}
+
+impl DefaultTextures {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self {
+            white: Arc::new(Texture::default_white(device, queue)),
+            normal: Arc::new(Texture::default_normal(device, queue)),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    pub sampler_config: SamplerConfig,
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
 }
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+    // What the depth texture is cleared to, and the skybox drawn at. With reversed z (see
+    //  Renderer::set_reverse_z) the far plane is at 0.
+    pub fn far_depth(reverse_z: bool) -> f32 {
+        if reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    // The depth test of pipelines drawing into the depth texture, passing what's closer.
+    //  or_equal also passes what's at the same depth.
+    pub fn depth_compare(reverse_z: bool, or_equal: bool) -> wgpu::CompareFunction {
+        match (reverse_z, or_equal) {
+            (false, false) => wgpu::CompareFunction::Less,
+            (false, true) => wgpu::CompareFunction::LessEqual,
+            (true, false) => wgpu::CompareFunction::Greater,
+            (true, true) => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+
+    // Multisampled targets are only resolved, never bound. The gl backend can only
+    //  multisample renderbuffers, which it makes of textures that are just attachments.
+    pub fn target_usage(sample_count: u32) -> wgpu::TextureUsages {
+        if sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        }
+    }
+
+    // sample_count has to match the color targets drawn with it, or the pipelines fail
+    //  validation. Multisampled it can't be bound as a regular texture anymore.
     pub fn create_depth_texture(
         device: &wgpu::Device,
         surface_configuration: &wgpu::SurfaceConfiguration,
         label: &str,
+        sample_count: u32,
     ) -> Self {
         let size = wgpu::Extent3d {
             width: surface_configuration.width,
@@ -28,35 +253,259 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: Self::target_usage(sample_count),
         };
 
         let texture = device.create_texture(&desc);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            ..Default::default()
+        let sampler_config = SamplerConfig::depth();
+        let sampler = sampler_config.create_sampler(device);
+
+        Self {
+            texture,
+            view,
+            sampler,
+            sampler_config,
+            size,
+            format: Self::DEPTH_FORMAT,
+        }
+    }
+
+    // What the scene is drawn to before it's copied to the surface, see bloom.rs. Floats, so
+    //  bright spots can go over 1.
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    // With a sample_count over 1 it's the multisampled target the scene is drawn to, and
+    //  resolved into the single sampled one. See RendererBuilder::sample_count.
+    pub fn create_hdr_texture(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        label: &str,
+        sample_count: u32,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: surface_configuration.width,
+            height: surface_configuration.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::HDR_FORMAT,
+            usage: Self::target_usage(sample_count),
         });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler_config = SamplerConfig::default();
+        let sampler = sampler_config.create_sampler(device);
 
         Self {
             texture,
             view,
             sampler,
+            sampler_config,
+            size,
+            format: Self::HDR_FORMAT,
         }
     }
 
+    // What a headless renderer draws its frames to instead of a surface. sRGB like most
+    //  surfaces, and copyable so read_back can get the frame out.
+    pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn create_offscreen_target(
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: surface_configuration.width,
+            height: surface_configuration.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: surface_configuration.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler_config = SamplerConfig::default();
+        let sampler = sampler_config.create_sampler(device);
+
+        Self {
+            texture,
+            view,
+            sampler,
+            sampler_config,
+            size,
+            format: surface_configuration.format,
+        }
+    }
+
+    // What the texture takes up on the gpu, every layer of its first mip level. Times the
+    //  sample count for multisampled ones, mip chains add up to a third on top.
+    pub fn byte_size(&self) -> u64 {
+        let info = self.format.describe();
+        let (block_width, block_height) = info.block_dimensions;
+        let blocks_x = self.size.width.div_ceil(block_width as u32);
+        let blocks_y = self.size.height.div_ceil(block_height as u32);
+        blocks_x as u64
+            * blocks_y as u64
+            * self.size.depth_or_array_layers as u64
+            * info.block_size as u64
+    }
+
+    // Swaps out the sampler, e.g. to turn on anisotropic filtering or make it a comparison sampler.
+    pub fn with_sampler(mut self, device: &wgpu::Device, config: SamplerConfig) -> Self {
+        self.sampler = config.create_sampler(device);
+        self.sampler_config = config;
+        self
+    }
+
+    // Copies the first layer back to the cpu, for the 8 bit rgba textures materials are made
+    //  of. Like Resource::read_back, on native the future only resolves once the device is
+    //  polled.
+    pub fn read_back(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> impl Future<Output = image::RgbaImage> {
+        let wgpu::Extent3d { width, height, .. } = self.size;
+        // Rows of a copy to a buffer have to start at a multiple of 256 bytes.
+        let row_bytes = 4 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = row_bytes.div_ceil(align) * align;
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Texture read back buffer"),
+            size: (padded_row_bytes * height) as wgpu::BufferAddress,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture read back encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                ..self.size
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mapping = staging_buffer.slice(..).map_async(wgpu::MapMode::Read);
+        async move {
+            mapping.await.expect("Failed to map texture read back buffer.");
+            let pixels = staging_buffer
+                .slice(..)
+                .get_mapped_range()
+                .chunks(padded_row_bytes as usize)
+                .flat_map(|row| row[..row_bytes as usize].to_vec())
+                .collect();
+            staging_buffer.unmap();
+            image::RgbaImage::from_raw(width, height, pixels)
+                .expect("Texture read back has the wrong size.")
+        }
+    }
+
+    // A 1x1 texture of a single color, for when a material is missing one of its textures.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        label: Option<&str>,
+    ) -> Self {
+        let texture_size = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            label,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &color,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4),
+                rows_per_image: NonZeroU32::new(1),
+            },
+            texture_size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler_config = SamplerConfig::default();
+        let sampler = sampler_config.create_sampler(device);
+
+        Self {
+            texture,
+            view,
+            sampler,
+            sampler_config,
+            size: texture_size,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+
+    // Multiplies to whatever factor it's used with.
+    pub fn default_white(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::from_color(
+            device,
+            queue,
+            [255, 255, 255, 255],
+            Some("default_white_texture"),
+        )
+    }
+
+    // A normal pointing straight out of the surface, (0, 0, 1) once the shaders map it from
+    //  0..1 to -1..1.
+    pub fn default_normal(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::from_color(device, queue, [128, 128, 255, 255], Some("default_normal_texture"))
+    }
+
     pub fn _from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -70,7 +519,7 @@ impl Texture {
                 panic!["Kapot"];
             }
         };
-        Self::_from_image(device, queue, &img, Some(label))
+        Self::_from_image(device, queue, &img, Some(label), SamplerConfig::default())
     }
 
     pub fn from_gltf_image(
@@ -78,6 +527,7 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &gltf::image::Data,
         label: Option<&str>,
+        sampler_config: SamplerConfig,
     ) -> Self {
         let texture_size = wgpu::Extent3d {
             width: img.width,
@@ -88,20 +538,21 @@ impl Texture {
         use gltf::image::Format;
 
         let format = wgpu::TextureFormat::Rgba8Unorm;
-        let converted_rgba: Vec<u8>;
-        match img.format {
+        let converted_rgba: Vec<u8> = match img.format {
             Format::R8G8B8 => {
                 // Wgpu seems to not support rgb without alpha, so add a byte for each set of rgb
-                converted_rgba = img.pixels.iter().enumerate().step_by(3).map(|(i, _)| {
+                img.pixels.iter().enumerate().step_by(3).map(|(i, _)| {
                     vec![
-                        img.pixels[i + 0],
+                        img.pixels[i],
                         img.pixels[i + 1],
                         img.pixels[i + 2],
-                        255 as u8,
+                        255,
                     ]
-                }).collect::<Vec<Vec<u8>>>().into_iter().flatten().collect::<Vec<u8>>();
+                }).collect::<Vec<Vec<u8>>>().into_iter().flatten().collect::<Vec<u8>>()
             },
-            _ => panic!["Unsupported gltf::image::Format in texture::from_gltf_image(device: &wgpu::Device, queue: &wgpu::Queue, img: &gltf::image::Data, label: Option<&str>)"],
+            // What Scene::export_gltf writes.
+            Format::R8G8B8A8 => img.pixels.clone(),
+            _ => panic!["Unsupported gltf::image::Format in texture::from_gltf_image(device: &wgpu::Device, queue: &wgpu::Queue, img: &gltf::image::Data, label: Option<&str>, sampler_config: SamplerConfig)"],
         };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
@@ -109,9 +560,11 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: label,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            label,
         });
 
         queue.write_texture(
@@ -131,20 +584,15 @@ impl Texture {
         );
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = sampler_config.create_sampler(device);
 
         Self {
             texture,
             view,
             sampler,
+            sampler_config,
+            size: texture_size,
+            format,
         }
     }
 
@@ -153,6 +601,7 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        sampler_config: SamplerConfig,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -169,8 +618,10 @@ impl Texture {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: label,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            label,
         });
 
         // Use the queue to copy the pixel data to GPU.
@@ -194,20 +645,95 @@ impl Texture {
 
         // Define texture view and sampler.
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+        let sampler = sampler_config.create_sampler(device);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            sampler_config,
+            size: texture_size,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        })
+    }
+
+    // Loads six images into a cube texture, in the +X, -X, +Y, -Y, +Z, -Z face order wgpu expects.
+    //  All faces should be square and of the same size.
+    pub fn load_cubemap<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: [P; 6],
+    ) -> Result<Self> {
+        let faces = faces
+            .iter()
+            .map(|path| Ok(image::open(path)?.to_rgba8()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let dimensions = faces[0].dimensions();
+        if faces.iter().any(|face| face.dimensions() != dimensions) {
+            bail!("Cubemap faces differ in size.");
+        }
+
+        let texture_size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 6,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("Cubemap texture"),
+        });
+
+        // Each face goes into its own array layer.
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                face,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * dimensions.0),
+                    rows_per_image: NonZeroU32::new(dimensions.1),
+                },
+                wgpu::Extent3d {
+                    depth_or_array_layers: 1,
+                    ..texture_size
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Cubemap view"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
             ..Default::default()
         });
+        let sampler_config = SamplerConfig {
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        };
+        let sampler = sampler_config.create_sampler(device);
 
         Ok(Self {
             texture,
             view,
             sampler,
+            sampler_config,
+            size: texture_size,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
         })
     }
 
@@ -219,6 +745,6 @@ impl Texture {
         let path_copy = path.as_ref().to_path_buf();
         let label = path_copy.to_str();
         let img = image::open(path)?;
-        Self::_from_image(device, queue, &img, label)
+        Self::_from_image(device, queue, &img, label, SamplerConfig::default())
     }
 }
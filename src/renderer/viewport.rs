@@ -0,0 +1,119 @@
+use crate::camera::Camera;
+
+// A region of the window in physical pixels, origin at the top left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    // Whether a point in physical pixels, like the cursor position, falls inside.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x as f32
+            && y >= self.y as f32
+            && x < (self.x + self.width) as f32
+            && y < (self.y + self.height) as f32
+    }
+}
+
+// Part of the window the scene is drawn into from its own camera, see
+//  DrawScene::draw_scene_viewports.
+#[derive(Clone, Debug)]
+pub struct Viewport {
+    pub rect: Rect,
+    pub camera: Camera,
+}
+
+impl Viewport {
+    // Matches the camera's aspect ratio to the rect, so the view isn't stretched.
+    pub fn new(rect: Rect, mut camera: Camera) -> Self {
+        camera.projection.resize(rect.width, rect.height);
+        Self { rect, camera }
+    }
+
+    // One viewport covering a window of the given size.
+    pub fn full((width, height): (u32, u32), camera: Camera) -> Self {
+        Self::new(
+            Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+            camera,
+        )
+    }
+
+    // Two viewports splitting a window of the given size down the middle.
+    pub fn side_by_side((width, height): (u32, u32), left: Camera, right: Camera) -> [Self; 2] {
+        let half = width / 2;
+        [
+            Self::new(
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width: half,
+                    height,
+                },
+                left,
+            ),
+            Self::new(
+                Rect {
+                    x: half,
+                    y: 0,
+                    width: width - half,
+                    height,
+                },
+                right,
+            ),
+        ]
+    }
+}
+
+// Restricts drawing to the rect.
+pub fn set_viewport(render_pass: &mut wgpu::RenderPass, rect: Rect) {
+    let Rect {
+        x,
+        y,
+        width,
+        height,
+    } = rect;
+    render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+    render_pass.set_scissor_rect(x, y, width, height);
+}
+
+// The uniform buffer and bind group a viewport's camera is written to. Every viewport
+//  needs its own, writes to one buffer would all land before the render pass runs.
+pub struct ViewportUniforms {
+    buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl ViewportUniforms {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Viewport uniform buffer"),
+            size: std::mem::size_of::<super::Uniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("Viewport uniform bind group"),
+        });
+        Self { buffer, bind_group }
+    }
+
+    // Takes everything but the camera from uniforms, the renderer's own.
+    pub fn write(&self, queue: &wgpu::Queue, mut uniforms: super::Uniforms, camera: &Camera) {
+        uniforms.update_view_proj(camera);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+}
@@ -0,0 +1,380 @@
+// Writing the scene out as gltf, see Scene::export_gltf.
+use super::{scenenode::SceneNode, Scene};
+use crate::renderer::{
+    material::{Material, DIFFUSE_SLOT},
+    model::{IndexResource, Model},
+};
+
+use anyhow::*;
+use cgmath::{InnerSpace, Vector3};
+use futures::executor::block_on;
+use gltf::json;
+use json::validation::Checked::Valid;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Builds up the json and the single binary buffer everything in it points into.
+struct Exporter {
+    root: json::Root,
+    buffer: Vec<u8>,
+}
+
+impl Exporter {
+    // Appends data to the buffer. Views are padded to 4 bytes, so the floats in the next
+    //  one stay aligned.
+    fn push_view(
+        &mut self,
+        data: &[u8],
+        target: Option<json::buffer::Target>,
+    ) -> json::Index<json::buffer::View> {
+        let offset = self.buffer.len();
+        self.buffer.extend_from_slice(data);
+        while !self.buffer.len().is_multiple_of(4) {
+            self.buffer.push(0);
+        }
+
+        self.root.buffer_views.push(json::buffer::View {
+            buffer: json::Index::new(0),
+            byte_length: data.len() as u32,
+            byte_offset: Some(offset as u32),
+            byte_stride: None,
+            name: None,
+            target: target.map(Valid),
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+        json::Index::new(self.root.buffer_views.len() as u32 - 1)
+    }
+
+    fn push_accessor<T: bytemuck::Pod>(
+        &mut self,
+        items: &[T],
+        component_type: json::accessor::ComponentType,
+        type_: json::accessor::Type,
+        target: json::buffer::Target,
+    ) -> json::Index<json::Accessor> {
+        let buffer_view = self.push_view(bytemuck::cast_slice(items), Some(target));
+        self.root.accessors.push(json::Accessor {
+            buffer_view: Some(buffer_view),
+            byte_offset: 0,
+            count: items.len() as u32,
+            component_type: Valid(json::accessor::GenericComponentType(component_type)),
+            type_: Valid(type_),
+            min: None,
+            max: None,
+            name: None,
+            normalized: false,
+            sparse: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+        json::Index::new(self.root.accessors.len() as u32 - 1)
+    }
+
+    // One gltf mesh per model, with a primitive for each of its meshes.
+    fn push_model(&mut self, model: &Model, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let first_material = self.root.materials.len();
+        for material in &model.materials {
+            self.push_material(material, device, queue);
+        }
+
+        let primitives = model
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let vertices = mesh.vertex_resource.local_slice();
+                let positions: Vec<[f32; 3]> = vertices.iter().map(|v| v.position).collect();
+                let normals: Vec<[f32; 3]> = vertices.iter().map(|v| v.normal).collect();
+                let tex_coords: Vec<[f32; 2]> = vertices.iter().map(|v| v.tex_coords).collect();
+                let colors: Vec<[f32; 4]> = vertices.iter().map(|v| v.color).collect();
+                let tex_coords_1: Vec<[f32; 2]> = vertices.iter().map(|v| v.tex_coords_1).collect();
+                // Gltf only stores the tangent, w says which way the bitangent points.
+                let tangents: Vec<[f32; 4]> = vertices
+                    .iter()
+                    .map(|v| {
+                        let normal = Vector3::from(v.normal);
+                        let tangent = Vector3::from(v.tangent);
+                        let handedness = if normal.cross(tangent).dot(v.bitangent.into()) < 0.0 {
+                            -1.0
+                        } else {
+                            1.0
+                        };
+                        [tangent.x, tangent.y, tangent.z, handedness]
+                    })
+                    .collect();
+
+                use json::accessor::{ComponentType, Type};
+                use json::buffer::Target::{ArrayBuffer, ElementArrayBuffer};
+                let position_accessor =
+                    self.push_accessor(&positions, ComponentType::F32, Type::Vec3, ArrayBuffer);
+                // Gltf requires the bounds of the positions.
+                let (min, max) = mesh_bounds(&positions);
+                let accessor = &mut self.root.accessors[position_accessor.value()];
+                accessor.min = Some(json::Value::from(min.to_vec()));
+                accessor.max = Some(json::Value::from(max.to_vec()));
+
+                let mut attributes = HashMap::new();
+                attributes.insert(Valid(json::mesh::Semantic::Positions), position_accessor);
+                attributes.insert(
+                    Valid(json::mesh::Semantic::Normals),
+                    self.push_accessor(&normals, ComponentType::F32, Type::Vec3, ArrayBuffer),
+                );
+                attributes.insert(
+                    Valid(json::mesh::Semantic::Tangents),
+                    self.push_accessor(&tangents, ComponentType::F32, Type::Vec4, ArrayBuffer),
+                );
+                attributes.insert(
+                    Valid(json::mesh::Semantic::TexCoords(0)),
+                    self.push_accessor(&tex_coords, ComponentType::F32, Type::Vec2, ArrayBuffer),
+                );
+                // A copy of the first set is what a mesh with one set gets, same for the all
+                //  white colors. No need to store those.
+                if tex_coords_1 != tex_coords {
+                    attributes.insert(
+                        Valid(json::mesh::Semantic::TexCoords(1)),
+                        self.push_accessor(
+                            &tex_coords_1,
+                            ComponentType::F32,
+                            Type::Vec2,
+                            ArrayBuffer,
+                        ),
+                    );
+                }
+                if colors.iter().any(|&color| color != [1.0; 4]) {
+                    attributes.insert(
+                        Valid(json::mesh::Semantic::Colors(0)),
+                        self.push_accessor(&colors, ComponentType::F32, Type::Vec4, ArrayBuffer),
+                    );
+                }
+                let indices = match &mesh.index_resource {
+                    IndexResource::U16(resource) => self.push_accessor(
+                        resource.local_slice(),
+                        ComponentType::U16,
+                        Type::Scalar,
+                        ElementArrayBuffer,
+                    ),
+                    IndexResource::U32(resource) => self.push_accessor(
+                        resource.local_slice(),
+                        ComponentType::U32,
+                        Type::Scalar,
+                        ElementArrayBuffer,
+                    ),
+                };
+
+                json::mesh::Primitive {
+                    attributes,
+                    indices: Some(indices),
+                    material: Some(json::Index::new((first_material + mesh.material) as u32)),
+                    mode: Valid(json::mesh::Mode::Triangles),
+                    targets: None,
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                }
+            })
+            .collect();
+
+        self.root.meshes.push(json::Mesh {
+            name: model.meshes.first().map(|mesh| mesh.name.clone()),
+            primitives,
+            weights: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+    }
+
+    // The factors, and the base color texture as a png in the buffer. The other textures
+    //  aren't exported yet.
+    fn push_material(&mut self, material: &Material, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let base_color_texture = material.diffuse_texture.as_ref().map(|texture| {
+            let image = read_back(texture, device, queue);
+            let mut png = Vec::new();
+            image::png::PngEncoder::new(&mut png)
+                .encode(
+                    &image,
+                    image.width(),
+                    image.height(),
+                    image::ColorType::Rgba8,
+                )
+                .expect("Failed to encode base color texture.");
+
+            let buffer_view = self.push_view(&png, None);
+            self.root.images.push(json::Image {
+                buffer_view: Some(buffer_view),
+                mime_type: Some(json::image::MimeType("image/png".to_string())),
+                name: None,
+                uri: None,
+                extensions: Default::default(),
+                extras: Default::default(),
+            });
+            self.root.textures.push(json::Texture {
+                name: None,
+                sampler: None,
+                source: json::Index::new(self.root.images.len() as u32 - 1),
+                extensions: Default::default(),
+                extras: Default::default(),
+            });
+            json::texture::Info {
+                index: json::Index::new(self.root.textures.len() as u32 - 1),
+                tex_coord: material.tex_coord(DIFFUSE_SLOT),
+                extensions: Default::default(),
+                extras: Default::default(),
+            }
+        });
+
+        self.root.materials.push(json::Material {
+            name: Some(material.name.clone()),
+            alpha_mode: Valid(if material.transparent {
+                json::material::AlphaMode::Blend
+            } else {
+                json::material::AlphaMode::Opaque
+            }),
+            double_sided: material.double_sided,
+            pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+                base_color_factor: json::material::PbrBaseColorFactor(material.base_color_factor),
+                base_color_texture,
+                metallic_factor: json::material::StrengthFactor(material.metallic_factor),
+                roughness_factor: json::material::StrengthFactor(material.roughness_factor),
+                ..Default::default()
+            },
+            emissive_factor: json::material::EmissiveFactor(material.emissive_factor),
+            ..Default::default()
+        });
+    }
+
+    // Adds node and its children, returning where node ended up. Children come before
+    //  their parent in the list, gltf doesn't mind.
+    fn push_node(&mut self, node: &SceneNode) -> json::Index<json::Node> {
+        let children: Vec<_> = node
+            .children
+            .iter()
+            .map(|child| self.push_node(child))
+            .collect();
+
+        // The model's instance is placed by the graph, so the node just refers to its mesh.
+        let mesh = match (node.model_id, node.instance_id) {
+            (Some(model_id), Some(_)) => Some(json::Index::new(model_id as u32)),
+            _ => None,
+        };
+
+        self.root.nodes.push(json::Node {
+            camera: None,
+            children: if children.is_empty() {
+                None
+            } else {
+                Some(children)
+            },
+            matrix: None,
+            mesh,
+            name: None,
+            rotation: Some(json::scene::UnitQuaternion([
+                node.rotation.v.x,
+                node.rotation.v.y,
+                node.rotation.v.z,
+                node.rotation.s,
+            ])),
+            scale: Some([node.scale; 3]),
+            translation: Some(node.position.into()),
+            skin: None,
+            weights: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+        json::Index::new(self.root.nodes.len() as u32 - 1)
+    }
+}
+
+fn mesh_bounds(positions: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    if positions.is_empty() {
+        return ([0.0; 3], [0.0; 3]);
+    }
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in positions {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    (min, max)
+}
+
+fn read_back(
+    texture: &crate::renderer::texture::Texture,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> image::RgbaImage {
+    let image = texture.read_back(device, queue);
+    device.poll(wgpu::Maintain::Wait);
+    block_on(image)
+}
+
+impl Scene {
+    // Writes the node graph with the meshes and materials of the models it uses, so the
+    //  scene can be opened elsewhere, e.g. Blender. A .glb path gets a single binary file,
+    //  anything else a .gltf with a .bin next to it. Only geometry, transforms and base
+    //  color textures are written; the cameras and lights are left out.
+    pub fn export_gltf<P: AsRef<Path>>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let mut exporter = Exporter {
+            root: json::Root::default(),
+            buffer: Vec::new(),
+        };
+
+        // Mesh indices match model ids.
+        for model in &self.models {
+            exporter.push_model(model, device, queue);
+        }
+        let root_node = exporter.push_node(&self.graph);
+        exporter.root.scenes.push(json::Scene {
+            name: None,
+            nodes: vec![root_node],
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+        exporter.root.scene = Some(json::Index::new(0));
+
+        let binary = path.extension().is_some_and(|extension| extension == "glb");
+        let bin_path = path.with_extension("bin");
+        exporter.root.buffers.push(json::Buffer {
+            byte_length: exporter.buffer.len() as u32,
+            name: None,
+            // A glb's buffer is the binary chunk.
+            uri: if binary {
+                None
+            } else {
+                bin_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            },
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+
+        if binary {
+            let json = json::serialize::to_vec(&exporter.root)?;
+            let glb = gltf::binary::Glb {
+                // to_writer works out the length itself.
+                header: gltf::binary::Header {
+                    magic: *b"glTF",
+                    version: 2,
+                    length: 0,
+                },
+                json: Cow::Owned(json),
+                bin: Some(Cow::Owned(exporter.buffer)),
+            };
+            glb.to_writer(std::fs::File::create(path)?)
+                .map_err(|e| anyhow!("Failed to write {:?}: {:?}", path, e))?;
+        } else {
+            std::fs::write(path, json::serialize::to_string_pretty(&exporter.root)?)?;
+            std::fs::write(bin_path, &exporter.buffer)?;
+        }
+
+        Ok(())
+    }
+}
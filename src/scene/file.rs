@@ -0,0 +1,167 @@
+// Saving a scene to json and loading it back, see Scene::save and Scene::load.
+use super::{scenenode::SceneNode, Scene};
+use crate::camera::{Camera, CameraState};
+use crate::renderer::{light::Light, model::ModelSource, Renderer};
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use cgmath::{Quaternion, Rad};
+
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    models: Vec<ModelEntry>,
+    graph: NodeEntry,
+    cameras: Vec<CameraState>,
+    active_camera: usize,
+    lights: Vec<Light>,
+}
+
+// Instances are listed in order, so the ids the nodes refer to stay the same.
+#[derive(Serialize, Deserialize)]
+struct ModelEntry {
+    source: ModelSource,
+    instances: Vec<InstanceEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct InstanceEntry {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeEntry {
+    position: [f32; 3],
+    // x, y, z, w.
+    rotation: [f32; 4],
+    scale: f32,
+    model_id: Option<usize>,
+    instance_id: Option<usize>,
+    children: Vec<NodeEntry>,
+    // Missing from files saved before nodes could spin.
+    #[serde(default)]
+    angular_velocity: [f32; 3],
+}
+
+impl From<&SceneNode> for NodeEntry {
+    fn from(node: &SceneNode) -> Self {
+        Self {
+            position: node.position.into(),
+            rotation: [
+                node.rotation.v.x,
+                node.rotation.v.y,
+                node.rotation.v.z,
+                node.rotation.s,
+            ],
+            scale: node.scale,
+            model_id: node.model_id,
+            instance_id: node.instance_id,
+            children: node.children.iter().map(NodeEntry::from).collect(),
+            angular_velocity: node.angular_velocity.into(),
+        }
+    }
+}
+
+impl NodeEntry {
+    // Errors on nodes referring to instances that aren't in the file.
+    fn to_node(&self, scene: &Scene) -> Result<SceneNode> {
+        if let Some(model_id) = self.model_id {
+            let model = scene
+                .models
+                .get(model_id)
+                .ok_or_else(|| anyhow!("Node refers to missing model {}.", model_id))?;
+            if let Some(instance_id) = self.instance_id {
+                if instance_id >= model.get_num_instances() {
+                    bail!(
+                        "Node refers to missing instance {} of model {}.",
+                        instance_id,
+                        model_id
+                    );
+                }
+            }
+        }
+
+        let [x, y, z, w] = self.rotation;
+        Ok(SceneNode {
+            position: self.position.into(),
+            rotation: Quaternion::new(w, x, y, z),
+            scale: self.scale,
+            model_id: self.model_id,
+            instance_id: self.instance_id,
+            children: self
+                .children
+                .iter()
+                .map(|child| child.to_node(scene))
+                .collect::<Result<_>>()?,
+            angular_velocity: self.angular_velocity.into(),
+            ..Default::default()
+        })
+    }
+}
+
+impl Scene {
+    // Writes the node graph, the models it uses with all their instances, the cameras and
+    //  the lights. Models are saved by where they came from, see ModelSource.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = SceneFile {
+            models: self
+                .models
+                .iter()
+                .map(|model| ModelEntry {
+                    source: model.source.clone(),
+                    instances: (0..model.get_num_instances())
+                        .filter_map(|id| model.instance_resource.local_at(id))
+                        .map(|raw| InstanceEntry {
+                            model: raw.model,
+                            color: raw.color,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            graph: NodeEntry::from(&self.graph),
+            cameras: self.cameras.iter().map(Camera::save_view).collect(),
+            active_camera: self.active_camera,
+            lights: self.lights.clone(),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    // Loads a scene written by save, loading its models again. The whole graph is synced
+    //  on the next update, so a saved make_galaxy can be animated right away.
+    pub fn load<P: AsRef<Path>>(renderer: &Renderer, path: P) -> Result<Self> {
+        let file: SceneFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+        let mut scene = Scene::empty();
+        for entry in &file.models {
+            let mut model = entry.source.load(renderer)?;
+            model.instance_resource.reserve(entry.instances.len());
+            for instance in &entry.instances {
+                model.add_instance_with_transform(instance.model.into(), Some(instance.color));
+            }
+            model.instance_resource.sync_gpu();
+            scene.add_model(model);
+        }
+
+        scene.graph = file.graph.to_node(&scene)?;
+        scene.dirty_nodes.push(vec![]);
+
+        if !file.cameras.is_empty() {
+            scene.cameras = file
+                .cameras
+                .iter()
+                .map(|&state| {
+                    let mut camera = Camera::new(state.position, Rad(state.yaw), Rad(state.pitch));
+                    camera.load_view(state);
+                    camera
+                })
+                .collect();
+            scene.active_camera = file.active_camera.min(scene.cameras.len() - 1);
+        }
+        scene.lights = file.lights;
+
+        Ok(scene)
+    }
+}
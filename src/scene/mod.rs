@@ -1,15 +1,47 @@
+mod export;
+mod file;
 pub mod scenenode;
 
-use crate::renderer::{light::*, model::*};
+use crate::camera::Camera;
+use crate::renderer::{
+    culling::{CullTarget, Visibility},
+    deferred::ShadingMode,
+    material::Material,
+    draw_constants::{DrawConstants, PerDraw},
+    forward_pass::ForwardPass,
+    instance::InstanceRaw,
+    light::*,
+    model::*,
+    particles::ParticleSystem,
+    viewport::{set_viewport, Viewport},
+};
 use scenenode::*;
 
 use std::ops::Range;
 use std::time::Duration;
 
-use cgmath::{Matrix4, Quaternion, Rotation3, Vector3};
+use futures::task::SpawnExt;
+
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, Rotation3, SquareMatrix, Vector3, Zero};
+
+// Handles are plain indices into Scene::models and a model's instance resource.
+pub type ModelHandle = usize;
+pub type InstanceHandle = usize;
+pub type CameraHandle = usize;
+pub type ParticleSystemHandle = usize;
+
+// Syncs uploading at least this many bytes go through the renderer's staging belt
+//  instead of queue.write_buffer, see Resource::sync_gpu_staged.
+const STAGED_SYNC_BYTES: usize = 1 << 20;
+
+// How fast make_galaxy spins its nodes, in degrees per second. What it used to do per
+//  frame at 60 fps.
+const SUN_SPEED: f32 = 60.0;
+const PLANET_ORBIT_SPEED: f32 = 6.0;
+const MOON_ORBIT_SPEED: f32 = -120.0;
 
 // Theorizing different types of syncs I'll need.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[allow(dead_code)]
 enum SyncJob {
     Instance {
@@ -22,16 +54,49 @@ enum SyncJob {
         model_id: usize,
         instance_ids: Range<usize>,
     },
-    Vertex,
-    Index,
+    Vertex {
+        model_id: usize,
+        mesh_id: usize,
+    },
+    Index {
+        model_id: usize,
+        mesh_id: usize,
+    },
     Animation,
 }
 
+// What a Scene::update uploaded, to log or budget the uploads per frame. Syncs too large
+//  for queue.write_buffer are counted too, though the renderer only records them when the
+//  scene is drawn, see Scene::sync_scene_gpu_staged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    // Instances whose transform was uploaded. Syncs merge the changed instances of a model
+    //  into one range, so unchanged ones in between count too.
+    pub instances_updated: usize,
+    // Gpu buffers the cpu side outgrew, which were created again at a larger size.
+    pub buffers_reallocated: usize,
+    pub bytes_uploaded: usize,
+}
+
 pub struct Scene {
     pub models: Vec<Model>,
-    _lights: Vec<Light>,
+    lights: Vec<Light>,
+    // Never empty, a scene starts out with a default camera.
+    cameras: Vec<Camera>,
+    active_camera: CameraHandle,
+    // Updated along with the scene and drawn after its models.
+    particle_systems: Vec<ParticleSystem>,
     sync_queue: Vec<SyncJob>,
+    // Large syncs, performed by the renderer in the frame's command encoder.
+    staged_sync_queue: Vec<SyncJob>,
     graph: SceneNode,
+    // Paths of the nodes changed since the last update, see node_mut.
+    dirty_nodes: Vec<Vec<usize>>,
+    // Set by defer_updates. Changed paths wait in deferred_nodes until flush.
+    updates_deferred: bool,
+    deferred_nodes: Vec<Vec<usize>>,
+    // The node the *_selected methods move, see select_node.
+    selected_node: Option<Vec<usize>>,
 }
 
 impl Scene {
@@ -39,36 +104,41 @@ impl Scene {
     //  (provided a base model is already added.)
     pub fn make_galaxy(&mut self) {
         if self.models.len() == 1 {
-            // Create positioning nodes.
+            // Create positioning nodes. The orbits sit at the center they go around, their
+            //  rotation carries the children offset from it along.
             let mut solar_system = SceneNode::default();
-            let mut planet_orbit = SceneNode {
-                position: Vector3::new(2.0, 0.0, 0.0),
-                ..Default::default()
-            };
+            let mut planet_orbit = SceneNode::default();
+            planet_orbit.set_angular_velocity(Vector3::unit_z(), PLANET_ORBIT_SPEED);
             let mut moon_orbit = SceneNode {
-                position: Vector3::new(0.75, 0.0, 0.0),
+                position: Vector3::new(2.0, 0.0, 0.0),
                 ..Default::default()
             };
+            moon_orbit.set_angular_velocity(Vector3::unit_z(), MOON_ORBIT_SPEED);
 
-            // Create instances and retrieve their ids.
-            let sun = self.add_instance(0);
-            let earth = self.add_instance(0);
-            let moon = self.add_instance(0);
+            // Create instances and retrieve their ids. They share a model, so tell them
+            //  apart by color.
+            let sun = self.add_instance_with_tint(0, Some([1.0, 0.75, 0.3, 1.0]));
+            let earth = self.add_instance_with_tint(0, Some([0.4, 0.6, 1.0, 1.0]));
+            let moon = self.add_instance_with_tint(0, Some([0.8, 0.8, 0.8, 1.0]));
 
             // Add the instances as nodes to their positioning nodes.
-            solar_system.add_child(SceneNode {
+            let mut sun_node = SceneNode {
                 model_id: Some(0),
                 instance_id: Some(sun),
                 scale: 10.0,
                 ..Default::default()
-            });
+            };
+            sun_node.set_angular_velocity(Vector3::unit_x(), SUN_SPEED);
+            solar_system.add_child(sun_node);
             planet_orbit.add_child(SceneNode {
+                position: Vector3::new(2.0, 0.0, 0.0),
                 model_id: Some(0),
                 instance_id: Some(earth),
                 scale: 5.0,
                 ..Default::default()
             });
             moon_orbit.add_child(SceneNode {
+                position: Vector3::new(0.75, 0.0, 0.0),
                 model_id: Some(0),
                 instance_id: Some(moon),
                 scale: 2.0,
@@ -80,41 +150,171 @@ impl Scene {
             solar_system.add_child(planet_orbit);
 
             self.graph = solar_system;
+            self.dirty_nodes.push(vec![]);
         } else {
             panic!("No base model found!");
         }
     }
 
-    // Hardcode some node changes that demonstrate a moon orbiting a planet
-    //  which is orbiting a star. (they're all avaocado rn bite me)
-    pub fn animate_galaxy(&mut self, _dt: Duration) {
-        // Fetch the nodes by getting mutable refs to the slices.
-        // It's a bit dumb but necessary(?) as we can't just do multiple mutable borrows of
-        //  individual items in a vector. #JustRustThings
-        let (sun, planet_orbit) = self.graph.children.split_at_mut(1);
-        planet_orbit[0].rotate(Quaternion::from_axis_angle(
-            Vector3::unit_z(),
-            cgmath::Deg(0.1),
-        ));
-        let (_planet, moon_orbit) = planet_orbit[0].children.split_at_mut(1);
+    // The node at the end of `path`, like node_mut but only to look at.
+    pub fn node(&self, path: &[usize]) -> Option<&SceneNode> {
+        let mut node = &self.graph;
+        for &i in path {
+            node = node.children.get(i)?;
+        }
+        Some(node)
+    }
+
+    // The path of the node placing the instance, for node and node_mut.
+    pub fn node_path_of(&self, model_id: usize, instance_id: usize) -> Option<Vec<usize>> {
+        fn find(node: &SceneNode, target: (usize, usize), path: &mut Vec<usize>) -> bool {
+            if node.model_id == Some(target.0) && node.instance_id == Some(target.1) {
+                return true;
+            }
+            for (i, child) in node.children.iter().enumerate() {
+                path.push(i);
+                if find(child, target, path) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+
+        let mut path = vec![];
+        if find(&self.graph, (model_id, instance_id), &mut path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    // Shows or hides the instances of the node at the end of `path` and of everything below
+    //  it, so hiding a planet's orbit hides its moon too. See Model::set_instance_visible.
+    pub fn set_node_visible(&mut self, path: &[usize], visible: bool) {
+        fn visit(node: &SceneNode, visible: bool, models: &mut [Model]) {
+            if let (Some(model_id), Some(instance_id)) = (node.model_id, node.instance_id) {
+                if let Some(model) = models.get_mut(model_id) {
+                    model.set_instance_visible(instance_id, visible);
+                }
+            }
+            for child in &node.children {
+                visit(child, visible, models);
+            }
+        }
+
+        let mut node = &self.graph;
+        for &i in path {
+            node = match node.children.get(i) {
+                Some(child) => child,
+                None => return,
+            };
+        }
+        visit(node, visible, &mut self.models);
+    }
+
+    // The node at the end of `path` (child indices starting at the root), to change its
+    //  transform. It's synced on the next update along with everything below it, nodes
+    //  that weren't handed out here are skipped entirely.
+    pub fn node_mut(&mut self, path: &[usize]) -> Option<&mut SceneNode> {
+        let mut node = &mut self.graph;
+        for &i in path {
+            node = node.children.get_mut(i)?;
+        }
+        if self.updates_deferred {
+            self.deferred_nodes.push(path.to_vec());
+        } else {
+            self.dirty_nodes.push(path.to_vec());
+        }
+        Some(node)
+    }
+
+    // Marks the node at `path` and everything below it for syncing on the next update,
+    //  without changing it. node_mut already does this for the nodes it hands out.
+    pub fn mark_changed(&mut self, path: &[usize]) {
+        let _ = self.node_mut(path);
+    }
+
+    // Marks only the children of the node at `path`, for when they changed but the node
+    //  itself didn't. Their world matrices are recomputed from the node's current one.
+    pub fn update_children(&mut self, path: &[usize]) {
+        let count = self.node(path).map_or(0, |node| node.children.len());
+        for i in 0..count {
+            let mut child = path.to_vec();
+            child.push(i);
+            self.mark_changed(&child);
+        }
+    }
+
+    // Holds back the changes made through node_mut until flush, so updates in between
+    //  don't sync half of an edit. Spinning nodes keep turning meanwhile, but the ones
+    //  above a held back node are only synced with it.
+    pub fn defer_updates(&mut self) {
+        self.updates_deferred = true;
+    }
+
+    // Stops deferring, the nodes changed since defer_updates are synced on the next update.
+    pub fn flush(&mut self) {
+        self.updates_deferred = false;
+        self.dirty_nodes.append(&mut self.deferred_nodes);
+    }
+
+    // Picks the node translate_selected, rotate_selected and scale_selected act on, by its
+    //  path as in node_mut. None, or a path to a node that isn't there, clears it. The
+    //  path isn't updated when the graph changes shape.
+    pub fn select_node(&mut self, path: Option<Vec<usize>>) {
+        self.selected_node = path.filter(|path| self.node(path).is_some());
+    }
+
+    pub fn selected_node(&self) -> Option<&[usize]> {
+        self.selected_node.as_deref()
+    }
+
+    // Moves the selected node by offset, in its parent's space.
+    pub fn translate_selected(&mut self, offset: Vector3<f32>) {
+        if let Some(node) = self.selected_node_mut() {
+            node._translate(offset.x, offset.y, offset.z);
+        }
+    }
+
+    // Rotates the selected node in its own space, like SceneNode::rotate.
+    pub fn rotate_selected(&mut self, rotation: Quaternion<f32>) {
+        if let Some(node) = self.selected_node_mut() {
+            node.rotate(rotation);
+        }
+    }
+
+    // Multiplies the selected node's scale by factor.
+    pub fn scale_selected(&mut self, factor: f32) {
+        if let Some(node) = self.selected_node_mut() {
+            let scale = node.scale * factor;
+            node._set_scale(scale);
+        }
+    }
 
-        // rotate the nodes... needs indices because we're getting slices form split_at_mut.. *<|8D
-        sun[0].rotate(Quaternion::from_axis_angle(
-            Vector3::unit_x(),
-            cgmath::Deg(1.0),
-        ));
-        moon_orbit[0].rotate(Quaternion::from_axis_angle(
-            Vector3::unit_z(),
-            cgmath::Deg(-2.0),
-        ));
+    fn selected_node_mut(&mut self) -> Option<&mut SceneNode> {
+        let path = self.selected_node.clone()?;
+        self.node_mut(&path)
     }
 
     pub fn empty() -> Self {
         Self {
             models: vec![],
-            _lights: vec![],
+            lights: vec![],
+            cameras: vec![Camera::new(
+                (0.0, 5.0, 10.0),
+                cgmath::Deg(-90.0),
+                cgmath::Deg(-20.0),
+            )],
+            active_camera: 0,
+            particle_systems: vec![],
             sync_queue: vec![],
+            staged_sync_queue: vec![],
             graph: SceneNode::new_root(),
+            dirty_nodes: vec![],
+            updates_deferred: false,
+            deferred_nodes: vec![],
+            selected_node: None,
         }
     }
 
@@ -123,7 +323,51 @@ impl Scene {
     }
 
     pub fn _add_light(&mut self, light: Light) {
-        self._lights.push(light);
+        self.lights.push(light);
+    }
+
+    pub fn add_camera(&mut self, camera: Camera) -> CameraHandle {
+        self.cameras.push(camera);
+        self.cameras.len() - 1
+    }
+
+    // The scene is drawn from the active camera, see DrawScene::draw_scene.
+    pub fn set_active_camera(&mut self, camera_id: CameraHandle) {
+        assert!(
+            camera_id < self.cameras.len(),
+            "No camera with id {}",
+            camera_id
+        );
+        self.active_camera = camera_id;
+    }
+
+    pub fn active_camera_id(&self) -> CameraHandle {
+        self.active_camera
+    }
+
+    pub fn active_camera(&self) -> &Camera {
+        &self.cameras[self.active_camera]
+    }
+
+    pub fn active_camera_mut(&mut self) -> &mut Camera {
+        &mut self.cameras[self.active_camera]
+    }
+
+    pub fn camera(&self, camera_id: CameraHandle) -> &Camera {
+        &self.cameras[camera_id]
+    }
+
+    pub fn add_particle_system(&mut self, particle_system: ParticleSystem) -> ParticleSystemHandle {
+        self.particle_systems.push(particle_system);
+        self.particle_systems.len() - 1
+    }
+
+    // To emit particles, they're moved by update.
+    pub fn particle_system_mut(
+        &mut self,
+        particle_system_id: ParticleSystemHandle,
+    ) -> &mut ParticleSystem {
+        &mut self.particle_systems[particle_system_id]
     }
 
     // Creates a new instance of a previously loaded model and returns the instance_id,
@@ -138,6 +382,65 @@ impl Scene {
         instance_id
     }
 
+    // Same as add_instance, but places the instance at the origin with an optional tint.
+    //  Its transform is expected to be driven by the scene graph.
+    pub fn add_instance_with_tint(&mut self, model_id: usize, tint: Option<[f32; 4]>) -> usize {
+        self.models[model_id].add_instance_with_transform(Matrix4::identity(), tint);
+        let instance_id = self.models[model_id].get_num_instances() - 1;
+        self.sync_queue.push(SyncJob::Instance {
+            model_id,
+            instance_id,
+        });
+        instance_id
+    }
+
+    // Spawns rows x cols instances of a model on a grid in the xz plane, spacing apart and
+    //  centered on the origin, for stress testing with lots of instances. They're tilted
+    //  45 degrees around the direction away from the center so they don't all look the
+    //  same. The instances are placed by one node each, under a single new node at the root
+    //  that moves the whole grid.
+    pub fn spawn_instance_grid(
+        &mut self,
+        model_id: ModelHandle,
+        rows: u32,
+        cols: u32,
+        spacing: f32,
+    ) -> Vec<InstanceHandle> {
+        let displacement = Vector3::new(
+            (cols as f32 - 1.0) * spacing * 0.5,
+            0.0,
+            (rows as f32 - 1.0) * spacing * 0.5,
+        );
+
+        let mut grid = SceneNode::default();
+        let mut instance_ids = Vec::with_capacity((rows * cols) as usize);
+        for z in 0..rows {
+            for x in 0..cols {
+                let position =
+                    Vector3::new(x as f32 * spacing, 0.0, z as f32 * spacing) - displacement;
+                let rotation = if position.is_zero() {
+                    Quaternion::one()
+                } else {
+                    Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+                };
+
+                let instance_id = self.add_instance_with_tint(model_id, None);
+                grid.add_child(SceneNode {
+                    position,
+                    rotation,
+                    model_id: Some(model_id),
+                    instance_id: Some(instance_id),
+                    ..Default::default()
+                });
+                instance_ids.push(instance_id);
+            }
+        }
+
+        self.graph.add_child(grid);
+        self.dirty_nodes.push(vec![self.graph.children.len() - 1]);
+        instance_ids
+    }
+
     pub fn _make_instance_child_of(
         &mut self,
         model_id: usize,
@@ -145,25 +448,160 @@ impl Scene {
         id_chain: Vec<usize>,
     ) {
         let mut node = &mut self.graph;
-        for i in id_chain {
+        for &i in &id_chain {
             node = &mut node.children[i];
         }
         node.add_child(SceneNode::_new_instance_node(model_id, instance_id));
         dbg!(&node.children.len());
+        let mut path = id_chain;
+        path.push(node.children.len() - 1);
+        self.dirty_nodes.push(path);
+    }
+
+    // Call after editing a mesh's vertices and/or indices through its resources, the
+    //  changes are synced on the next update.
+    pub fn mark_mesh_changed(&mut self, model_id: usize, mesh_id: usize) {
+        self.sync_queue.push(SyncJob::Vertex { model_id, mesh_id });
+        self.sync_queue.push(SyncJob::Index { model_id, mesh_id });
+    }
+
+    // See Model::reload. Queued uploads of the old meshes are dropped, the new ones are
+    //  uploaded already and may not have the same number of meshes.
+    pub fn reload_model(
+        &mut self,
+        renderer: &crate::renderer::Renderer,
+        model_id: usize,
+    ) -> anyhow::Result<()> {
+        self.models[model_id].reload(renderer)?;
+        let stale = |job: &SyncJob| match job {
+            SyncJob::Vertex { model_id: id, .. } | SyncJob::Index { model_id: id, .. } => {
+                *id == model_id
+            }
+            _ => false,
+        };
+        self.sync_queue.retain(|job| !stale(job));
+        self.staged_sync_queue.retain(|job| !stale(job));
+        Ok(())
     }
 
     pub fn _remove_instance_of(&mut self, id: usize) {
         // No need to sync, because we can just call draw_indexed with a smaller range?
+        //  Do give back gpu memory once enough instances are gone though.
         self.models[id]._remove_instance();
+        self.models[id].instance_resource.shrink_gpu();
+    }
+
+    // Computes the world matrix of the node at the end of `path` (child indices starting
+    //  at the root) by accumulating local matrices. Unlike collect_changed this ignores the
+    //  changed flag, so it can be queried at any time.
+    pub fn world_transform(&self, path: &[usize]) -> Option<Matrix4<f32>> {
+        let mut node = &self.graph;
+        let mut mat = node.local_matrix();
+        for &i in path {
+            node = node.children.get(i)?;
+            mat = mat * node.local_matrix();
+        }
+        Some(mat)
+    }
+
+    // Every instance placed by a node, with its world matrix. Like world_transform the
+    //  matrices are accumulated from the local ones, so nodes moved since the last update
+    //  are already where they'll be drawn. Hidden instances are in there too, see
+    //  Model::is_instance_visible. Instances without a node aren't, nothing places them.
+    pub fn iter_instances(
+        &self,
+    ) -> impl Iterator<Item = (ModelHandle, InstanceHandle, Matrix4<f32>)> {
+        fn visit(
+            node: &SceneNode,
+            parent_mat: Matrix4<f32>,
+            result: &mut Vec<(ModelHandle, InstanceHandle, Matrix4<f32>)>,
+        ) {
+            let mat = parent_mat * node.local_matrix();
+            if let (Some(model_id), Some(instance_id)) = (node.model_id, node.instance_id) {
+                result.push((model_id, instance_id, mat));
+            }
+            for child in &node.children {
+                visit(child, mat, result);
+            }
+        }
+
+        let mut result = vec![];
+        visit(&self.graph, Matrix4::identity(), &mut result);
+        result.into_iter()
+    }
+
+    // Tests the ray against the world space bounding box of every instance and returns
+    //  the nearest hit together with its distance along the ray.
+    //  TODO: refine hits against the actual triangles.
+    pub fn raycast(
+        &self,
+        origin: Vector3<f32>,
+        dir: Vector3<f32>,
+    ) -> Option<(ModelHandle, InstanceHandle, f32)> {
+        let mut nearest: Option<(ModelHandle, InstanceHandle, f32)> = None;
+        for (model_id, model) in self.models.iter().enumerate() {
+            for instance_id in 0..model.get_num_instances() {
+                if !model.is_instance_visible(instance_id) {
+                    continue;
+                }
+                let bounds = match model.instance_bounds(instance_id) {
+                    Some(b) => b,
+                    None => continue,
+                };
+                if let Some(t) = ray_intersects_aabb(origin, dir, bounds) {
+                    let closer = match nearest {
+                        Some((_, _, nearest_t)) => t < nearest_t,
+                        None => true,
+                    };
+                    if closer {
+                        nearest = Some((model_id, instance_id, t));
+                    }
+                }
+            }
+        }
+        nearest
     }
 
     // Stub of collecting sync jobs.
     fn collect_sync_jobs(&mut self) {
-        // Construct root world matrix, so we can propagate it through the tree.
-        let root_mat = Matrix4::from(self.graph.rotation)
-            * Matrix4::from_translation(self.graph.position)
-            * Matrix4::from_scale(self.graph.scale);
-        let changed = self.graph.collect_changed(root_mat);
+        // A dirty node above one held back by defer_updates would collect the half done
+        //  edit along with it, so it waits for flush as well.
+        let deferred_nodes = &self.deferred_nodes;
+        let (held, mut dirty_nodes): (Vec<Vec<usize>>, Vec<Vec<usize>>) = self
+            .dirty_nodes
+            .drain(..)
+            .partition(|path| deferred_nodes.iter().any(|deferred| deferred.starts_with(path)));
+        for path in held {
+            if !self.deferred_nodes.contains(&path) {
+                self.deferred_nodes.push(path);
+            }
+        }
+
+        // Only the dirty nodes and what's below them moved. A node below another dirty one
+        //  is collected with it, sorting puts every path right after its ancestors.
+        dirty_nodes.sort();
+        dirty_nodes.dedup();
+        let mut roots: Vec<Vec<usize>> = vec![];
+        for path in dirty_nodes {
+            if !roots.iter().any(|root| path.starts_with(root)) {
+                roots.push(path);
+            }
+        }
+
+        let mut changed = vec![];
+        for path in roots {
+            // The parent's world matrix is still the one from its last collect, nothing
+            //  above a dirty subtree has moved. The root applies its own local matrix, so
+            //  it starts from identity. This keeps the results in line with
+            //  Scene::world_transform.
+            let mut parent_mat = Matrix4::identity();
+            let mut node = &mut self.graph;
+            for &i in &path {
+                parent_mat = node.world_matrix;
+                node = &mut node.children[i];
+            }
+            changed.append(&mut node.collect_changed(parent_mat));
+        }
 
         // Collect instance sync jobs
         let mut instance_syncs: Vec<Vec<(usize, Matrix4<f32>)>> = vec![vec![]; self.models.len()];
@@ -176,110 +614,848 @@ impl Scene {
             }
         }
 
-        // TODO: Collecting consecutive insance ids to use sliced buffer writes would go
-        //  here I guess. Just need to profile if worth.
         for (model_id, model) in instance_syncs.iter().enumerate() {
             for (instance_id, instance) in model {
                 self.models[model_id].change_instance_raw(*instance_id, *instance);
             }
-            // Instances of a model are on the same resource so we can just sync.
-            //  once for now. TODO: fix syncing the same resource multiple times on
-            //    seperate instance chances.
-            self.sync_queue.push(SyncJob::Instance {
-                model_id,
-                instance_id: 0,
-            });
+            // Only upload what changed, in one buffer write from the first changed instance
+            //  to the last. merge_sync_jobs joins it with the model's other instance jobs.
+            let mut instance_ids = None;
+            for (instance_id, _) in model {
+                cover_range(&mut instance_ids, *instance_id..*instance_id + 1);
+            }
+            if let Some(instance_ids) = instance_ids {
+                self.sync_queue.push(SyncJob::Instances {
+                    model_id,
+                    instance_ids,
+                });
+            }
         }
+
+        self.merge_sync_jobs();
+    }
+
+    // Merges the queued jobs so every resource is synced at most once per update, see
+    //  merge_sync_jobs below.
+    fn merge_sync_jobs(&mut self) {
+        let jobs = std::mem::take(&mut self.sync_queue);
+        self.sync_queue = merge_sync_jobs(jobs, self.models.len());
     }
 
-    // Stub of what the sync fn could look like.
-    // TODO: shouldn't sync whole instance resource on a singular change. WIP
-    //  - Also syncs the resource multile times on changes of seperate instances of the resource. >:(
-    fn sync_scene_gpu(&mut self) {
-        for job in &self.sync_queue {
+    // Performs the queued jobs, which merge_sync_jobs has reduced to one per resource.
+    //  Large jobs are left for sync_scene_gpu_staged.
+    fn sync_scene_gpu(&mut self) -> SyncReport {
+        let mut report = SyncReport::default();
+        for job in std::mem::take(&mut self.sync_queue) {
+            self.report_job(&job, &mut report);
+            if self.job_size(&job) >= STAGED_SYNC_BYTES {
+                self.staged_sync_queue.push(job);
+                continue;
+            }
+
             match job {
                 SyncJob::Instance { model_id, .. } => {
-                    self.models[*model_id].instance_resource.sync_gpu();
+                    self.models[model_id].instance_resource.sync_gpu();
                 }
-                _ => unimplemented!["Scene::sync_scene_gpu not implementend SyncJob case!"],
+                SyncJob::Instances {
+                    model_id,
+                    instance_ids,
+                } => {
+                    let offset = instance_ids.start * std::mem::size_of::<InstanceRaw>();
+                    self.models[model_id]
+                        .instance_resource
+                        ._partial_sync_gpu(instance_ids, offset);
+                }
+                SyncJob::Vertex { model_id, mesh_id } => {
+                    self.models[model_id].meshes[mesh_id]
+                        .vertex_resource
+                        .sync_gpu();
+                }
+                SyncJob::Index { model_id, mesh_id } => {
+                    self.models[model_id].meshes[mesh_id]
+                        .index_resource
+                        .sync_gpu();
+                }
+                SyncJob::Animation => {}
             }
         }
+        report
+    }
 
-        self.sync_queue.clear();
+    // Adds what the job is about to upload to the report. A buffer that has to grow is
+    //  uploaded in full, whatever part the job asked for.
+    fn report_job(&self, job: &SyncJob, report: &mut SyncReport) {
+        let (reallocates, instances, full_bytes) = match job {
+            SyncJob::Instance { model_id, .. } | SyncJob::Instances { model_id, .. } => {
+                let resource = &self.models[*model_id].instance_resource;
+                (
+                    resource.needs_grow(),
+                    resource.get_cpu_length(),
+                    resource.get_cpu_byte_length(),
+                )
+            }
+            SyncJob::Vertex { model_id, mesh_id } => {
+                let resource = &self.models[*model_id].meshes[*mesh_id].vertex_resource;
+                (resource.needs_grow(), 0, resource.get_cpu_byte_length())
+            }
+            SyncJob::Index { model_id, mesh_id } => {
+                let resource = &self.models[*model_id].meshes[*mesh_id].index_resource;
+                (resource.needs_grow(), 0, resource.get_cpu_byte_length())
+            }
+            SyncJob::Animation => (false, 0, 0),
+        };
+
+        if reallocates {
+            report.buffers_reallocated += 1;
+        }
+        // Staged instance syncs upload the whole resource too.
+        if reallocates || self.job_size(job) >= STAGED_SYNC_BYTES {
+            report.instances_updated += instances;
+            report.bytes_uploaded += full_bytes;
+            return;
+        }
+        report.instances_updated += match job {
+            SyncJob::Instance { .. } => instances,
+            SyncJob::Instances { instance_ids, .. } => instance_ids.len(),
+            _ => 0,
+        };
+        report.bytes_uploaded += self.job_size(job);
+    }
+
+    // Performs the syncs that were too large for queue.write_buffer by recording them into
+    //  the frame's encoder through the staging belt. Large instance syncs upload the whole
+    //  resource, as that's what they nearly amount to anyway.
+    pub fn sync_scene_gpu_staged(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut wgpu::util::StagingBelt,
+    ) {
+        for job in self.staged_sync_queue.drain(..) {
+            match job {
+                SyncJob::Instance { model_id, .. } | SyncJob::Instances { model_id, .. } => {
+                    self.models[model_id]
+                        .instance_resource
+                        .sync_gpu_staged(encoder, belt);
+                }
+                SyncJob::Vertex { model_id, mesh_id } => {
+                    self.models[model_id].meshes[mesh_id]
+                        .vertex_resource
+                        .sync_gpu_staged(encoder, belt);
+                }
+                SyncJob::Index { model_id, mesh_id } => {
+                    self.models[model_id].meshes[mesh_id]
+                        .index_resource
+                        .sync_gpu_staged(encoder, belt);
+                }
+                // Uploads nothing, so it's never large enough to end up here.
+                SyncJob::Animation => {}
+            }
+        }
+    }
+
+    // Number of bytes a job uploads.
+    fn job_size(&self, job: &SyncJob) -> usize {
+        match job {
+            SyncJob::Instance { model_id, .. } => {
+                self.models[*model_id].instance_resource.get_cpu_byte_length()
+            }
+            SyncJob::Instances { instance_ids, .. } => {
+                instance_ids.len() * std::mem::size_of::<InstanceRaw>()
+            }
+            SyncJob::Vertex { model_id, mesh_id } => self.models[*model_id].meshes[*mesh_id]
+                .vertex_resource
+                .get_cpu_byte_length(),
+            SyncJob::Index { model_id, mesh_id } => self.models[*model_id].meshes[*mesh_id]
+                .index_resource
+                .get_cpu_byte_length(),
+            SyncJob::Animation => 0,
+        }
     }
 
     // fn unpdate stub that would also handle animations, scenegraph updates etc.
-    //  For now it just collects sync jobs and syncs.
-    pub fn update(&mut self, _dt: Duration) {
+    //  For now it just collects sync jobs and syncs, and reports what that uploaded.
+    //  Particle systems upload their own buffers and aren't in the report.
+    pub fn update(&mut self, dt: Duration) -> SyncReport {
+        // Nodes with an angular velocity turn on their own, see SceneNode::spin.
+        self.graph
+            .spin(dt.as_secs_f32(), &mut vec![], &mut self.dirty_nodes);
         self.collect_sync_jobs();
-        self.sync_scene_gpu();
+        let report = self.sync_scene_gpu();
+        for particle_system in &mut self.particle_systems {
+            particle_system.update(dt);
+        }
+        report
     }
 
     pub fn _set_scale<T: Into<f32>>(&mut self, scale: T) {
         self.graph._set_scale(scale.into());
+        self.dirty_nodes.push(vec![]);
     }
 }
 
-pub trait DrawScene {
-    fn draw_scene(&mut self, scene: &Scene) -> Result<(), wgpu::SurfaceError>;
-}
+// All instance jobs of a model collapse into a single range covering every changed
+//  instance, which may re-upload some unchanged ones in between but saves on writes.
+//  Other jobs are kept once each.
+fn merge_sync_jobs(jobs: Vec<SyncJob>, model_count: usize) -> Vec<SyncJob> {
+    let mut instance_ranges: Vec<Option<Range<usize>>> = vec![None; model_count];
+    let mut merged = vec![];
 
-impl DrawScene for crate::renderer::Renderer {
-    // Draws all models and their instances.
-    fn draw_scene(&mut self, scene: &Scene) -> Result<(), wgpu::SurfaceError> {
-        // TODO: figure out how to deal with this renderpass initialization boilerplate code.
-        //  - I tried putting the code in a renderer::get_forward_pass() -> Result<(RndrPss, CmndEnc, SwpChTx), err>
-        //      - But it had borrow issues as &frame.fiew is used by render_pass...
-        //      - THERE HAS TO BE a "clean" way of abstracting this away tho?
-        //      - Maybe do some Drop trait for a render_pass abstraction, keeping ownership to some Renderer field AND,
-        //          borrowing the render_pass abstraction to draw fns that request some render_pass abstraction?
-        //          - Dropping it could perhaps also mean "submit cmd encoder to queue"? <-- prolly pretty unintuitive to any1 but me tho
-        let frame = self.surface.get_current_texture()?;
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
-        // Encoders can create a commandbuffer.
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
+    for job in jobs {
+        match job {
+            SyncJob::Instance {
+                model_id,
+                instance_id,
+            } => cover_range(
+                &mut instance_ranges[model_id],
+                instance_id..instance_id + 1,
+            ),
+            SyncJob::Instances {
+                model_id,
+                instance_ids,
+            } => cover_range(&mut instance_ranges[model_id], instance_ids),
+            job => {
+                if !merged.contains(&job) {
+                    merged.push(job);
+                }
+            }
+        }
+    }
+
+    for (model_id, range) in instance_ranges.into_iter().enumerate() {
+        if let Some(instance_ids) = range {
+            merged.push(SyncJob::Instances {
+                model_id,
+                instance_ids,
             });
+        }
+    }
 
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Scene render pass"),
-            color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(self.clear_color),
-                    store: true,
-                },
-            }],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: true,
-                }),
-                stencil_ops: None,
-            }),
-        });
+    merged
+}
+
+// Grows range so it also covers other.
+fn cover_range(range: &mut Option<Range<usize>>, other: Range<usize>) {
+    *range = Some(match range.take() {
+        Some(r) => r.start.min(other.start)..r.end.max(other.end),
+        None => other,
+    });
+}
+
+// Slab test, returns the distance along the ray to where it enters the box
+//  (or 0 when the origin is inside it).
+fn ray_intersects_aabb(
+    origin: Vector3<f32>,
+    dir: Vector3<f32>,
+    (min, max): (Vector3<f32>, Vector3<f32>),
+) -> Option<f32> {
+    let mut t_enter = 0.0f32;
+    let mut t_exit = f32::MAX;
+    for axis in 0..3 {
+        let inverse_dir = 1.0 / dir[axis];
+        let mut t0 = (min[axis] - origin[axis]) * inverse_dir;
+        let mut t1 = (max[axis] - origin[axis]) * inverse_dir;
+        if inverse_dir < 0.0 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        t_enter = t_enter.max(t0);
+        t_exit = t_exit.min(t1);
+        if t_exit < t_enter {
+            return None;
+        }
+    }
+    Some(t_enter)
+}
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        for m in &scene.models {
-            render_pass.draw_model_instanced(
-                &m,
+// Opaque models in one instanced draw each, or a few when culling left gaps in their
+//  instances.
+fn draw_opaque_models<'a, 'b: 'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    renderer: &'a crate::renderer::Renderer,
+    uniform_bind_group: &'a wgpu::BindGroup,
+    scene: &'a Scene,
+    per_draw: &impl Fn(usize) -> PerDraw<'b>,
+    shading_mode: ShadingMode,
+    visibility: &[Visibility<'a>],
+) {
+    for (model_id, m) in scene.models.iter().enumerate() {
+        if m.is_transparent() {
+            continue;
+        }
+        match &visibility[model_id] {
+            Visibility::All => draw_model_culled(
+                render_pass,
+                renderer,
+                uniform_bind_group,
+                m,
                 0..m.get_num_instances() as u32,
-                &self.uniform_bind_group,
-                &self.light_bind_group,
+                per_draw(model_id),
+                shading_mode,
+            ),
+            Visibility::Ranges(ranges) => {
+                for range in ranges {
+                    draw_model_culled(
+                        render_pass,
+                        renderer,
+                        uniform_bind_group,
+                        m,
+                        range.clone(),
+                        per_draw(model_id),
+                        shading_mode,
+                    );
+                }
+            }
+            Visibility::Indirect(target) => draw_model_indirect(
+                render_pass,
+                renderer,
+                uniform_bind_group,
+                m,
+                target,
+                per_draw(model_id),
+                shading_mode,
+            ),
+            Visibility::Lod { instances, levels } => draw_model_lods(
+                render_pass,
+                renderer,
+                uniform_bind_group,
+                m,
+                (instances, levels),
+                per_draw(model_id),
+                shading_mode,
+            ),
+        }
+    }
+}
+
+// Double sided materials are drawn without backface culling, depth biased ones with the
+//  renderer's depth bias and transparent ones blended. Deferred shading draws into the
+//  G-buffer instead, transparent materials are always drawn forward.
+fn mesh_pipeline<'a>(
+    renderer: &'a crate::renderer::Renderer,
+    shading_mode: ShadingMode,
+    material: &Material,
+) -> &'a wgpu::RenderPipeline {
+    if material.depth_bias {
+        return match shading_mode {
+            ShadingMode::Forward => &renderer.depth_bias_render_pipeline,
+            ShadingMode::Deferred => &renderer.deferred.depth_bias_pipeline,
+        };
+    }
+    if material.transparent && shading_mode == ShadingMode::Forward {
+        return if material.double_sided {
+            &renderer.transparent_no_cull_render_pipeline
+        } else {
+            &renderer.transparent_render_pipeline
+        };
+    }
+    match (shading_mode, material.double_sided) {
+        (ShadingMode::Forward, false) => &renderer.render_pipeline,
+        (ShadingMode::Forward, true) => &renderer.no_cull_render_pipeline,
+        (ShadingMode::Deferred, false) => &renderer.deferred.pipeline,
+        (ShadingMode::Deferred, true) => &renderer.deferred.no_cull_pipeline,
+    }
+}
+
+// Like DrawModel::draw_model_instanced, but picks the pipeline per mesh, see
+//  mesh_pipeline.
+fn draw_model_culled<'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    renderer: &'a crate::renderer::Renderer,
+    uniform_bind_group: &'a wgpu::BindGroup,
+    model: &'a Model,
+    instances: Range<u32>,
+    draw: PerDraw<'a>,
+    shading_mode: ShadingMode,
+) {
+    render_pass.set_vertex_buffer(1, model.instance_resource.get_gpu_buffer().slice(..));
+    for mesh in &model.meshes {
+        let material = &model.materials[mesh.material];
+        render_pass.set_pipeline(mesh_pipeline(renderer, shading_mode, material));
+        render_pass.draw_mesh_instanced(
+            mesh,
+            instances.clone(),
+            material,
+            uniform_bind_group,
+            &renderer.light_bind_group,
+            draw,
+        );
+        renderer.record_draw(instances.end - instances.start, mesh.num_elements());
+    }
+}
+
+// Draws each level of detail's instances in one go, given as the buffer they were sorted
+//  into and the range of each level in it. See lod.rs.
+fn draw_model_lods<'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    renderer: &'a crate::renderer::Renderer,
+    uniform_bind_group: &'a wgpu::BindGroup,
+    model: &'a Model,
+    (instances, levels): (&'a wgpu::Buffer, &[Range<u32>]),
+    draw: PerDraw<'a>,
+    shading_mode: ShadingMode,
+) {
+    render_pass.set_vertex_buffer(1, instances.slice(..));
+    for (level, range) in levels.iter().enumerate() {
+        if range.is_empty() {
+            continue;
+        }
+        for mesh in model.lod_meshes(level) {
+            let material = &model.materials[mesh.material];
+            render_pass.set_pipeline(mesh_pipeline(renderer, shading_mode, material));
+            render_pass.draw_mesh_instanced(
+                mesh,
+                range.clone(),
+                material,
+                uniform_bind_group,
+                &renderer.light_bind_group,
+                draw,
             );
+            renderer.record_draw(range.end - range.start, mesh.num_elements());
+        }
+    }
+}
+
+// Draws the instances the gpu culling copied to the target, see culling.rs.
+fn draw_model_indirect<'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    renderer: &'a crate::renderer::Renderer,
+    uniform_bind_group: &'a wgpu::BindGroup,
+    model: &'a Model,
+    target: &'a CullTarget,
+    draw: PerDraw<'a>,
+    shading_mode: ShadingMode,
+) {
+    render_pass.set_vertex_buffer(1, target.instance_buffer.slice(..));
+    for (mesh_id, mesh) in model.meshes.iter().enumerate() {
+        let material = &model.materials[mesh.material];
+        render_pass.set_pipeline(mesh_pipeline(renderer, shading_mode, material));
+        render_pass.draw_mesh_indirect(
+            mesh,
+            &target.indirect_buffer,
+            CullTarget::indirect_offset(mesh_id),
+            material,
+            uniform_bind_group,
+            &renderer.light_bind_group,
+            draw,
+        );
+        // Only the gpu knows how many instances are left.
+        renderer.record_draw(0, 0);
+    }
+}
+
+pub trait DrawScene {
+    fn draw_scene(&mut self, scene: &mut Scene) -> Result<(), wgpu::SurfaceError>;
+    fn draw_scene_viewports(
+        &mut self,
+        scene: &mut Scene,
+        viewports: &[Viewport],
+    ) -> Result<(), wgpu::SurfaceError>;
+}
+
+impl DrawScene for crate::renderer::Renderer {
+    // Draws all models and their instances from the scene's active camera.
+    fn draw_scene(&mut self, scene: &mut Scene) -> Result<(), wgpu::SurfaceError> {
+        let viewport = Viewport::full(self.get_size(), scene.active_camera().clone());
+        self.draw_scene_viewports(scene, &[viewport])
+    }
+
+    // Draws the scene once per viewport, each from its own camera into its part of the
+    //  window. They share one render pass, so the frame is only cleared once.
+    fn draw_scene_viewports(
+        &mut self,
+        scene: &mut Scene,
+        viewports: &[Viewport],
+    ) -> Result<(), wgpu::SurfaceError> {
+        let mut forward_pass = ForwardPass::begin(&self.target, &self.device)?;
+        self.reset_frame_stats();
+
+        // The projections have to map depth the way the pipelines compare it, see
+        //  Renderer::set_reverse_z.
+        let viewports: Vec<Viewport> = viewports
+            .iter()
+            .cloned()
+            .map(|mut viewport| {
+                viewport.camera.projection.set_reverse_z(self.reverse_z());
+                viewport
+            })
+            .collect();
+        let viewports = &viewports[..];
+
+        // Record the large resource syncs before drawing.
+        scene.sync_scene_gpu_staged(&mut forward_pass.encoder, &mut self.staging_belt);
+        self.staging_belt.finish();
+
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.start(&self.device, &mut forward_pass.encoder);
+        }
+
+        self.prepare_viewports(viewports);
+
+        // One set of draw constants per model.
+        let draws: Vec<DrawConstants> = (0..scene.models.len())
+            .map(|model_id| {
+                DrawConstants::new(match self.selection {
+                    Some((selected_model, instance_id)) if selected_model == model_id => {
+                        Some(instance_id)
+                    }
+                    _ => None,
+                })
+            })
+            .collect();
+        if let Some(draw_constants_buffer) = &mut self.draw_constants_buffer {
+            draw_constants_buffer.write(&self.device, &self.queue, &draws);
+        }
+
+        // The boxes are shared by all viewports, so they're colored against the frustum of
+        //  the scene's active camera.
+        if self.debug_bounds {
+            let camera = scene.active_camera();
+            let view_proj = camera.view_projection();
+            self.debug_lines.clear();
+            for m in &scene.models {
+                for instance_id in 0..m.get_num_instances() {
+                    if !m.is_instance_visible(instance_id) {
+                        continue;
+                    }
+                    if let Some(bounds) = m.instance_bounds(instance_id) {
+                        self.debug_lines.add_bounds(bounds, view_proj);
+                    }
+                }
+            }
+            self.debug_lines.sync_gpu();
+        }
+
+        // The selected model isn't culled, see Culling.
+        let selected_model = self.selection.map(|(model_id, _)| model_id);
+        self.culling.record(
+            &self.device,
+            &self.queue,
+            &mut forward_pass.encoder,
+            &scene.models,
+            viewports,
+            selected_model,
+        );
+        let culling = &self.culling;
+        let mut visibility: Vec<Vec<Visibility>> = viewports
+            .iter()
+            .enumerate()
+            .map(|(viewport_id, viewport)| {
+                culling.visibility(&scene.models, viewport_id, viewport, selected_model)
+            })
+            .collect();
+        // Then what's left is sorted by level of detail.
+        self.lod_instances
+            .prepare(&scene.models, viewports, &visibility, selected_model);
+        for (viewport_id, visibility) in visibility.iter_mut().enumerate() {
+            self.lod_instances.apply(viewport_id, visibility);
+        }
+
+        let per_draw = |model_id: usize| match &self.draw_constants_buffer {
+            Some(draw_constants_buffer) => draw_constants_buffer.per_draw(model_id),
+            None => PerDraw::PushConstants(draws[model_id]),
+        };
+
+        // Deferred shading fills the G-buffer with the opaque models first, it's lit in the
+        //  pass below.
+        let shading_mode = self.shading_mode();
+        if shading_mode == ShadingMode::Deferred {
+            let mut gbuffer_pass = self
+                .deferred
+                .gbuffer_pass(&mut forward_pass.encoder, &self.depth_texture.view);
+            for (viewport_id, viewport) in viewports.iter().enumerate() {
+                set_viewport(&mut gbuffer_pass, viewport.rect);
+                draw_opaque_models(
+                    &mut gbuffer_pass,
+                    self,
+                    self.viewport_bind_group(viewport_id),
+                    scene,
+                    &per_draw,
+                    shading_mode,
+                    &visibility[viewport_id],
+                );
+            }
+            drop(gbuffer_pass);
+
+            self.draw_ssao(&mut forward_pass.encoder, viewports);
+        }
+
+        // The G-buffer pass already filled the depth buffer, keep it for the models drawn
+        //  on top of the lit result.
+        let depth_load = match shading_mode {
+            ShadingMode::Forward => wgpu::LoadOp::Clear(self.depth_clear_value()),
+            ShadingMode::Deferred => wgpu::LoadOp::Load,
+        };
+        // With MSAA the scene is drawn multisampled and resolved into the HDR target.
+        let (color_view, resolve_target) = match &self.msaa_texture {
+            Some(msaa_texture) => (&msaa_texture.view, Some(&self.hdr_texture.view)),
+            None => (&self.hdr_texture.view, None),
+        };
+        let mut render_pass = forward_pass.pass(
+            color_view,
+            resolve_target,
+            self.clear_color,
+            &self.depth_texture.view,
+            depth_load,
+        );
+
+        for (viewport_id, viewport) in viewports.iter().enumerate() {
+            set_viewport(&mut render_pass, viewport.rect);
+            let uniform_bind_group = self.viewport_bind_group(viewport_id);
+
+            // Draw the skybox first, everything else ends up in front of it.
+            if let Some((_, skybox_bind_group)) = &self.skybox {
+                render_pass.set_pipeline(&self.skybox_pipeline);
+                render_pass.set_bind_group(0, uniform_bind_group, &[]);
+                render_pass.set_bind_group(1, skybox_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+
+            match shading_mode {
+                ShadingMode::Forward => draw_opaque_models(
+                    &mut render_pass,
+                    self,
+                    uniform_bind_group,
+                    scene,
+                    &per_draw,
+                    shading_mode,
+                    &visibility[viewport_id],
+                ),
+                ShadingMode::Deferred => self.deferred.draw_lighting(
+                    &mut render_pass,
+                    uniform_bind_group,
+                    &self.light_bind_group,
+                ),
+            }
+
+            // Transparent ones after, back to front so they composite right. The instances
+            //  share one buffer, so each gets its own draw call in sorted order. That's a draw
+            //  per instance per mesh instead of one per mesh, fine for a handful of
+            //  transparent instances but costly for many. Sorting across models isn't done
+            //  (yet).
+            for (model_id, m) in scene.models.iter().enumerate() {
+                if !m.is_transparent() {
+                    continue;
+                }
+                for instance_id in m.instances_back_to_front(viewport.camera.position) {
+                    let instance_id = instance_id as u32;
+                    draw_model_culled(
+                        &mut render_pass,
+                        self,
+                        uniform_bind_group,
+                        m,
+                        instance_id..instance_id + 1,
+                        per_draw(model_id),
+                        ShadingMode::Forward,
+                    );
+                }
+            }
+
+            for particle_system in &scene.particle_systems {
+                let count = particle_system.draw(
+                    &mut render_pass,
+                    &self.particle_pipeline,
+                    uniform_bind_group,
+                );
+                if count > 0 {
+                    // The two triangles of each quad.
+                    self.record_draw(count, 6);
+                }
+            }
+
+            if self.debug_bounds {
+                self.debug_lines.draw(&mut render_pass, uniform_bind_group);
+            }
         }
 
         drop(render_pass);
-        self.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
+        drop(visibility);
+
+        // Copy the HDR target to the frame, with the bloom added if it's on.
+        self.bloom.draw(&mut forward_pass.encoder);
+        let mut output_pass = forward_pass.output_pass();
+        self.bloom.composite(&mut output_pass);
+        drop(output_pass);
+
+        #[cfg(feature = "debug-ui")]
+        self.debug_ui.draw(
+            &self.device,
+            &self.queue,
+            &mut forward_pass.encoder,
+            &forward_pass.view,
+            self.get_size(),
+        );
+
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.finish(&mut forward_pass.encoder);
+        }
+        forward_pass.submit(&self.queue);
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.submitted();
+        }
+
+        // Hand the staging buffers back to the belt once the gpu is done with them.
+        self.local_pool
+            .spawner()
+            .spawn(self.staging_belt.recall())
+            .expect("Failed to spawn staging belt recall.");
+        self.local_pool.run_until_stalled();
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::test_renderer;
+
+    // Moving instances 3..6 of a model with 100 only uploads those three.
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn moved_instances_upload_alone() {
+        let renderer = test_renderer();
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/res/avocado");
+        let path = path.join("Avocado.glb");
+        let mut scene = Scene::empty();
+        scene.add_model(Model::load(&renderer, path).unwrap());
+        let instance_ids = scene.spawn_instance_grid(0, 10, 10, 1.0);
+        assert_eq!(scene.models[0].get_num_instances(), 100);
+        scene.update(Duration::from_secs(0));
+
+        for &instance_id in &instance_ids[3..6] {
+            let path = scene.node_path_of(0, instance_id).unwrap();
+            scene.node_mut(&path).unwrap()._translate(0.0, 1.0, 0.0);
+        }
+        let report = scene.update(Duration::from_secs(0));
+        assert_eq!(report.instances_updated, 3);
+        assert_eq!(report.buffers_reallocated, 0);
+        assert_eq!(report.bytes_uploaded, 3 * std::mem::size_of::<InstanceRaw>());
+    }
+
+    // Three changed instances of one model make for a single upload.
+    #[test]
+    fn merge_sync_jobs_once_per_resource() {
+        let jobs = vec![
+            SyncJob::Instance {
+                model_id: 1,
+                instance_id: 7,
+            },
+            SyncJob::Vertex {
+                model_id: 0,
+                mesh_id: 0,
+            },
+            SyncJob::Instances {
+                model_id: 1,
+                instance_ids: 2..4,
+            },
+            SyncJob::Vertex {
+                model_id: 0,
+                mesh_id: 0,
+            },
+            SyncJob::Instance {
+                model_id: 1,
+                instance_id: 5,
+            },
+        ];
+        assert_eq!(
+            merge_sync_jobs(jobs, 2),
+            vec![
+                SyncJob::Vertex {
+                    model_id: 0,
+                    mesh_id: 0,
+                },
+                SyncJob::Instances {
+                    model_id: 1,
+                    instance_ids: 2..8,
+                },
+            ]
+        );
+    }
+
+    // Changes made between defer_updates and flush are only collected after the flush.
+    #[test]
+    fn deferred_updates_wait_for_flush() {
+        let mut scene = Scene::empty();
+        scene.graph.add_child(SceneNode::default());
+        scene.collect_sync_jobs();
+
+        scene.defer_updates();
+        scene.node_mut(&[0]).unwrap()._translate(1.0, 0.0, 0.0);
+        scene.node_mut(&[0]).unwrap()._set_scale(2.0);
+        scene.collect_sync_jobs();
+        assert_eq!(scene.graph.children[0].world_matrix, Matrix4::identity());
+
+        scene.flush();
+        scene.collect_sync_jobs();
+        assert_eq!(
+            Some(scene.graph.children[0].world_matrix),
+            scene.world_transform(&[0])
+        );
+    }
+
+    // A spinning parent doesn't sync a deferred edit below it, other spinning nodes keep
+    //  syncing.
+    #[test]
+    fn deferred_edit_under_spinning_parent() {
+        let mut scene = Scene::empty();
+        for _ in 0..2 {
+            let mut parent = SceneNode::default();
+            parent.set_angular_velocity(Vector3::unit_y(), 90.0);
+            parent.add_child(SceneNode {
+                position: Vector3::new(1.0, 0.0, 0.0),
+                ..Default::default()
+            });
+            scene.graph.add_child(parent);
+        }
+        scene.update(Duration::from_secs(1));
+        let held = scene.graph.children[0].children[0].world_matrix;
+        let other = scene.graph.children[1].children[0].world_matrix;
+
+        scene.defer_updates();
+        scene.node_mut(&[0, 0]).unwrap()._translate(0.0, 5.0, 0.0);
+        scene.update(Duration::from_secs(1));
+        assert_eq!(scene.graph.children[0].children[0].world_matrix, held);
+        assert_ne!(scene.graph.children[1].children[0].world_matrix, other);
+
+        scene.flush();
+        scene.update(Duration::from_secs(0));
+        assert_eq!(
+            Some(scene.graph.children[0].children[0].world_matrix),
+            scene.world_transform(&[0, 0])
+        );
+    }
+
+    // A node rotated a quarter turn around z swings its child's offset from +x to +y.
+    #[test]
+    fn child_orbits_rotated_parent() {
+        let mut scene = Scene::empty();
+        let mut center = SceneNode {
+            position: Vector3::new(0.0, 0.0, 5.0),
+            rotation: Quaternion::from_axis_angle(Vector3::unit_z(), cgmath::Deg(90.0)),
+            ..Default::default()
+        };
+        center.add_child(SceneNode {
+            position: Vector3::new(2.0, 0.0, 0.0),
+            ..Default::default()
+        });
+        scene.graph.add_child(center);
+        scene.mark_changed(&[0]);
+        scene.collect_sync_jobs();
+
+        let expected = Vector3::new(0.0, 2.0, 5.0);
+        let collected = scene.graph.children[0].children[0].world_matrix;
+        assert!((collected.w.truncate() - expected).magnitude() < 1e-5);
+        let world = scene.world_transform(&[0, 0]).unwrap();
+        assert!((world.w.truncate() - expected).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn cover_range_grows() {
+        let mut range = None;
+        cover_range(&mut range, 5..6);
+        assert_eq!(range, Some(5..6));
+        cover_range(&mut range, 1..3);
+        assert_eq!(range, Some(1..6));
+        cover_range(&mut range, 2..4);
+        assert_eq!(range, Some(1..6));
+    }
+}
@@ -1,12 +1,15 @@
 use cgmath::{
+    InnerSpace,
     Vector3,
     Matrix4,
     Quaternion,
     Rotation3,
+    SquareMatrix,
+    Zero,
 };
 
 
-// Consider all these fields LOCAL ONLY!!!
+// Consider all these fields LOCAL ONLY!!! (Except world_matrix.)
 //  The "world matrix" aka Instance will be calculated when something
 //    changes and needs to be synced to gpu
 #[derive(Clone)]
@@ -17,7 +20,12 @@ pub struct SceneNode {
     pub model_id: Option<usize>,
     pub instance_id: Option<usize>,
     pub children: Vec<SceneNode>,
-    pub changed: bool,
+    // The world matrix as of the last collect_changed. Lets the scene start collecting at
+    //  a changed node without going through all its ancestors again.
+    pub world_matrix: Matrix4<f32>,
+    // The axis the node keeps spinning around, scaled by the degrees per second. Zero
+    //  leaves it be. See set_angular_velocity.
+    pub angular_velocity: Vector3<f32>,
 }
 
 impl SceneNode {
@@ -30,7 +38,8 @@ impl SceneNode {
             model_id: None,
             instance_id: None,
             children: vec![],
-            changed: false,
+            world_matrix: Matrix4::identity(),
+            angular_velocity: Vector3::zero(),
         }
     }
 
@@ -42,7 +51,8 @@ impl SceneNode {
             model_id: Some(model_id),
             instance_id: Some(instance_id),
             children: vec![],
-            changed: true,
+            world_matrix: Matrix4::identity(),
+            angular_velocity: Vector3::zero(),
         }
     }
 
@@ -67,60 +77,78 @@ impl SceneNode {
         other.add_child(self);
     }
 
-    // For now sets the changed flag, because propagating the values is
-    //  handled by SceneNode::collect_changed(). This is probably obsolete but
-    //  current design of fetching sync jobs relies on this flag.
-    //  TODO: come up with a nicer way that doesn't traverse the tree on each change
-    //      --> possible sync job can be inferred by changed status of parent.
-    pub fn update_children(
-        &mut self,
-        //_parent_pos: Vector3<f32>,
-        //_parent_rot: Quaternion<f32>,
-        //_parent_scale: f32,
-    ) {
-        for child in &mut self.children {
-            child.changed = true;
-            child.update_children();
-        }
+    // The node's transform relative to its parent. Scales, then rotates, then translates,
+    //  same as Instance::to_raw. Children are placed in the rotated frame, so to orbit
+    //  something rotate a node at the center and offset its children.
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position) * Matrix4::from(self.rotation) * Matrix4::from_scale(self.scale)
     }
 
-    // Collects all changed node model and instance ids and their new world views as instances.
+    // Recomputes the world matrices of this node and everything below it, and collects their
+    //  model and instance ids along with the new matrices. Everything below a changed node
+    //  moved with it, so the whole subtree is collected. The scene only calls this on nodes
+    //  it knows changed, see Scene::node_mut.
     pub fn collect_changed(&mut self, parent_mat: Matrix4<f32>) -> Vec<(Option<usize>, Option<usize>, Matrix4<f32>)> {
         let mut result = vec![];
-        let mat = Matrix4::from(self.rotation) * Matrix4::from_translation(self.position) * Matrix4::from_scale(self.scale);
-        let accumulated_mat = parent_mat * mat;
+        self.collect_into(parent_mat, &mut result);
+        result
+    }
 
-        if self.changed {
-            result.push((self.model_id, self.instance_id, accumulated_mat));
-            // Don't forget to unset this flag :))
-            self.changed = false;
-        }
+    fn collect_into(&mut self, parent_mat: Matrix4<f32>, result: &mut Vec<(Option<usize>, Option<usize>, Matrix4<f32>)>) {
+        self.world_matrix = parent_mat * self.local_matrix();
+        result.push((self.model_id, self.instance_id, self.world_matrix));
 
         for c in &mut self.children {
-            result.append(&mut c.collect_changed(accumulated_mat));
+            c.collect_into(self.world_matrix, result);
         }
+    }
 
-        result
+    // Replaces the whole local transform at once.
+    pub fn set_local_transform(&mut self, position: Vector3<f32>, rotation: Quaternion<f32>, scale: f32) {
+        self.position = position;
+        self.rotation = rotation;
+        self.scale = scale;
     }
 
     pub fn _translate<T: Into<f32>>(&mut self, x: T, y: T, z: T) {
         self.position.x += x.into();
         self.position.y += y.into();
         self.position.z += z.into();
-        self.changed = true;
-        self.update_children();
     }
 
     pub fn _set_scale<T: Into<f32>>(&mut self, scale: T) {
         self.scale = scale.into();
-        self.changed = true;
-        self.update_children()
     }
 
     pub fn rotate(&mut self, rotation: Quaternion<f32>) {
         self.rotation = self.rotation * rotation;
-        self.changed = true;
-        self.update_children();
+    }
+
+    // Keeps the node rotating around axis (in its own space) on every Scene::update.
+    //  Negative speeds spin the other way, 0 stops it.
+    pub fn set_angular_velocity(&mut self, axis: Vector3<f32>, degrees_per_sec: f32) {
+        self.angular_velocity = if axis.is_zero() {
+            Vector3::zero()
+        } else {
+            axis.normalize() * degrees_per_sec
+        };
+    }
+
+    // Turns the spinning nodes in this subtree by dt seconds worth of their angular
+    //  velocity, and collects their paths (below path) for the scene to sync.
+    pub fn spin(&mut self, dt: f32, path: &mut Vec<usize>, spun: &mut Vec<Vec<usize>>) {
+        let speed = self.angular_velocity.magnitude();
+        if speed > 0.0 {
+            let axis = self.angular_velocity / speed;
+            self.rotate(Quaternion::from_axis_angle(axis, cgmath::Deg(speed * dt)));
+            spun.push(path.clone());
+        }
+
+        for (i, child) in self.children.iter_mut().enumerate() {
+            path.push(i);
+            child.spin(dt, path, spun);
+            path.pop();
+        }
     }
 }
 
@@ -133,7 +161,31 @@ impl Default for SceneNode {
             model_id: None,
             instance_id: None,
             children: vec![],
-            changed: false,
+            world_matrix: Matrix4::identity(),
+            angular_velocity: Vector3::zero(),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A second of spinning turns the node as far at 60 frames per second as at 10.
+    #[test]
+    fn spin_scales_with_dt() {
+        let mut turned = vec![];
+        for &frames in &[60, 10] {
+            let mut node = SceneNode::default();
+            node.set_angular_velocity(Vector3::unit_y(), 90.0);
+            for _ in 0..frames {
+                node.spin(1.0 / frames as f32, &mut vec![], &mut vec![]);
+            }
+            turned.push(node.rotation);
+        }
+
+        let expected = Quaternion::from_axis_angle(Vector3::unit_y(), cgmath::Deg(90.0));
+        for rotation in turned {
+            assert!(rotation.dot(expected).abs() > 1.0 - 1e-5, "{:?}", rotation);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use wgpu_practice::renderer::{Renderer, RendererBuilder};
+
+// A renderer drawing to a texture instead of a window. The tests that need one are
+//  ignored by default, `cargo test -- --ignored` runs them where there's an adapter.
+//  WGPU_BACKEND=gl picks the software OpenGL driver where there's no Vulkan one.
+pub fn headless_renderer(width: u32, height: u32) -> Renderer {
+    build_headless(RendererBuilder::new(), width, height)
+}
+
+// Same as headless_renderer, with the settings of builder.
+pub fn build_headless(builder: RendererBuilder, width: u32, height: u32) -> Renderer {
+    futures::executor::block_on(builder.build_headless(width, height))
+        .unwrap_or_else(|e| panic!("Couldn't start a headless renderer: {}", e))
+}
+
+// The models main.rs loads, straight from the source tree rather than the build's copy.
+pub fn res_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("res")
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use std::time::Duration;
+
+use cgmath::Vector3;
+use wgpu_practice::renderer::deferred::ShadingMode;
+use wgpu_practice::renderer::light::Light;
+use wgpu_practice::renderer::model::Model;
+use wgpu_practice::renderer::particles::ParticleSystem;
+use wgpu_practice::renderer::texture::Texture;
+use wgpu_practice::renderer::{Renderer, RendererBuilder, RendererError};
+use wgpu_practice::scene::{DrawScene, Scene};
+
+// The demo's galaxy of avocados, seen from the default camera. Anything other than the
+//  clear color means the whole path from loading to the output pass works.
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn avocado_draws() {
+    let mut renderer = common::headless_renderer(256, 256);
+    let frame = draw_galaxy(&mut renderer);
+
+    let clear = image::Rgba([0, 0, 0, 255]);
+    let drawn = frame.pixels().filter(|&&pixel| pixel != clear).count();
+    assert!(drawn > 0, "Nothing was drawn");
+}
+
+// A white environment lights the avocados more than the neutral gray one does.
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn environment_lights_the_scene() {
+    let mut renderer = common::headless_renderer(256, 256);
+    let neutral = brightness(&draw_galaxy(&mut renderer));
+
+    let dir = std::env::temp_dir().join("wgpu_practice_white_environment");
+    std::fs::create_dir_all(&dir).unwrap();
+    let face = dir.join("white.png");
+    image::RgbaImage::from_pixel(4, 4, image::Rgba([255; 4]))
+        .save(&face)
+        .unwrap();
+    let faces = [&face, &face, &face, &face, &face, &face];
+    let cubemap = Texture::load_cubemap(&renderer.device, &renderer.queue, faces).unwrap();
+    renderer.set_environment(Some(&cubemap));
+    let lit = brightness(&draw_galaxy(&mut renderer));
+
+    assert!(lit > neutral, "{} isn't brighter than {}", lit, neutral);
+}
+
+fn draw_galaxy(renderer: &mut Renderer) -> image::RgbaImage {
+    let mut scene = Scene::empty();
+    let path = common::res_dir().join("avocado").join("Avocado.glb");
+    scene.add_model(Model::load(renderer, path).unwrap());
+    scene.make_galaxy();
+    scene.update(Duration::from_millis(16));
+
+    renderer.draw_scene(&mut scene).unwrap();
+    renderer.capture_frame().unwrap()
+}
+
+fn brightness(frame: &image::RgbaImage) -> u64 {
+    frame
+        .pixels()
+        .map(|pixel| pixel[0] as u64 + pixel[1] as u64 + pixel[2] as u64)
+        .sum()
+}
+
+// With MSAA every pipeline drawing into the scene has to be multisampled as well, wgpu
+//  panics on the first one that isn't. Forward and deferred, with particles and the debug
+//  bounds on, before and after a resize.
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn msaa_draws() {
+    let builder = RendererBuilder::new().sample_count(4);
+    let mut renderer = common::build_headless(builder, 256, 256);
+    assert_eq!(renderer.sample_count(), 4);
+    renderer.draw_debug_bounds(true);
+
+    let mut scene = Scene::empty();
+    let path = common::res_dir().join("avocado").join("Avocado.glb");
+    scene.add_model(Model::load(&renderer, path).unwrap());
+    scene.make_galaxy();
+    let mut particles = ParticleSystem::new(&renderer, 16);
+    particles.emit_burst(Vector3::new(0.0, 0.0, 0.0), 16, 1.0, [1.0; 4], 0.1, 1.0);
+    scene.add_particle_system(particles);
+    scene.update(Duration::from_millis(16));
+
+    let clear = image::Rgba([0, 0, 0, 255]);
+    for (shading_mode, size) in [(ShadingMode::Forward, 256), (ShadingMode::Deferred, 128)] {
+        renderer.resize(Some(winit::dpi::PhysicalSize::new(size, size)));
+        renderer.set_shading_mode(shading_mode);
+        renderer.draw_scene(&mut scene).unwrap();
+        let frame = renderer.capture_frame().unwrap();
+        assert_eq!(frame.dimensions(), (size, size));
+        let drawn = frame.pixels().filter(|&&pixel| pixel != clear).count();
+        assert!(
+            drawn > 0,
+            "Nothing was drawn with {:?} shading",
+            shading_mode
+        );
+    }
+}
+
+// The avocado's buffers and textures show up in the report, along with the renderer's
+//  targets.
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn memory_report_counts_the_avocado() {
+    let renderer = common::headless_renderer(256, 256);
+    let empty = renderer.gpu_memory_report(&[]);
+    assert_eq!(empty.meshes, 0);
+    assert!(empty.render_targets > 0);
+
+    let path = common::res_dir().join("avocado").join("Avocado.glb");
+    let models = [Model::load(&renderer, path).unwrap()];
+    let report = renderer.gpu_memory_report(&models);
+    assert!(report.instances > 0);
+    assert!(report.meshes > 0);
+    assert!(report.textures > empty.textures);
+    assert_eq!(
+        report.total(),
+        report.instances + report.meshes + report.textures + report.render_targets
+    );
+}
+
+// What's set on the builder is what the renderer starts out with. An empty scene leaves
+//  nothing but the clear color.
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn builder_settings_apply() {
+    let light = Light::point([0.0, 5.0, 0.0], [1.0, 0.5, 0.25], 10.0);
+    let builder = RendererBuilder::new()
+        .power_preference(wgpu::PowerPreference::LowPower)
+        .present_mode(wgpu::PresentMode::Fifo)
+        .clear_color(wgpu::Color::RED)
+        .limits(wgpu::Limits::downlevel_defaults())
+        .light(light);
+    let mut renderer = common::build_headless(builder, 64, 64);
+    assert_eq!(renderer.light().position, light.position);
+    assert_eq!(renderer.light().color, light.color);
+
+    renderer.draw_scene(&mut Scene::empty()).unwrap();
+    let frame = renderer.capture_frame().unwrap();
+    let pixel = frame.get_pixel(0, 0);
+    assert!(pixel[0] > pixel[1] && pixel[0] > pixel[2], "{:?}", pixel);
+}
+
+// No adapter has every feature there is.
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn missing_features_are_reported() {
+    let builder = RendererBuilder::new().features(wgpu::Features::all());
+    match futures::executor::block_on(builder.build_headless(64, 64)) {
+        Err(RendererError::MissingFeatures { missing, .. }) => assert!(!missing.is_empty()),
+        Err(e) => panic!("Expected missing features, got {}", e),
+        Ok(_) => panic!("Expected missing features, the renderer started"),
+    }
+}
+
+// A file that isn't there fails on its own, the models around it still load.
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn load_many_skips_bad_files() {
+    let renderer = common::headless_renderer(64, 64);
+    let avocado = common::res_dir().join("avocado").join("Avocado.glb");
+    let missing = common::res_dir().join("missing.glb");
+    let models = Model::load_many(&renderer, &[&avocado, &missing, &avocado]);
+
+    assert_eq!(models.len(), 3);
+    assert!(models[0].is_ok() && models[2].is_ok());
+    let error = format!("{:#}", models[1].as_ref().err().unwrap());
+    assert!(error.contains("missing.glb"), "{}", error);
+}